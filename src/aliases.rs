@@ -0,0 +1,339 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use bstr::ByteSlice;
+use clap::Parser;
+use rusqlite::Connection;
+
+use crate::stats::Scope;
+use pxh::helpers::{shell_words, word_spans};
+
+// Builtins and keywords that are never on PATH but must not be shadowed.
+const SHELL_RESERVED: &[&str] = &[
+    "alias", "bg", "bind", "break", "builtin", "case", "cd", "command", "continue", "declare",
+    "dirs", "disown", "do", "done", "echo", "elif", "else", "enable", "esac", "eval", "exec",
+    "exit", "export", "fc", "fg", "fi", "for", "getopts", "hash", "help", "history", "if", "in",
+    "jobs", "kill", "let", "local", "popd", "printf", "pushd", "pwd", "read", "readonly", "return",
+    "select", "set", "shift", "source", "test", "then", "time", "trap", "type", "typeset",
+    "ulimit", "umask", "unalias", "unset", "until", "wait", "while",
+];
+
+// Words that end a simple command; a prefix ending in one is not useful.
+const OPERATORS: &[&str] = &["|", "||", "&&", ";", "&", ">", ">>", "<", "2>", "2>&1"];
+
+#[derive(Parser, Debug)]
+#[clap(after_help = "Output is valid for both bash and zsh, e.g.:\n  \
+alias | pxh suggest-aliases --aliases - > ~/.pxh-aliases && source ~/.pxh-aliases")]
+pub struct SuggestAliasesCommand {
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Existing aliases, in `alias` output or rc-file form (- for stdin); commands they already cover and their names are skipped"
+    )]
+    pub aliases: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Only consider commands started at or after TIME (e.g. 30d, 2025-01-31)"
+    )]
+    pub since: Option<String>,
+    #[clap(
+        long,
+        value_name = "HOST",
+        value_delimiter = ',',
+        help = "Only consider commands from these hosts"
+    )]
+    pub host: Vec<String>,
+    #[clap(long, default_value_t = 5, help = "Minimum number of times a command was typed")]
+    pub min_count: i64,
+    #[clap(long, default_value_t = 2, help = "Minimum number of sessions it was typed in")]
+    pub min_sessions: usize,
+    #[clap(long, default_value_t = 8, help = "Minimum length of the text an alias replaces")]
+    pub min_length: usize,
+    #[clap(short, long, default_value_t = 10, help = "Maximum number of suggestions")]
+    pub limit: usize,
+}
+
+// Uses of one candidate expansion, keyed by its whitespace-normalized text.
+// `chars` is the total length of the text the shortcut would replace, before
+// subtracting the shortcut name. `spellings` counts the texts as typed.
+#[derive(Default)]
+struct Tally {
+    count: i64,
+    chars: i64,
+    sessions: HashSet<i64>,
+    fillers: HashSet<String>,
+    spellings: HashMap<String, i64>,
+}
+
+impl Tally {
+    // The text as most often typed: collapsing whitespace inside quotes
+    // would change the command.
+    fn text(&self) -> String {
+        let most = self.spellings.iter().max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)));
+        most.map(|(text, _)| text.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    // `alias name='text'`; further arguments are appended.
+    Alias,
+    // `name() { text; }` where text contains a `"$1"` placeholder.
+    Function,
+}
+
+struct Suggestion {
+    kind: Kind,
+    name: String,
+    // The normalized text, for comparing suggestions; `text` is as typed.
+    key: String,
+    text: String,
+    count: i64,
+    sessions: usize,
+    saved: i64,
+}
+
+impl Suggestion {
+    fn definition(&self) -> String {
+        match self.kind {
+            Kind::Alias => format!("alias {}='{}'", self.name, self.text.replace('\'', "'\\''")),
+            Kind::Function => format!("{}() {{ {} \"${{@:2}}\"; }}", self.name, self.text),
+        }
+    }
+}
+
+impl SuggestAliasesCommand {
+    pub fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let (alias_names, alias_values) = match &self.aliases {
+            Some(path) => parse_aliases(&read_aliases(path)?),
+            None => Default::default(),
+        };
+
+        let scope = Scope::new(self.since.as_deref(), None, &self.host, false)?;
+        let rows: Vec<(Vec<u8>, i64, i64)> = scope.query(
+            &conn,
+            "SELECT full_command, session_id, COUNT(*) FROM command_history
+              WHERE {scope} GROUP BY full_command, session_id",
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )?;
+
+        let mut aliases: HashMap<String, Tally> = HashMap::new();
+        let mut functions: HashMap<String, Tally> = HashMap::new();
+        for (command, session, count) in &rows {
+            // An alias can't hold several command lines.
+            let Ok(command) = command.trim().to_str() else {
+                continue;
+            };
+            if command.contains('\n') {
+                continue;
+            }
+            tally_command(command, *session, *count, &mut aliases, &mut functions);
+        }
+
+        let mut candidates: Vec<_> = aliases
+            .into_iter()
+            .map(|(text, tally)| (Kind::Alias, text, tally))
+            .chain(functions.into_iter().map(|(text, tally)| (Kind::Function, text, tally)))
+            .filter(|(kind, text, tally)| {
+                tally.count >= self.min_count
+                    && tally.sessions.len() >= self.min_sessions
+                    && (*kind == Kind::Alias || tally.fillers.len() >= 2)
+                    && text.len() >= self.min_length
+                    && !alias_values.contains(&normalized(text))
+            })
+            .collect();
+        // Rank by approximate savings (one initial per word) to decide which
+        // overlapping candidate wins, then by the real savings once named.
+        candidates.sort_by_cached_key(|(_, text, tally)| {
+            let words = word_spans(text.as_bytes()).len() as i64;
+            (-(tally.chars - tally.count * words), text.clone())
+        });
+
+        let mut taken: HashSet<String> = alias_names;
+        let mut suggestions: Vec<Suggestion> = Vec::new();
+        for (kind, key, tally) in candidates {
+            if suggestions.len() >= self.limit {
+                break;
+            }
+            if suggestions.iter().any(|s| overlaps(&s.key, &key)) {
+                continue;
+            }
+            let Some(name) = shortcut_name(&key, &taken) else {
+                continue;
+            };
+            let saved = tally.chars - tally.count * name.len() as i64;
+            if saved <= 0 {
+                continue;
+            }
+            taken.insert(name.clone());
+            suggestions.push(Suggestion {
+                kind,
+                name,
+                text: tally.text(),
+                key,
+                count: tally.count,
+                sessions: tally.sessions.len(),
+                saved,
+            });
+        }
+        suggestions.sort_by(|a, b| b.saved.cmp(&a.saved).then_with(|| a.name.cmp(&b.name)));
+
+        let total: i64 = rows.iter().map(|(_, _, count)| count).sum();
+        if suggestions.is_empty() {
+            println!(
+                "# No repeated commands worth a shortcut among {total} commands (try lowering --min-count)"
+            );
+            return Ok(());
+        }
+        println!("# Suggested by pxh from {total} commands; review before sourcing.");
+        for s in &suggestions {
+            let shown = s.text.replace("\"$1\"", "<arg>");
+            println!(
+                "\n# {shown}: typed {} times in {} sessions, saves ~{} keystrokes",
+                s.count, s.sessions, s.saved
+            );
+            println!("{}", s.definition());
+        }
+        Ok(())
+    }
+}
+
+// Count every useful expansion of one command line: each word prefix of two
+// or more words (an alias), and each version with one middle word replaced
+// by an argument (a function).
+fn tally_command(
+    command: &str,
+    session: i64,
+    count: i64,
+    aliases: &mut HashMap<String, Tally>,
+    functions: &mut HashMap<String, Tally>,
+) {
+    let add = |map: &mut HashMap<String, Tally>, text: &str, chars: usize, filler: &str| {
+        let tally = map.entry(normalized(text)).or_default();
+        *tally.spellings.entry(text.to_string()).or_default() += count;
+        tally.count += count;
+        tally.chars += count * chars as i64;
+        tally.sessions.insert(session);
+        if !filler.is_empty() {
+            tally.fillers.insert(filler.to_string());
+        }
+    };
+
+    let spans = word_spans(command.as_bytes());
+    let words: Vec<&str> = spans.iter().map(|s| &command[s.clone()]).collect();
+    if words.len() < 2 {
+        return;
+    }
+    // Prefixes stop at the first word with quoting or substitutions, which an
+    // alias could cut in half; the whole command is always safe.
+    let plain = words.iter().take_while(|w| is_plain(w)).count();
+    for n in 2..words.len().min(plain + 1) {
+        if !OPERATORS.contains(&words[n - 1]) {
+            let text = &command[..spans[n - 1].end];
+            add(aliases, text, text.len(), "");
+        }
+    }
+    add(aliases, command, command.len(), "");
+
+    if plain == words.len() && words.len() >= 3 {
+        for i in 1..words.len() - 1 {
+            if OPERATORS.contains(&words[i]) || words[i].starts_with('-') {
+                continue;
+            }
+            let mut template = words.clone();
+            template[i] = "\"$1\"";
+            let fixed = command.len() - words[i].len() - 1;
+            add(functions, &template.join(" "), fixed, words[i]);
+        }
+    }
+}
+
+fn is_plain(word: &str) -> bool {
+    !word.contains(['\'', '"', '\\', '`', '$', '(', ')', '{', '}', '#', '*', '?', '~', '!'])
+}
+
+fn normalized(text: &str) -> String {
+    text.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Whether two suggestions cover the same commands: one's fixed words (those
+// before a function's argument, or all of an alias) start the other's.
+fn overlaps(a: &str, b: &str) -> bool {
+    let stem = |text: &str| text.split_once(" \"$1\"").map_or(text, |(stem, _)| stem).to_string();
+    let (a, b) = (stem(a), stem(b));
+    word_prefix(&a, &b) || word_prefix(&b, &a)
+}
+
+// Whether `prefix` is `text` or its first few words.
+fn word_prefix(prefix: &str, text: &str) -> bool {
+    text.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+// Initials of the fixed words (`git commit -m` -> `gcm`), lengthened from
+// the last word, then numbered, until the name shadows nothing.
+fn shortcut_name(text: &str, taken: &HashSet<String>) -> Option<String> {
+    let words: Vec<Vec<char>> = text
+        .split_ascii_whitespace()
+        .filter(|w| *w != "\"$1\"")
+        .map(|w| w.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()))
+        .map(Iterator::collect)
+        .filter(|w: &Vec<char>| !w.is_empty())
+        .collect();
+    let initials: String = words.iter().map(|w| w[0]).collect();
+    let last = words.last()?;
+    let mut names: Vec<String> = (1..=last.len().min(4))
+        .map(|n| {
+            format!("{}{}", &initials[..initials.len() - 1], last[..n].iter().collect::<String>())
+        })
+        .collect();
+    names.extend((2..10).map(|n| format!("{initials}{n}")));
+    names.into_iter().find(|name| name.len() >= 2 && is_free(name, taken))
+}
+
+fn is_free(name: &str, taken: &HashSet<String>) -> bool {
+    if taken.contains(name) || SHELL_RESERVED.contains(&name) {
+        return false;
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    !env::split_paths(&path).any(|dir| dir.join(name).exists())
+}
+
+fn read_aliases(path: &PathBuf) -> Result<String, Box<dyn std::error::Error>> {
+    if path.as_os_str() == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        Ok(contents)
+    } else {
+        Ok(fs::read_to_string(path)
+            .map_err(|e| format!("unable to read aliases from {}: {e}", path.display()))?)
+    }
+}
+
+// Names and (whitespace-normalized) expansions from `alias` output, which is
+// `name='value'` in zsh and `alias name='value'` in bash and rc files.
+fn parse_aliases(contents: &str) -> (HashSet<String>, HashSet<String>) {
+    let mut names = HashSet::new();
+    let mut values = HashSet::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("alias ").map_or(line, str::trim_start);
+        let Some((name, value)) = line.split_once('=') else {
+            continue;
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) || name.starts_with('#') {
+            continue;
+        }
+        let value: Vec<String> = shell_words(value.as_bytes())
+            .iter()
+            .map(|w| String::from_utf8_lossy(w).into_owned())
+            .collect();
+        names.insert(name.to_string());
+        values.insert(normalized(&value.join(" ")));
+    }
+    (names, values)
+}
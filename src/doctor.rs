@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 use rusqlite::Connection;
@@ -79,6 +79,7 @@ impl DoctorCommand {
             ("Database", self.check_database(&conn, db_path)),
             ("Shell Integration", self.check_shell_integration(db_path)),
             ("Config", self.check_config()),
+            ("Sync", self.check_sync()),
             ("Path", self.check_path_ambiguity()),
             ("Secrets", self.check_secrets(&conn)),
         ];
@@ -406,6 +407,105 @@ impl DoctorCommand {
         results
     }
 
+    fn check_sync(&self) -> Vec<CheckResult> {
+        let mut results = Vec::new();
+
+        let config = pxh::recall::config::Config::load();
+        let directories = config.sync.directory_paths();
+        let Some(key_file) = config.sync.key_file_path() else {
+            for dir in &directories {
+                results.push(CheckResult::ok(format!(
+                    "Sync files in {} are not encrypted",
+                    dir.display()
+                )));
+            }
+            return results;
+        };
+
+        match pxh::encryption::SyncKey::from_file(&key_file) {
+            Ok(_) => results.push(CheckResult::ok(format!("Sync key: {}", key_file.display()))),
+            Err(e) => {
+                results.push(CheckResult::fail(
+                    format!("Sync key unusable: {}", key_file.display()),
+                    format!("{e}; sync, scan --dir and scrub --dir will fail"),
+                ));
+                return results;
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Ok(meta) = std::fs::metadata(&key_file) {
+                let mode = meta.permissions().mode() & 0o777;
+                if mode & 0o077 != 0 {
+                    results.push(CheckResult::warn(
+                        format!("Sync key permissions {mode:04o} (should be 0600)"),
+                        format!("Run: chmod 600 {}", key_file.display()),
+                    ));
+                }
+            }
+        }
+
+        for dir in &directories {
+            results.extend(Self::check_sync_dir(dir, &key_file));
+        }
+        results
+    }
+
+    // Checks one directory that sync files encrypted with `key_file` go to.
+    fn check_sync_dir(dir: &Path, key_file: &Path) -> Vec<CheckResult> {
+        let mut results = Vec::new();
+        let in_dir = |path: &Path| {
+            let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.into());
+            canonical(path).starts_with(canonical(dir))
+        };
+        if in_dir(key_file) {
+            results.push(CheckResult::fail(
+                format!("Sync key is inside the sync directory {}", dir.display()),
+                "Anyone who can read the folder can decrypt it; move the key elsewhere",
+            ));
+        }
+
+        let plaintext: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                pxh::encryption::is_sync_file(path) && pxh::encryption::is_plaintext_database(path)
+            })
+            .collect();
+        let plaintext_segments: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| pxh::segments::is_segment_dir(path))
+            .filter(|seg_dir| {
+                pxh::segments::read_manifest(seg_dir)
+                    .ok()
+                    .flatten()
+                    .is_some_and(|m| m.segments.iter().any(|s| !s.name.ends_with(".enc")))
+            })
+            .collect();
+        if plaintext.is_empty() && plaintext_segments.is_empty() {
+            results.push(CheckResult::ok(format!("No unencrypted databases in {}", dir.display())));
+        }
+        for path in plaintext {
+            results.push(CheckResult::warn(
+                format!("Unencrypted database in encrypted sync dir: {}", path.display()),
+                "Delete it once its host syncs with the key (this host's copy is replaced on the next sync)",
+            ));
+        }
+        for seg_dir in plaintext_segments {
+            results.push(CheckResult::warn(
+                format!("Unencrypted segments in encrypted sync dir: {}", seg_dir.display()),
+                "Its host replaces them with an encrypted snapshot once it syncs with the key",
+            ));
+        }
+
+        results
+    }
+
     fn check_path_ambiguity(&self) -> Vec<CheckResult> {
         let mut results = Vec::new();
 
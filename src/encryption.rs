@@ -0,0 +1,160 @@
+//! Authenticated encryption of directory-sync files.
+//!
+//! When `[sync] key_file` is configured, `pxh sync DIR` writes
+//! `<host>.db.enc` instead of a plaintext `<host>.db`. The file is the magic
+//! header, a random salt and nonce, then the whole SQLite database sealed with
+//! XChaCha20-Poly1305. The key is derived from the key file's contents with
+//! Argon2id, so the file may hold either a passphrase or random bytes. Readers
+//! decrypt into a private temp file and work on that; nothing plaintext is
+//! ever written to the sync directory.
+
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    Key, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+use tempfile::NamedTempFile;
+
+use crate::recall::config::Config;
+
+/// First bytes of every encrypted sync file; also authenticated with the salt.
+const MAGIC: &[u8; 8] = b"PXHENC1\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+/// First bytes of every SQLite database file.
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// Suffix of encrypted sync files, appended to the usual `.db`.
+pub const ENCRYPTED_SUFFIX: &str = ".db.enc";
+
+/// The secret read from a key file. Each file derives its own key from this
+/// and its salt.
+pub struct SyncKey {
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for SyncKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SyncKey(..)")
+    }
+}
+
+impl SyncKey {
+    /// Read a key file: a passphrase or random key, surrounding whitespace
+    /// ignored.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read(path)
+            .map_err(|e| format!("unable to read sync key file {}: {e}", path.display()))?;
+        let secret = contents.trim_ascii().to_vec();
+        if secret.is_empty() {
+            return Err(format!("sync key file {} is empty", path.display()).into());
+        }
+        Ok(SyncKey { secret })
+    }
+
+    /// The key named by `[sync] key_file`, or `None` when sync files are not
+    /// encrypted.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        config.sync.key_file_path().map(|path| Self::from_file(&path)).transpose()
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<XChaCha20Poly1305, Box<dyn std::error::Error>> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(&self.secret, salt, &mut key)
+            .map_err(|e| format!("unable to derive sync key: {e}"))?;
+        Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+/// Whether `path` is a file sync reads and writes: `*.db` or `*.db.enc`.
+pub fn is_sync_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".db") || name.ends_with(ENCRYPTED_SUFFIX))
+}
+
+/// Whether `path` starts with the encrypted sync file header.
+pub fn is_encrypted(path: &Path) -> bool {
+    starts_with(path, MAGIC)
+}
+
+/// Whether `path` is an unencrypted SQLite database.
+pub fn is_plaintext_database(path: &Path) -> bool {
+    starts_with(path, SQLITE_MAGIC)
+}
+
+fn starts_with(path: &Path, magic: &[u8]) -> bool {
+    let mut header = vec![0u8; magic.len()];
+    fs::File::open(path).and_then(|mut f| f.read_exact(&mut header)).is_ok() && header == magic
+}
+
+/// Seal `plaintext` under a fresh salt and nonce.
+pub fn encrypt(key: &SyncKey, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let salt = rand::random::<[u8; SALT_LEN]>();
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+    let mut out = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    let sealed = key
+        .cipher(&salt)?
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &out })
+        .map_err(|_| "unable to encrypt sync file")?;
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Open data sealed by [`encrypt`]; fails if it was made with another key
+/// or has been modified.
+pub fn decrypt(key: &SyncKey, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+        return Err("not an encrypted pxh sync file".into());
+    }
+    let (aad, rest) = data.split_at(MAGIC.len() + SALT_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    key.cipher(&aad[MAGIC.len()..])?
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad })
+        .map_err(|_| "unable to decrypt sync file (wrong key or corrupted file)".into())
+}
+
+/// Encrypt the file at `source` to `dest`, replacing it atomically.
+pub fn encrypt_file(
+    key: &SyncKey,
+    source: &Path,
+    dest: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sealed = encrypt(key, &fs::read(source)?)?;
+    let dir = dest.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp_file = NamedTempFile::new_in(dir)?;
+    temp_file.write_all(&sealed)?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(dest)?;
+    Ok(())
+}
+
+/// Decrypt the file at `path` into a private temp file outside the sync
+/// directory, deleted when dropped.
+pub fn decrypt_to_temp(
+    key: &SyncKey,
+    path: &Path,
+) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+    let plaintext =
+        decrypt(key, &fs::read(path)?).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut temp_file = tempfile::Builder::new().prefix("pxh-sync-").suffix(".db").tempfile()?;
+    temp_file.write_all(&plaintext)?;
+    temp_file.flush()?;
+    Ok(temp_file)
+}
+
+/// The name a host's encrypted sync file has in `dir`.
+pub fn encrypted_path(dir: &Path, hostname: &str) -> PathBuf {
+    dir.join(format!("{hostname}{ENCRYPTED_SUFFIX}"))
+}
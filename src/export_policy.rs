@@ -0,0 +1,314 @@
+//! Outbound privacy rules for sync (`[sync.export]` in the config).
+//!
+//! Inbound secret filtering protects the receiver; these rules protect the
+//! sender. They run over the temporary copy of the database that is about to
+//! leave the machine -- the file sent over SSH or written to the sync
+//! directory -- deleting withheld rows and redacting the rest, then vacuuming
+//! so nothing withheld survives in free pages. The live database is never
+//! touched.
+
+use std::{
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use regex::bytes::RegexSet;
+use rusqlite::Connection;
+
+use crate::recall::config::{Config, SyncExportConfig, expand_home};
+
+/// What redacted usernames and directories are replaced with. Merges skip
+/// rows carrying our own machine id and this value: they are redacted echoes
+/// of rows we still hold in full.
+pub const REDACTED: &str = "[redacted]";
+
+/// A compiled `[sync.export]` section.
+#[derive(Debug, Default)]
+pub struct ExportPolicy {
+    max_age_days: Option<u32>,
+    exclude_hosts: Option<RegexSet>,
+    exclude_directories: Vec<PathBuf>,
+    exclude_commands: Option<RegexSet>,
+    redact_usernames: bool,
+    redact_directories: Vec<PathBuf>,
+}
+
+/// Rows a policy kept from leaving, per rule, and rows it redacted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ExportReport {
+    /// `(rule, rows)` for every exclusion rule in force, in the order they
+    /// are checked; a row is counted under the first rule that withholds it.
+    pub withheld: Vec<(&'static str, usize)>,
+    pub redacted: usize,
+}
+
+impl ExportReport {
+    pub fn total_withheld(&self) -> usize {
+        self.withheld.iter().map(|(_, n)| n).sum()
+    }
+}
+
+fn compile(patterns: &[String], key: &str) -> Result<Option<RegexSet>, Box<dyn std::error::Error>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    RegexSet::new(patterns)
+        .map(Some)
+        .map_err(|e| format!("invalid [sync.export] {key} pattern: {e}").into())
+}
+
+fn under_any(dir: Option<&[u8]>, prefixes: &[PathBuf]) -> bool {
+    dir.is_some_and(|dir| {
+        let path = Path::new(OsStr::from_bytes(dir));
+        prefixes.iter().any(|prefix| path.starts_with(prefix))
+    })
+}
+
+impl ExportPolicy {
+    pub fn new(config: &SyncExportConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let paths = |dirs: &[String]| dirs.iter().map(|d| expand_home(d)).collect();
+        Ok(ExportPolicy {
+            max_age_days: config.max_age_days,
+            exclude_hosts: compile(&config.exclude_hosts, "exclude_hosts")?,
+            exclude_directories: paths(&config.exclude_directories),
+            exclude_commands: compile(&config.exclude_commands, "exclude_commands")?,
+            redact_usernames: config.redact_usernames,
+            redact_directories: paths(&config.redact_directories),
+        })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(&config.sync.export)
+    }
+
+    /// Whether the policy lets everything through unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.max_age_days.is_none()
+            && self.exclude_hosts.is_none()
+            && self.exclude_directories.is_empty()
+            && self.exclude_commands.is_none()
+            && !self.redact_usernames
+            && self.redact_directories.is_empty()
+    }
+
+    fn rules(&self) -> Vec<&'static str> {
+        let mut rules = Vec::new();
+        if self.max_age_days.is_some() {
+            rules.push("max_age_days");
+        }
+        if self.exclude_hosts.is_some() {
+            rules.push("exclude_hosts");
+        }
+        if !self.exclude_directories.is_empty() {
+            rules.push("exclude_directories");
+        }
+        if self.exclude_commands.is_some() {
+            rules.push("exclude_commands");
+        }
+        rules
+    }
+
+    /// The first rule that withholds a row, if any. Rows without a start
+    /// time count as too old, as with `--since`.
+    fn withholding_rule(
+        &self,
+        now: i64,
+        command: &[u8],
+        hostname: Option<&[u8]>,
+        directory: Option<&[u8]>,
+        start: Option<i64>,
+    ) -> Option<&'static str> {
+        if let Some(days) = self.max_age_days
+            && start.is_none_or(|start| start <= now - days as i64 * 86400)
+        {
+            return Some("max_age_days");
+        }
+        if let Some(hosts) = &self.exclude_hosts
+            && hostname.is_some_and(|h| hosts.is_match(h))
+        {
+            return Some("exclude_hosts");
+        }
+        if under_any(directory, &self.exclude_directories) {
+            return Some("exclude_directories");
+        }
+        if self.exclude_commands.as_ref().is_some_and(|c| c.is_match(command)) {
+            return Some("exclude_commands");
+        }
+        None
+    }
+
+    /// Apply the policy to `conn`, a copy about to be exported. With
+    /// `dry_run` nothing is changed and the report says what would be.
+    pub fn apply(
+        &self,
+        conn: &mut Connection,
+        now: i64,
+        dry_run: bool,
+    ) -> Result<ExportReport, Box<dyn std::error::Error>> {
+        let mut report = ExportReport {
+            withheld: self.rules().into_iter().map(|rule| (rule, 0)).collect(),
+            redacted: 0,
+        };
+        if self.is_empty() {
+            return Ok(report);
+        }
+
+        let mut withheld_ids = Vec::new();
+        let mut redacted_dir_ids = Vec::new();
+        let mut redacted_rows = 0;
+        {
+            let mut stmt = conn.prepare(
+                "SELECT id, full_command, hostname, working_directory, start_unix_timestamp, username
+                   FROM command_history",
+            )?;
+            let mut rows = stmt.query(())?;
+            while let Some(row) = rows.next()? {
+                let id: i64 = row.get(0)?;
+                let command = row.get_ref(1)?.as_bytes_or_null()?.unwrap_or_default();
+                let hostname = row.get_ref(2)?.as_bytes_or_null()?;
+                let directory = row.get_ref(3)?.as_bytes_or_null()?;
+                let start: Option<i64> = row.get(4)?;
+                if let Some(rule) = self.withholding_rule(now, command, hostname, directory, start)
+                {
+                    if let Some(entry) = report.withheld.iter_mut().find(|(r, _)| *r == rule) {
+                        entry.1 += 1;
+                    }
+                    withheld_ids.push(id);
+                    continue;
+                }
+                let redact_dir = under_any(directory, &self.redact_directories);
+                let redact_user =
+                    self.redact_usernames && row.get_ref(5)?.as_bytes_or_null()?.is_some();
+                if redact_dir {
+                    redacted_dir_ids.push(id);
+                }
+                if redact_dir || redact_user {
+                    redacted_rows += 1;
+                }
+            }
+        }
+        report.redacted = redacted_rows;
+        if dry_run {
+            return Ok(report);
+        }
+
+        let tx = conn.transaction()?;
+        {
+            let mut delete = tx.prepare("DELETE FROM command_history WHERE id = ?")?;
+            for id in &withheld_ids {
+                delete.execute([id])?;
+            }
+            // OR REPLACE: rows that differed only in what we redact become
+            // duplicates, and one copy is enough.
+            let mut redact_dir = tx.prepare(
+                "UPDATE OR REPLACE command_history SET working_directory = CAST(? AS blob) WHERE id = ?",
+            )?;
+            for id in &redacted_dir_ids {
+                redact_dir.execute((REDACTED, id))?;
+            }
+            if self.redact_usernames {
+                tx.execute(
+                    "UPDATE OR REPLACE command_history SET username = CAST(? AS blob)
+                      WHERE username IS NOT NULL",
+                    [REDACTED],
+                )?;
+            }
+        }
+        tx.commit()?;
+        conn.execute("VACUUM", ())?;
+        Ok(report)
+    }
+
+    /// Apply the policy to the database file at `path`.
+    pub fn apply_to_file(
+        &self,
+        path: &Path,
+        now: i64,
+    ) -> Result<ExportReport, Box<dyn std::error::Error>> {
+        if self.is_empty() {
+            return Ok(ExportReport::default());
+        }
+        let mut conn = Connection::open(path)?;
+        self.apply(&mut conn, now, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::initialize_base_schema(&conn).unwrap();
+        crate::run_schema_migrations(&conn).unwrap();
+        for (cmd, host, dir, user, start) in [
+            ("ls", "laptop", "/home/me/work/acme", "me", 150_000),
+            ("vault read secret", "laptop", "/home/me", "me", 150_000),
+            ("make", "corp-build", "/src", "me", 150_000),
+            ("git pull", "laptop", "/home/me/personal", "me", 150_000),
+            ("git pull", "laptop", "/home/me/personal", "other", 150_000),
+            ("echo old", "laptop", "/home/me", "me", 10),
+        ] {
+            conn.execute(
+                "INSERT INTO command_history (session_id, full_command, shellname, hostname,
+                                              working_directory, username, start_unix_timestamp)
+                 VALUES (1, CAST(? AS blob), 'zsh', CAST(? AS blob), CAST(? AS blob),
+                         CAST(? AS blob), ?)",
+                rusqlite::params![cmd, host, dir, user, start],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn policy() -> ExportPolicy {
+        ExportPolicy::new(&SyncExportConfig {
+            exclude_directories: vec!["/home/me/work".into()],
+            exclude_hosts: vec!["^corp-".into()],
+            exclude_commands: vec!["^vault ".into()],
+            max_age_days: Some(1),
+            redact_usernames: true,
+            redact_directories: vec!["/home/me/personal".into()],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_export_policy_withholds_and_redacts() {
+        let mut conn = history_db();
+        let now = 200_000;
+        let report = policy().apply(&mut conn, now, true).unwrap();
+        let expected = ExportReport {
+            withheld: vec![
+                ("max_age_days", 1),
+                ("exclude_hosts", 1),
+                ("exclude_directories", 1),
+                ("exclude_commands", 1),
+            ],
+            redacted: 2,
+        };
+        assert_eq!(report, expected);
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM command_history", (), |r| r.get(0)).unwrap()
+        };
+        assert_eq!(count(&conn), 6, "dry run changes nothing");
+
+        assert_eq!(policy().apply(&mut conn, now, false).unwrap(), expected);
+        // The two `git pull` rows differed only by username.
+        assert_eq!(count(&conn), 1);
+        let (user, dir): (Vec<u8>, Vec<u8>) = conn
+            .query_row("SELECT username, working_directory FROM command_history", (), |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((user.as_slice(), dir.as_slice()), (REDACTED.as_bytes(), REDACTED.as_bytes()));
+    }
+
+    #[test]
+    fn test_export_policy_rejects_bad_regex() {
+        let config = SyncExportConfig { exclude_hosts: vec!["(".into()], ..Default::default() };
+        let err = ExportPolicy::new(&config).unwrap_err().to_string();
+        assert!(err.contains("exclude_hosts"), "{err}");
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    os::unix::ffi::OsStrExt,
+};
+
+use bstr::{BString, ByteSlice};
+use clap::Parser;
+use rusqlite::Connection;
+
+use pxh::helpers::{edit_distance, word_spans};
+
+// A correction must start within this many seconds of the failure ending.
+const FIX_WINDOW_SECS: i64 = 60;
+// Larger edits are a different command, not a typo fix.
+const MAX_EDITS: usize = 2;
+// Settings key holding the last command_history id already scanned for pairs.
+const SCANNED_KEY: &str = "corrections_scanned_id";
+
+#[derive(Parser, Debug)]
+#[clap(after_help = "Corrections are learned from history: a failed command followed shortly \
+in the same session by a successful one that differs by a typo or two (`gti status` then \
+`git status`). A new typo in a learned word is corrected too (`gti log` -> `git log`).")]
+pub struct FixCommand {
+    #[clap(long, help = "List learned corrections, most frequent first")]
+    pub list: bool,
+    #[clap(help = "Command to correct; defaults to the last failed command in this shell session")]
+    pub command: Vec<OsString>,
+}
+
+impl FixCommand {
+    pub fn go(&self, mut conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        learn_corrections(&mut conn)?;
+        let pairs = load_corrections(&conn)?;
+
+        if self.list {
+            for pair in &pairs {
+                println!("{:>5}  {}  ->  {}", pair.count, pair.typo.as_bstr(), pair.fix.as_bstr());
+            }
+            return Ok(());
+        }
+
+        let failed: Vec<u8> = if self.command.is_empty() {
+            let session_id: i64 = env::var("PXH_SESSION_ID")
+                .map_err(|_| "PXH_SESSION_ID not set; are you in a pxh-enabled shell?")?
+                .parse()?;
+            conn.query_row(
+                "SELECT full_command FROM command_history
+                  WHERE session_id = ? AND exit_status != 0 ORDER BY id DESC LIMIT 1",
+                [session_id],
+                |r| r.get(0),
+            )
+            .map_err(|_| "no failed command in this session")?
+        } else {
+            self.command.join(OsStr::new(" ")).as_bytes().to_vec()
+        };
+
+        let fix = suggest(&failed, &pairs)
+            .ok_or_else(|| format!("no learned correction for `{}`", failed.as_bstr()))?;
+        println!("{}", fix.as_bstr());
+        Ok(())
+    }
+}
+
+/// Record correction pairs from commands added since the last scan;
+/// returns how many were found.
+pub(crate) fn learn_corrections(
+    conn: &mut Connection,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    let scanned: i64 = pxh::get_setting(&tx, SCANNED_KEY)?
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+    // Stop short of commands still running (unsealed and recent) so they are
+    // considered once they finish; older unsealed rows are from dead shells.
+    let through: i64 = tx.query_row(
+        "SELECT COALESCE(
+             (SELECT MIN(id) - 1 FROM command_history
+               WHERE id > ?1 AND exit_status IS NULL AND start_unix_timestamp > ?2),
+             (SELECT MAX(id) FROM command_history), 0)",
+        (scanned, chrono::Local::now().timestamp() - 86400),
+        |r| r.get(0),
+    )?;
+
+    let candidates: Vec<(i64, Vec<u8>, i64, Vec<u8>)> = tx
+        .prepare(
+            "SELECT prev.id, prev.full_command, cur.id, cur.full_command
+               FROM command_history cur
+               JOIN command_history prev ON prev.id = (
+                    SELECT MAX(p.id) FROM command_history p
+                     WHERE p.session_id = cur.session_id AND p.id < cur.id)
+              WHERE cur.id > ?1 AND cur.id <= ?2
+                AND cur.exit_status = 0 AND prev.exit_status != 0
+                AND cur.start_unix_timestamp
+                    - COALESCE(prev.end_unix_timestamp, prev.start_unix_timestamp)
+                    BETWEEN 0 AND ?3",
+        )?
+        .query_map((scanned, through, FIX_WINDOW_SECS), |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut learned = 0;
+    for (typo_id, typo, fix_id, fix) in candidates {
+        if is_correction(&typo, &fix) {
+            learned += tx.execute(
+                "INSERT OR IGNORE INTO command_corrections (fix_id, typo_id) VALUES (?, ?)",
+                (fix_id, typo_id),
+            )?;
+        }
+    }
+    pxh::set_setting(&tx, SCANNED_KEY, &BString::from(through.max(scanned).to_string()))?;
+    tx.commit()?;
+    Ok(learned)
+}
+
+// A small edit relative to the command: `sl` -> `ls` qualifies, `ls` -> `cd`
+// does not.
+fn is_correction(typo: &[u8], fix: &[u8]) -> bool {
+    let (typo, fix) = (typo.trim(), fix.trim());
+    let distance = edit_distance(typo, fix);
+    distance > 0 && distance <= MAX_EDITS && distance * 2 <= typo.len().max(fix.len())
+}
+
+struct Correction {
+    typo: Vec<u8>,
+    fix: Vec<u8>,
+    count: i64,
+}
+
+// Distinct pairs, most frequent (then most recent) first. Pairs whose
+// commands have been scrubbed drop out through the joins.
+fn load_corrections(conn: &Connection) -> Result<Vec<Correction>, Box<dyn std::error::Error>> {
+    let pairs = conn
+        .prepare(
+            "SELECT t.full_command, f.full_command, COUNT(*)
+               FROM command_corrections c
+               JOIN command_history t ON t.id = c.typo_id
+               JOIN command_history f ON f.id = c.fix_id
+              GROUP BY t.full_command, f.full_command
+              ORDER BY COUNT(*) DESC, MAX(c.fix_id) DESC",
+        )?
+        .query_map([], |r| Ok(Correction { typo: r.get(0)?, fix: r.get(1)?, count: r.get(2)? }))?
+        .collect::<Result<_, _>>()?;
+    Ok(pairs)
+}
+
+// The learned fix for exactly this command, or failing that, the command with
+// one word replaced where a learned pair corrected that word in the same place.
+fn suggest(failed: &[u8], pairs: &[Correction]) -> Option<Vec<u8>> {
+    let failed = failed.trim();
+    if let Some(pair) = pairs.iter().find(|p| p.typo.trim() == failed) {
+        return Some(pair.fix.trim().to_vec());
+    }
+
+    let spans = word_spans(failed);
+    pairs.iter().find_map(|pair| {
+        let (index, typo_word, fix_word) = changed_word(&pair.typo, &pair.fix)?;
+        let span = spans.get(index)?.clone();
+        (&failed[span.clone()] == typo_word)
+            .then(|| [&failed[..span.start], fix_word, &failed[span.end..]].concat())
+    })
+}
+
+// The position and contents of the only word that differs between two
+// commands with the same number of words.
+fn changed_word<'a>(typo: &'a [u8], fix: &'a [u8]) -> Option<(usize, &'a [u8], &'a [u8])> {
+    let (typo_spans, fix_spans) = (word_spans(typo), word_spans(fix));
+    if typo_spans.len() != fix_spans.len() {
+        return None;
+    }
+    let mut changed = typo_spans
+        .into_iter()
+        .zip(fix_spans)
+        .enumerate()
+        .filter(|(_, (t, f))| typo[t.clone()] != fix[f.clone()]);
+    let (index, (t, f)) = changed.next()?;
+    changed.next().is_none().then(|| (index, &typo[t], &fix[f]))
+}
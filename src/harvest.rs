@@ -0,0 +1,359 @@
+//! Harvesting shell history from hosts without pxh (`pxh sync --histfile-only`).
+//!
+//! A small POSIX shell script runs over the ssh command (or `--transport`)
+//! and prints the remote user and hostname, then each readable history file
+//! in [`HISTFILES`] from the byte we asked for: a `pxh-histfile <shell>
+//! <start> <length>` line followed by exactly that many bytes. The caller
+//! parses the new bytes like `pxh import` would and inserts them.
+//!
+//! Per remote and shell we keep a [`HarvestState`]: how far we have read,
+//! and the last few complete lines before that point (the anchor). Each run
+//! asks for the file starting at the anchor, so we can tell an appended
+//! file (the anchor is still there) from one the shell rewrote -- bash
+//! truncates its history to `HISTFILESIZE` lines on exit. A rewritten file
+//! is fetched whole and read from after the anchor's last occurrence, or
+//! from the start if it is gone -- then entries without a timestamp that
+//! were imported before are dropped (see [`drop_reimported`]), since the
+//! unique index can't tell them apart. Only complete entries are consumed: a
+//! trailing partial line, bash `#<timestamp>` line or zsh continuation is
+//! left for the next run.
+
+use std::{collections::HashMap, ops::Range};
+
+use blake2::{Blake2b, Digest, digest::consts::U8};
+use bstr::{BString, ByteSlice};
+use itertools::Itertools;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::Invocation;
+
+/// History files harvested, as (shell, path relative to `$HOME`).
+pub const HISTFILES: &[(&str, &str)] = &[("bash", ".bash_history"), ("zsh", ".zsh_history")];
+/// Lines kept as the anchor to recognize where we stopped reading: enough
+/// to be distinctive, few enough to survive the file losing its oldest
+/// lines.
+const ANCHOR_LINES: usize = 4;
+/// Upper bound on the anchor's size in bytes, past its last line.
+const ANCHOR_LEN: usize = 1024;
+const HEADER: &str = "pxh-harvest";
+const FILE_HEADER: &str = "pxh-histfile";
+
+/// How far one remote history file has been harvested.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HarvestState {
+    /// Bytes of the file consumed so far.
+    pub offset: u64,
+    /// The complete lines just before `offset`.
+    pub anchor: BString,
+}
+
+impl HarvestState {
+    /// Where the next fetch starts: at the anchor, so it can be checked.
+    pub fn fetch_from(&self) -> u64 {
+        self.offset.saturating_sub(self.anchor.len() as u64)
+    }
+}
+
+fn state_key(host: &str, shell: &str) -> String {
+    format!("harvest_{host}_{shell}")
+}
+
+/// The harvest state for `shell`'s history on `host`; the default (read
+/// everything) if there is none yet.
+pub fn load_state(conn: &Connection, host: &str, shell: &str) -> HarvestState {
+    crate::get_setting(conn, &state_key(host, shell))
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_slice(&value).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_state(
+    conn: &Connection,
+    host: &str,
+    shell: &str,
+    state: &HarvestState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    crate::set_setting(conn, &state_key(host, shell), &BString::from(serde_json::to_vec(state)?))
+}
+
+/// The remote command printing each of [`HISTFILES`] from the matching
+/// entry of `starts` (from the beginning if the file is now shorter).
+pub fn remote_script(starts: &[u64]) -> String {
+    let specs: Vec<String> = HISTFILES
+        .iter()
+        .zip(starts)
+        .map(|((shell, path), start)| format!("\"{shell} {path} {start}\""))
+        .collect();
+    format!(
+        "sh -c 'printf \"{HEADER} %s %s\\n\" \"$(id -un)\" \"$(uname -n)\"; \
+         for spec in {}; do set -- $spec; f=\"$HOME/$2\"; [ -r \"$f\" ] || continue; \
+         size=$(($(wc -c < \"$f\"))); start=$3; [ \"$size\" -lt \"$start\" ] && start=0; \
+         printf \"{FILE_HEADER} %s %s %s\\n\" \"$1\" \"$start\" \"$((size - start))\"; \
+         tail -c +$((start + 1)) \"$f\" | head -c $((size - start)); done'",
+        specs.join(" ")
+    )
+}
+
+/// One history file as the remote sent it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteHistfile {
+    pub shell: String,
+    /// Byte of the file `data` starts at.
+    pub start: u64,
+    pub data: Vec<u8>,
+}
+
+/// What [`remote_script`] printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Harvest {
+    pub username: Option<BString>,
+    pub hostname: Option<BString>,
+    pub files: Vec<RemoteHistfile>,
+}
+
+/// Parse [`remote_script`] output, skipping anything a noisy login shell
+/// prints before it.
+pub fn parse_output(output: &[u8]) -> Result<Harvest, String> {
+    let mut lines = output.split_inclusive(|&b| b == b'\n');
+    let mut consumed = 0;
+    let header = loop {
+        let line = lines.next().ok_or("remote shell printed no history listing")?;
+        consumed += line.len();
+        if let Some(rest) = line.strip_prefix(HEADER.as_bytes()) {
+            break rest;
+        }
+    };
+    let mut fields = header.fields().map(BString::from);
+    let (username, hostname) = (fields.next(), fields.next());
+
+    let mut rest = &output[consumed..];
+    let mut files = vec![];
+    while !rest.is_empty() {
+        let end = rest.find_byte(b'\n').ok_or("history listing cut off")?;
+        let line = rest[..end].to_str_lossy();
+        let parsed = line.strip_prefix(FILE_HEADER).and_then(|fields| {
+            let (shell, start, len) = fields.split_whitespace().collect_tuple()?;
+            Some((shell.to_string(), start.parse::<u64>().ok()?, len.parse::<usize>().ok()?))
+        });
+        let (shell, start, len) = parsed.ok_or_else(|| format!("unexpected line {line:?}"))?;
+        rest = &rest[end + 1..];
+        if rest.len() < len {
+            return Err(format!("{shell} history cut off (the file changed while being read?)"));
+        }
+        files.push(RemoteHistfile { shell, start, data: rest[..len].to_vec() });
+        rest = &rest[len..];
+    }
+    Ok(Harvest { username, hostname, files })
+}
+
+fn is_timestamp_line(line: &[u8]) -> bool {
+    line.len() > 1 && line[0] == b'#' && line[1..].iter().all(u8::is_ascii_digit)
+}
+
+/// How much of `buf` holds complete entries: up to the last newline, minus
+/// trailing lines that belong to an entry not fully written yet.
+fn complete_len(buf: &[u8], shell: &str) -> usize {
+    let mut end = buf.rfind_byte(b'\n').map_or(0, |i| i + 1);
+    while end > 0 {
+        let start = buf[..end - 1].rfind_byte(b'\n').map_or(0, |i| i + 1);
+        let line = &buf[start..end - 1];
+        let unfinished = match shell {
+            "bash" => is_timestamp_line(line),
+            _ => line.last() == Some(&b'\\'),
+        };
+        if !unfinished {
+            break;
+        }
+        end = start;
+    }
+    end
+}
+
+/// The part of `file.data` not harvested yet and the state to save once it
+/// is, or None if the file was rewritten and has to be fetched whole.
+pub fn take_new(
+    state: &HarvestState,
+    file: &RemoteHistfile,
+) -> Option<(Range<usize>, HarvestState)> {
+    let data = &file.data;
+    let begin = if file.start == state.fetch_from() && data.starts_with(&state.anchor) {
+        state.anchor.len()
+    } else if file.start == 0 && !state.anchor.is_empty() {
+        data.rfind(&state.anchor).map_or(0, |i| i + state.anchor.len())
+    } else if file.start == 0 {
+        0
+    } else {
+        return None;
+    };
+    let end = begin + complete_len(&data[begin..], &file.shell);
+
+    let mut anchor_start = end;
+    for _ in 0..ANCHOR_LINES {
+        if anchor_start == 0 {
+            break;
+        }
+        let line_start = data[..anchor_start - 1].rfind_byte(b'\n').map_or(0, |i| i + 1);
+        // However long, the last line stays: an empty anchor past the
+        // start of the file would match anywhere.
+        if anchor_start < end && end - line_start > ANCHOR_LEN {
+            break;
+        }
+        anchor_start = line_start;
+    }
+    let next = HarvestState {
+        offset: file.start + end as u64,
+        anchor: BString::from(&data[anchor_start..end]),
+    };
+    Some((begin..end, next))
+}
+
+/// Drop the entries without a timestamp of a history file read again from
+/// its start that `conn` already has: rows of `session_id` from `hostname`
+/// and `username` with the same command and no timestamp. Each such row
+/// accounts for one occurrence, so repeats added since are kept.
+pub fn drop_reimported(
+    conn: &Connection,
+    hostname: &BString,
+    username: &BString,
+    session_id: i64,
+    invocations: Vec<Invocation>,
+) -> rusqlite::Result<Vec<Invocation>> {
+    let mut imported: HashMap<BString, usize> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT full_command FROM command_history
+         WHERE session_id = ? AND hostname = ? AND username = ? AND start_unix_timestamp IS NULL",
+    )?;
+    let commands = stmt.query_map((session_id, hostname.as_bytes(), username.as_bytes()), |r| {
+        r.get::<_, Vec<u8>>(0)
+    })?;
+    for command in commands {
+        *imported.entry(BString::from(command?)).or_default() += 1;
+    }
+    Ok(invocations
+        .into_iter()
+        .filter(|invocation| {
+            if invocation.start_unix_timestamp.is_some() {
+                return true;
+            }
+            match imported.get_mut(&invocation.command) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            }
+        })
+        .collect())
+}
+
+/// A stable session id for `shell`'s history on `host`.
+pub fn session_id(host: &str, shell: &str) -> i64 {
+    let hash = Blake2b::<U8>::digest(format!("{host}:{shell}"));
+    (u64::from_le_bytes(hash.into()) >> 1) as i64
+}
+
+/// Parse harvested `shell` history the way `pxh import` would.
+pub fn parse_invocations(
+    shell: &str,
+    data: &[u8],
+    source: &str,
+    hostname: &BString,
+    username: &BString,
+    session_id: i64,
+) -> Vec<Invocation> {
+    match shell {
+        "zsh" => crate::parse_zsh_history(data, source, hostname, username, session_id),
+        _ => crate::parse_bash_history(data, hostname, username, session_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(shell: &str, start: u64, data: &[u8]) -> RemoteHistfile {
+        RemoteHistfile { shell: shell.into(), start, data: data.to_vec() }
+    }
+
+    #[test]
+    fn test_parse_output_skips_login_noise() {
+        let output = b"Welcome!\npxh-harvest alice web1\n\
+                       pxh-histfile bash 0 7\nls\npwd\npxh-histfile zsh 10 0\n";
+        let harvest = parse_output(output).unwrap();
+        assert_eq!(harvest.username, Some(BString::from("alice")));
+        assert_eq!(harvest.hostname, Some(BString::from("web1")));
+        assert_eq!(harvest.files, vec![file("bash", 0, b"ls\npwd\n"), file("zsh", 10, b"")]);
+
+        assert!(parse_output(b"pxh-harvest a b\npxh-histfile bash 0 9\nls\n").is_err());
+        assert!(parse_output(b"no header\n").is_err());
+    }
+
+    #[test]
+    fn test_take_new_follows_appends_and_rewrites() {
+        // First run: everything up to the unfinished timestamp line.
+        let first = file("bash", 0, b"#100\nls\n#200\npwd\n#300\n");
+        let (range, state) = take_new(&HarvestState::default(), &first).unwrap();
+        assert_eq!(&first.data[range], b"#100\nls\n#200\npwd\n");
+        assert_eq!(state.offset, 17);
+        assert_eq!(state.anchor, "#100\nls\n#200\npwd\n");
+
+        // Appended: the fetch starts at the anchor, which still matches.
+        let appended = file("bash", state.fetch_from(), b"#100\nls\n#200\npwd\n#300\nid\n");
+        let (range, next) = take_new(&state, &appended).unwrap();
+        assert_eq!(&appended.data[range], b"#300\nid\n");
+        assert_eq!(next.offset, 25);
+        assert_eq!(next.anchor, "#200\npwd\n#300\nid\n");
+
+        // Rewritten without the oldest entry: the anchor moved, so the
+        // partial fetch is refused; the whole file resumes after it.
+        let whole = b"#200\npwd\n#300\nid\n#400\nw\n";
+        let rewritten = file("bash", next.fetch_from(), &whole[8..]);
+        assert!(take_new(&next, &rewritten).is_none());
+        let (range, after) = take_new(&next, &file("bash", 0, whole)).unwrap();
+        assert_eq!(&whole[range], b"#400\nw\n");
+        assert_eq!(after.offset, whole.len() as u64);
+
+        // A zsh continuation isn't consumed until its last line arrives.
+        let zsh = file("zsh", 0, b": 1:0;ls\n: 2:0;echo a\\\n");
+        let (range, _) = take_new(&HarvestState::default(), &zsh).unwrap();
+        assert_eq!(&zsh.data[range], b": 1:0;ls\n");
+    }
+
+    #[test]
+    fn test_take_new_anchors_on_a_long_last_line() {
+        let mut data = b"ls\necho ".to_vec();
+        data.extend(std::iter::repeat_n(b'x', ANCHOR_LEN));
+        data.push(b'\n');
+        let (_, state) = take_new(&HarvestState::default(), &file("bash", 0, &data)).unwrap();
+        assert_eq!(state.anchor, data[3..]);
+
+        // A rewritten file without that line is read from the start.
+        let (range, _) = take_new(&state, &file("bash", 0, b"pwd\n")).unwrap();
+        assert_eq!(range, 0..4);
+    }
+
+    #[test]
+    fn test_drop_reimported_counts_occurrences() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::initialize_base_schema(&conn).unwrap();
+        crate::run_schema_migrations(&conn).unwrap();
+        let (host, user) = (BString::from("web1"), BString::from("alice"));
+        let parse = |data: &[u8]| parse_invocations("bash", data, "", &host, &user, 7);
+        let tx = conn.transaction().unwrap();
+        for invocation in parse(b"ls\npwd\nls\n") {
+            invocation.insert(&tx).unwrap();
+        }
+        tx.commit().unwrap();
+
+        // Read again after bash rewrote it: only `id` and the third `ls`
+        // are new.
+        let kept =
+            drop_reimported(&conn, &host, &user, 7, parse(b"ls\npwd\nls\nid\nls\n")).unwrap();
+        let commands: Vec<_> = kept.iter().map(|i| i.command.to_string()).collect();
+        assert_eq!(commands, ["id", "ls"]);
+        // Other sessions' rows don't count.
+        assert_eq!(drop_reimported(&conn, &host, &user, 8, parse(b"ls\n")).unwrap().len(), 1);
+    }
+}
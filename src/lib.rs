@@ -21,15 +21,20 @@ use itertools::Itertools;
 use regex::bytes::Regex;
 use rusqlite::{
     Connection, Error, ErrorCode, Result, Row, Transaction, TransactionBehavior,
-    functions::FunctionFlags,
+    functions::FunctionFlags, types::ValueRef,
 };
 use serde::{Deserialize, Serialize};
 
 type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
+pub mod encryption;
+pub mod export_policy;
+pub mod harvest;
 pub mod recall;
 pub mod secrets_patterns;
+pub mod segments;
 pub mod sync;
+pub mod sync_stream;
 
 pub fn get_setting(
     conn: &Connection,
@@ -245,6 +250,11 @@ pub fn initialize_full_schema(conn: &Connection) -> Result<(), Box<dyn std::erro
          );
          CREATE INDEX memdb.result_timestamp ON show_results(ch_start_unix_timestamp, ch_id);",
     )?;
+    register_regexp(conn)
+}
+
+/// Register the `REGEXP` operator (`x REGEXP pattern`), matching bytes.
+pub fn register_regexp(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
     conn.create_scalar_function("regexp", 2, FunctionFlags::SQLITE_DETERMINISTIC, move |ctx| {
         assert_eq!(ctx.len(), 2, "called with unexpected number of arguments");
         let regexp: Arc<Regex> = ctx
@@ -258,8 +268,57 @@ pub fn initialize_full_schema(conn: &Connection) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+/// Register the query helpers offered by `pxh sql`:
+/// - `text(x)`: BLOB (or anything) as text, invalid UTF-8 replaced
+/// - `duration(start, end)`: seconds between two unix timestamps
+/// - `fmt_time(ts [, format])`: unix timestamp as local time, strftime-style
+pub fn register_sql_helpers(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let flags = FunctionFlags::SQLITE_DETERMINISTIC | FunctionFlags::SQLITE_UTF8;
+    conn.create_scalar_function("text", 1, flags, |ctx| {
+        Ok(match ctx.get_raw(0) {
+            ValueRef::Null => None,
+            ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+                Some(String::from_utf8_lossy(bytes).into_owned())
+            }
+            ValueRef::Integer(i) => Some(i.to_string()),
+            ValueRef::Real(f) => Some(f.to_string()),
+        })
+    })?;
+    conn.create_scalar_function("duration", 2, flags, |ctx| {
+        let start: Option<i64> = ctx.get(0)?;
+        let end: Option<i64> = ctx.get(1)?;
+        Ok(start.zip(end).map(|(start, end)| end - start))
+    })?;
+    // Local time depends on the environment, so fmt_time is not deterministic.
+    for arity in [1, 2] {
+        conn.create_scalar_function("fmt_time", arity, FunctionFlags::SQLITE_UTF8, |ctx| {
+            let ts: Option<i64> = ctx.get(0)?;
+            let format = if ctx.len() > 1 { ctx.get::<String>(1)? } else { TIME_FORMAT.into() };
+            if chrono::format::StrftimeItems::new(&format).any(|i| i == chrono::format::Item::Error)
+            {
+                return Err(Error::UserFunctionError(
+                    format!("fmt_time: invalid format '{format}'").into(),
+                ));
+            }
+            Ok(ts
+                .and_then(|ts| Local.timestamp_opt(ts, 0).single())
+                .map(|t| t.format(&format).to_string()))
+        })?;
+    }
+    Ok(())
+}
+
+/// Views created by `views.sql`; `maintenance` keeps these.
+pub const STANDARD_VIEWS: &[&str] = &["v_history", "v_sessions"];
+
+/// (Re)create the standard views.
+pub fn create_standard_views(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute_batch(include_str!("views.sql"))?;
+    Ok(())
+}
+
 /// Current schema version -- bump when adding new migrations below.
-pub const CURRENT_SCHEMA_VERSION: i32 = 3;
+pub const CURRENT_SCHEMA_VERSION: i32 = 6;
 
 /// Run versioned schema migrations tracked via PRAGMA user_version.
 pub fn run_schema_migrations(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
@@ -292,6 +351,37 @@ pub fn run_schema_migrations(conn: &Connection) -> Result<(), Box<dyn std::error
         conn.pragma_update(None, "user_version", 3)?;
     }
 
+    if version < 4 {
+        // Query-friendly views (v_history, v_sessions); see views.sql.
+        create_standard_views(conn)?;
+        conn.pragma_update(None, "user_version", 4)?;
+    }
+
+    if version < 5 {
+        // Learned typo corrections: a failed command (typo_id) and the
+        // successful command that fixed it (fix_id), both command_history
+        // ids so scrubbed entries drop out of the pairs with them.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS command_corrections (
+                 fix_id INTEGER PRIMARY KEY,
+                 typo_id INTEGER NOT NULL
+             )",
+        )?;
+        conn.pragma_update(None, "user_version", 5)?;
+    }
+
+    if version < 6 {
+        // Tombstones for deleted history: the identity hash of each deleted
+        // row (see sync::row_identity_hash), synced so deletions converge.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS tombstones (
+                 row_hash BLOB PRIMARY KEY,
+                 deleted_unix_timestamp INTEGER NOT NULL
+             )",
+        )?;
+        conn.pragma_update(None, "user_version", 6)?;
+    }
+
     Ok(())
 }
 
@@ -433,9 +523,89 @@ pub struct Invocation {
     pub session_id: i64,
     #[serde(default)]
     pub machine_id: Option<u64>,
+    /// Row id when loaded from a database; not part of the export format.
+    #[serde(skip)]
+    pub id: Option<i64>,
+    /// Totals over every run of this command, set by `aggregate_by_command`.
+    #[serde(skip)]
+    pub stats: Option<CommandStats>,
+}
+
+/// Aggregate figures for one distinct command (`pxh show --unique`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandStats {
+    pub count: u64,
+    pub first_seen: Option<i64>,
+    pub last_seen: Option<i64>,
+    /// Runs with a recorded exit status, and how many of those returned 0
+    pub with_status: u64,
+    pub succeeded: u64,
+    /// Runs with both timestamps, and their summed duration in seconds
+    pub timed: u64,
+    pub total_duration: i64,
+}
+
+impl CommandStats {
+    /// Fraction of runs with a known exit status that succeeded.
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.with_status > 0).then(|| self.succeeded as f64 / self.with_status as f64)
+    }
+
+    pub fn mean_duration(&self) -> Option<i64> {
+        (self.timed > 0).then(|| self.total_duration / self.timed as i64)
+    }
+
+    fn add(&mut self, row: &Invocation) {
+        self.count += 1;
+        if let Some(start) = row.start_unix_timestamp {
+            self.first_seen = Some(self.first_seen.map_or(start, |t| t.min(start)));
+            self.last_seen = Some(self.last_seen.map_or(start, |t| t.max(start)));
+        }
+        if let Some(status) = row.exit_status {
+            self.with_status += 1;
+            self.succeeded += u64::from(status == 0);
+        }
+        if let Some(duration) = row.duration() {
+            self.timed += 1;
+            self.total_duration += duration;
+        }
+    }
+}
+
+/// Collapse rows with identical commands into one row each, keeping the
+/// most recent run as the representative and recording totals in
+/// `Invocation::stats`. Groups are returned in order of first appearance.
+pub fn aggregate_by_command(rows: Vec<Invocation>) -> Vec<Invocation> {
+    let mut index: HashMap<BString, usize> = HashMap::new();
+    let mut groups: Vec<Invocation> = Vec::new();
+    for row in rows {
+        match index.get(&row.command) {
+            Some(&slot) => {
+                let group = &mut groups[slot];
+                let mut stats = group.stats.take().unwrap_or_default();
+                stats.add(&row);
+                if (row.start_unix_timestamp, row.id) > (group.start_unix_timestamp, group.id) {
+                    *group = row;
+                }
+                group.stats = Some(stats);
+            }
+            None => {
+                let mut stats = CommandStats::default();
+                stats.add(&row);
+                index.insert(row.command.clone(), groups.len());
+                groups.push(Invocation { stats: Some(stats), ..row });
+            }
+        }
+    }
+    groups
 }
 
 impl Invocation {
+    /// Seconds between start and end, when both are known.
+    pub fn duration(&self) -> Option<i64> {
+        Some(self.end_unix_timestamp? - self.start_unix_timestamp?)
+    }
+
     fn sameish(&self, other: &Self) -> bool {
         self.command == other.command && self.start_unix_timestamp == other.start_unix_timestamp
     }
@@ -517,13 +687,25 @@ pub fn import_zsh_history(
         .or_else(|| uzers::get_current_username().map(|v| BString::from(v.into_vec())))
         .unwrap_or_else(|| BString::from("unknown"));
     let hostname = hostname.unwrap_or_else(get_hostname);
+    let session_id = generate_import_session_id(histfile);
+    Ok(parse_zsh_history(&buf, &histfile.display().to_string(), &hostname, &username, session_id))
+}
+
+/// Parse zsh EXTENDED_HISTORY content, tagging every entry with `hostname`,
+/// `username` and `session_id`. `source` names the content in warnings.
+pub fn parse_zsh_history(
+    buf: &[u8],
+    source: &str,
+    hostname: &BString,
+    username: &BString,
+    session_id: i64,
+) -> Vec<Invocation> {
     // Pre-join backslash-continuation lines: zsh EXTENDED_HISTORY writes
     // multi-line commands as physical lines ending with '\'.
-    let logical_lines = join_continuation_lines(&buf);
+    let logical_lines = join_continuation_lines(buf);
 
     let mut ret = vec![];
     let mut skipped = 0usize;
-    let session_id = generate_import_session_id(histfile);
     for (line_num, line) in logical_lines.iter().enumerate() {
         let line = line.as_slice();
         let Some((fields, command)) = line.splitn(2, |&ch| ch == b';').collect_tuple() else {
@@ -541,8 +723,7 @@ pub fn import_zsh_history(
             Some(ts) => ts,
             None => {
                 eprintln!(
-                    "warning: {}: skipping line {}: bad timestamp {:?}",
-                    histfile.display(),
+                    "warning: {source}: skipping line {}: bad timestamp {:?}",
                     line_num + 1,
                     BString::from(start_time),
                 );
@@ -555,8 +736,7 @@ pub fn import_zsh_history(
                 Some(d) => d,
                 None => {
                     eprintln!(
-                        "warning: {}: skipping line {}: bad duration {:?}",
-                        histfile.display(),
+                        "warning: {source}: skipping line {}: bad duration {:?}",
                         line_num + 1,
                         BString::from(duration_seconds),
                     );
@@ -579,10 +759,10 @@ pub fn import_zsh_history(
     }
 
     if skipped > 0 {
-        eprintln!("warning: {}: skipped {skipped} malformed line(s)", histfile.display());
+        eprintln!("warning: {source}: skipped {skipped} malformed line(s)");
     }
 
-    Ok(dedup_invocations(ret))
+    dedup_invocations(ret)
 }
 
 pub fn import_bash_history(
@@ -597,10 +777,21 @@ pub fn import_bash_history(
         .or_else(|| uzers::get_current_username().map(|v| BString::from(v.as_bytes())))
         .unwrap_or_else(|| BString::from("unknown"));
     let hostname = hostname.unwrap_or_else(get_hostname);
+    let session_id = generate_import_session_id(histfile);
+    Ok(parse_bash_history(&buf, &hostname, &username, session_id))
+}
+
+/// Parse bash history content (with or without `#<timestamp>` lines), tagging
+/// every entry with `hostname`, `username` and `session_id`.
+pub fn parse_bash_history(
+    buf: &[u8],
+    hostname: &BString,
+    username: &BString,
+    session_id: i64,
+) -> Vec<Invocation> {
     let buf_iter = buf.split(|&ch| ch == b'\n').filter(|l| !l.is_empty());
 
     let mut ret = vec![];
-    let session_id = generate_import_session_id(histfile);
     let mut last_ts = None;
     for line in buf_iter {
         if line[0] == b'#'
@@ -624,7 +815,7 @@ pub fn import_bash_history(
         ret.push(invocation);
     }
 
-    Ok(dedup_invocations(ret))
+    dedup_invocations(ret)
 }
 
 pub fn import_json_history(histfile: &Path) -> Result<Vec<Invocation>, Box<dyn std::error::Error>> {
@@ -660,6 +851,8 @@ impl Invocation {
             start_unix_timestamp: row.get("start_unix_timestamp")?,
             end_unix_timestamp: row.get("end_unix_timestamp")?,
             machine_id: row.get::<_, Option<i64>>("machine_id").ok().flatten().map(|v| v as u64),
+            id: row.get("id").ok(),
+            stats: None,
         })
     }
 }
@@ -696,7 +889,10 @@ impl From<Option<&Vec<u8>>> for PrettyExportString {
 }
 
 impl Invocation {
-    fn to_json_export(&self) -> serde_json::Value {
+    /// The JSON object used by `pxh export` (and accepted by `import
+    /// --shellname json`): byte fields are strings when valid UTF-8 and
+    /// byte arrays otherwise.
+    pub fn to_json_export(&self) -> serde_json::Value {
         serde_json::json!({
             "session_id": self.session_id,
             "command": PrettyExportString::from(self.command.as_slice()),
@@ -724,6 +920,297 @@ pub fn json_export(rows: &[Invocation]) -> Result<(), Box<dyn std::error::Error>
     Ok(())
 }
 
+/// How `write_results` renders rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The human-readable table (`present_results_human_readable`)
+    Table,
+    /// A JSON array in the `pxh export` format
+    Json,
+    /// One `pxh export` JSON object per line
+    Ndjson,
+    Csv,
+    /// Tab-separated, with tabs, newlines and backslashes escaped
+    Tsv,
+    /// A `{field}` template rendered once per row (see `RowTemplate`)
+    Template(String),
+    /// Raw commands, each terminated by NUL (for `xargs -0`, fzf `--read0`)
+    Null,
+}
+
+/// Extra CSV/TSV columns when rows carry `CommandStats` (`show --unique`).
+const STATS_COLUMNS: &[&str] = &["count", "first_seen", "last_seen", "success_rate"];
+
+/// Columns written by the CSV and TSV formats, in order.
+const DELIMITED_COLUMNS: &[&str] = &[
+    "start_unix_timestamp",
+    "end_unix_timestamp",
+    "duration",
+    "session_id",
+    "exit_status",
+    "hostname",
+    "username",
+    "shellname",
+    "working_directory",
+    "command",
+];
+
+/// Render `rows` to stdout in `format`. `fields`, `markers` and
+/// `suppress_headers` apply to the table; headers also apply to CSV/TSV.
+pub fn write_results(
+    format: &OutputFormat,
+    fields: &[&str],
+    rows: &[Invocation],
+    markers: Option<&[RowMarker]>,
+    suppress_headers: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if *format == OutputFormat::Table {
+        return present_results_human_readable(fields, rows, markers, suppress_headers);
+    }
+    // Parse the template before writing anything so a bad field fails cleanly.
+    let template = match format {
+        OutputFormat::Template(t) => Some(RowTemplate::parse(t)?),
+        _ => None,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    match format {
+        OutputFormat::Table => unreachable!("handled above"),
+        OutputFormat::Json => {
+            let values: Vec<serde_json::Value> = rows.iter().map(json_with_stats).collect();
+            serde_json::to_writer(&mut out, &values)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut out, &json_with_stats(row))?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let (separator, escape): (&str, fn(&str) -> String) =
+                if *format == OutputFormat::Csv { (",", csv_escape) } else { ("\t", tsv_escape) };
+            let with_stats = rows.iter().any(|r| r.stats.is_some());
+            if !suppress_headers {
+                let stats_columns = if with_stats { STATS_COLUMNS } else { &[] };
+                writeln!(out, "{}", DELIMITED_COLUMNS.iter().chain(stats_columns).join(separator))?;
+            }
+            for row in rows {
+                let mut values = delimited_values(row);
+                if with_stats {
+                    values.extend(stats_values(row.stats.unwrap_or_default()));
+                }
+                writeln!(out, "{}", values.iter().map(|v| escape(v)).join(separator))?;
+            }
+        }
+        OutputFormat::Template(_) => {
+            let template = template.expect("parsed above");
+            for row in rows {
+                writeln!(out, "{}", template.render(row))?;
+            }
+        }
+        OutputFormat::Null => {
+            for row in rows {
+                out.write_all(row.command.as_slice())?;
+                out.write_all(b"\0")?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// The export object, plus the `STATS_COLUMNS` keys for aggregated rows.
+fn json_with_stats(row: &Invocation) -> serde_json::Value {
+    let mut value = row.to_json_export();
+    if let (Some(stats), Some(object)) = (row.stats, value.as_object_mut()) {
+        object.insert("count".into(), stats.count.into());
+        object.insert("first_seen".into(), stats.first_seen.into());
+        object.insert("last_seen".into(), stats.last_seen.into());
+        object.insert("success_rate".into(), stats.success_rate().into());
+    }
+    value
+}
+
+fn stats_values(stats: CommandStats) -> Vec<String> {
+    let opt = |v: Option<i64>| v.map(|v| v.to_string()).unwrap_or_default();
+    vec![
+        stats.count.to_string(),
+        opt(stats.first_seen),
+        opt(stats.last_seen),
+        stats.success_rate().map(|r| format!("{r:.3}")).unwrap_or_default(),
+    ]
+}
+
+fn delimited_values(row: &Invocation) -> Vec<String> {
+    let opt = |v: Option<i64>| v.map(|v| v.to_string()).unwrap_or_default();
+    let bytes = |v: &Option<BString>| v.as_ref().map(binary_display_helper).unwrap_or_default();
+    let duration = row.duration();
+    vec![
+        opt(row.start_unix_timestamp),
+        opt(row.end_unix_timestamp),
+        opt(duration),
+        row.session_id.to_string(),
+        opt(row.exit_status),
+        bytes(&row.hostname),
+        bytes(&row.username),
+        row.shellname.clone(),
+        bytes(&row.working_directory),
+        binary_display_helper(&row.command),
+    ]
+}
+
+/// Quote a CSV field (RFC 4180) when it contains a separator, quote or newline.
+pub fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn tsv_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+/// A parsed `show --template`: literal text with `{field}` or
+/// `{field:strftime}` placeholders. `{{` and `}}` are literal braces;
+/// backslashes have no special meaning.
+///
+/// Fields: `start`, `end` (local time; default format `%Y-%m-%d %H:%M:%S`),
+/// `duration` (seconds), `dir`, `host`, `user`, `shell`, `session`,
+/// `status` and `command`. Unknown values render empty. Newlines in values
+/// are written as `\n` so every row stays on one line.
+#[derive(Debug, PartialEq)]
+pub struct RowTemplate {
+    segments: Vec<TemplateSegment>,
+}
+
+#[derive(Debug, PartialEq)]
+enum TemplateSegment {
+    Literal(String),
+    Field(TemplateField, Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TemplateField {
+    Start,
+    End,
+    Duration,
+    Dir,
+    Host,
+    User,
+    Shell,
+    Session,
+    Status,
+    Command,
+}
+
+impl RowTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(format!("unclosed '{{' in template: {template}")),
+                        }
+                    }
+                    let (name, format) = match spec.split_once(':') {
+                        Some((name, format)) => (name, Some(format.to_string())),
+                        None => (spec.as_str(), None),
+                    };
+                    let field = match name {
+                        "start" => TemplateField::Start,
+                        "end" => TemplateField::End,
+                        "duration" => TemplateField::Duration,
+                        "dir" => TemplateField::Dir,
+                        "host" => TemplateField::Host,
+                        "user" => TemplateField::User,
+                        "shell" => TemplateField::Shell,
+                        "session" => TemplateField::Session,
+                        "status" => TemplateField::Status,
+                        "command" => TemplateField::Command,
+                        _ => return Err(format!("unknown template field: {{{name}}}")),
+                    };
+                    if format.is_some()
+                        && !matches!(field, TemplateField::Start | TemplateField::End)
+                    {
+                        return Err(format!(
+                            "only {{start}} and {{end}} take a format: {{{spec}}}"
+                        ));
+                    }
+                    if let Some(format) = &format
+                        && chrono::format::StrftimeItems::new(format)
+                            .any(|item| item == chrono::format::Item::Error)
+                    {
+                        return Err(format!("invalid time format in template: {{{spec}}}"));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(TemplateSegment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(TemplateSegment::Field(field, format));
+                }
+                '}' => return Err(format!("unmatched '}}' in template: {template}")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(TemplateSegment::Literal(literal));
+        }
+        Ok(RowTemplate { segments })
+    }
+
+    pub fn render(&self, row: &Invocation) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            let (field, format) = match segment {
+                TemplateSegment::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                TemplateSegment::Field(field, format) => (*field, format.as_deref()),
+            };
+            let time = |t: Option<i64>| {
+                t.and_then(|t| Local.timestamp_opt(t, 0).single())
+                    .map(|t| t.format(format.unwrap_or(TIME_FORMAT)).to_string())
+                    .unwrap_or_default()
+            };
+            let bytes =
+                |v: &Option<BString>| v.as_ref().map(binary_display_helper).unwrap_or_default();
+            let value = match field {
+                TemplateField::Start => time(row.start_unix_timestamp),
+                TemplateField::End => time(row.end_unix_timestamp),
+                TemplateField::Duration => {
+                    row.duration().map(|d| d.to_string()).unwrap_or_default()
+                }
+                TemplateField::Dir => bytes(&row.working_directory),
+                TemplateField::Host => bytes(&row.hostname),
+                TemplateField::User => bytes(&row.username),
+                TemplateField::Shell => row.shellname.clone(),
+                TemplateField::Session => row.session_id.to_string(),
+                TemplateField::Status => row.exit_status.map(|s| s.to_string()).unwrap_or_default(),
+                TemplateField::Command => binary_display_helper(&row.command),
+            };
+            out.push_str(&value.replace('\n', "\\n").replace('\r', "\\r"));
+        }
+        out
+    }
+}
+
 // column list: command, start, host, shell, cwd, end, duratio, session, ...
 
 struct QueryResultColumnDisplayer {
@@ -805,6 +1292,68 @@ fn displayers() -> HashMap<&'static str, QueryResultColumnDisplayer> {
             }),
         },
     );
+    // Aggregate columns for `show --unique`; blank for plain rows.
+    ret.insert(
+        "count",
+        QueryResultColumnDisplayer {
+            header: "Count",
+            header_style: "Fy",
+            displayer: Box::new(|row| {
+                let text = row.stats.map(|s| s.count.to_string()).unwrap_or_default();
+                prettytable::Cell::new(&text).style_spec("rFy")
+            }),
+        },
+    );
+    ret.insert(
+        "first_seen",
+        QueryResultColumnDisplayer {
+            header: "First Seen",
+            header_style: "Fg",
+            displayer: Box::new(|row| {
+                let first_seen = row.stats.and_then(|s| s.first_seen);
+                prettytable::Cell::new(&time_display_helper(first_seen)).style_spec("Fg")
+            }),
+        },
+    );
+    ret.insert(
+        "last_seen",
+        QueryResultColumnDisplayer {
+            header: "Last Seen",
+            header_style: "Fg",
+            displayer: Box::new(|row| {
+                let last_seen = row.stats.and_then(|s| s.last_seen);
+                prettytable::Cell::new(&time_display_helper(last_seen)).style_spec("Fg")
+            }),
+        },
+    );
+    ret.insert(
+        "success_rate",
+        QueryResultColumnDisplayer {
+            header: "OK",
+            header_style: "Fg",
+            displayer: Box::new(|row| match row.stats.and_then(|s| s.success_rate()) {
+                Some(rate) if rate < 1.0 => {
+                    prettytable::Cell::new(&format!("{:.0}%", rate * 100.0)).style_spec("rFr")
+                }
+                Some(_) => prettytable::Cell::new("100%").style_spec("rFg"),
+                None => prettytable::Cell::new("n/a").style_spec("rFd"),
+            }),
+        },
+    );
+    ret.insert(
+        "avg_duration",
+        QueryResultColumnDisplayer {
+            header: "Avg Duration",
+            header_style: "Fm",
+            displayer: Box::new(|row| {
+                let text = match row.stats.and_then(|s| s.mean_duration()) {
+                    Some(duration) => format!("{duration}s"),
+                    None => "n/a".into(),
+                };
+                prettytable::Cell::new(&text).style_spec("Fm")
+            }),
+        },
+    );
     // TODO: Make session similar to "context" and just print `.` when
     // it is the current session.
     ret.insert(
@@ -846,9 +1395,23 @@ fn displayers() -> HashMap<&'static str, QueryResultColumnDisplayer> {
     ret
 }
 
+/// Per-row annotation for results shown with surrounding context (`show
+/// -A/-B/-C`): whether the row matched the search or is a neighbour, and
+/// whether it starts a new group (printed after a `--` separator, as grep
+/// does).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowMarker {
+    pub matched: bool,
+    pub group_start: bool,
+}
+
+/// Print `rows` as a table of `fields` (keys of `displayers()`). With
+/// `markers` (one per row), a leading column flags matched rows with `>`
+/// and groups are separated by `--` lines.
 pub fn present_results_human_readable(
     fields: &[&str],
     rows: &[Invocation],
+    markers: Option<&[RowMarker]>,
     suppress_headers: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let displayers = displayers();
@@ -857,6 +1420,9 @@ pub fn present_results_human_readable(
 
     if !suppress_headers {
         let mut title_row = prettytable::Row::empty();
+        if markers.is_some() {
+            title_row.add_cell(prettytable::Cell::new(""));
+        }
         for field in fields {
             let Some(d) = displayers.get(field) else {
                 return Err(Box::from(format!("Invalid 'show' field: {field}")));
@@ -867,9 +1433,21 @@ pub fn present_results_human_readable(
         table.set_titles(title_row);
     }
 
-    for row in rows.iter() {
+    for (i, row) in rows.iter().enumerate() {
         let is_failed = matches!(row.exit_status, Some(s) if s != 0);
         let mut display_row = prettytable::Row::empty();
+        if let Some(marker) = markers.map(|m| m[i]) {
+            if marker.group_start && i > 0 {
+                table.add_row(prettytable::Row::new(vec![
+                    prettytable::Cell::new("--").style_spec("Fd"),
+                ]));
+            }
+            display_row.add_cell(if marker.matched {
+                prettytable::Cell::new(">").style_spec("bFy")
+            } else {
+                prettytable::Cell::new(" ")
+            });
+        }
         for field in fields {
             let cell = (displayers[field].displayer)(row);
             if is_failed {
@@ -955,6 +1533,9 @@ pub mod helpers {
     use std::ffi::OsString;
     use std::path::{Path, PathBuf};
 
+    use bstr::ByteSlice;
+    use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Weekday};
+
     /// Parse an SSH command string into command and arguments, handling quotes and spaces.
     /// Similar to how rsync and other tools parse the -e option.
     pub fn parse_ssh_command(ssh_cmd: &str) -> (String, Vec<String>) {
@@ -1017,6 +1598,49 @@ pub mod helpers {
         (cmd, args)
     }
 
+    /// Turn a `--transport` template into the program and arguments that run
+    /// `remote_command` for `target`. The template is split like `-e`;
+    /// `{target}` anywhere in it becomes the target. A `{command}` argument
+    /// becomes the remote shell command; without one it is appended as
+    /// `sh -c <command>`, since exec-style transports (`kubectl exec`,
+    /// `docker exec`, `sudo`) run their arguments without a shell.
+    /// `{command}` inside a larger word is rejected: how to quote it there
+    /// depends on what reads the word.
+    pub fn transport_command(
+        template: &str,
+        target: &str,
+        remote_command: &str,
+    ) -> Result<(String, Vec<String>), String> {
+        let (program, args) = parse_ssh_command(template.trim());
+        if program.is_empty() {
+            return Err("--transport template is empty".to_string());
+        }
+        if program.contains("{command}")
+            || args.iter().any(|arg| arg != "{command}" && arg.contains("{command}"))
+        {
+            return Err(
+                "{command} must be a whole argument of the --transport template".to_string()
+            );
+        }
+        let program = program.replace("{target}", target);
+        let mut has_command = false;
+        let mut args: Vec<String> = args
+            .into_iter()
+            .map(|arg| {
+                if arg == "{command}" {
+                    has_command = true;
+                    remote_command.to_string()
+                } else {
+                    arg.replace("{target}", target)
+                }
+            })
+            .collect();
+        if !has_command {
+            args.extend(["sh".to_string(), "-c".to_string(), remote_command.to_string()]);
+        }
+        Ok((program, args))
+    }
+
     /// Build a list of candidate paths to search for pxh on the remote host.
     /// The first candidate that exists and is executable will be used.
     fn remote_pxh_candidates(configured_path: &str) -> Vec<String> {
@@ -1095,6 +1719,238 @@ pub mod helpers {
         r#"$(if [ -d "${XDG_DATA_HOME:-$HOME/.local/share}/pxh" ]; then echo "${XDG_DATA_HOME:-$HOME/.local/share}/pxh/pxh.db"; elif [ -d "$HOME/.pxh" ]; then echo "$HOME/.pxh/pxh.db"; else echo "${XDG_DATA_HOME:-$HOME/.local/share}/pxh/pxh.db"; fi)"#.to_string()
     }
 
+    /// Parse a point in time for `--since`/`--until` style options into a
+    /// unix timestamp, relative to `now`. Accepts:
+    /// - relative offsets: `90s`, `30m`, `2h`, `3d`, `1w`
+    /// - `now`, `today`, `yesterday`, and weekday names (`monday`, `mon`),
+    ///   meaning local midnight of the most recent such day (today included)
+    /// - absolute local times: `2025-01-31`, `2025-01-31 14:00`,
+    ///   `2025-01-31 14:00:05` (a `T` separator also works)
+    /// - raw unix timestamps prefixed with `@`: `@1700000000`
+    pub fn parse_time_spec(spec: &str, now: DateTime<Local>) -> Result<i64, String> {
+        let spec = spec.trim();
+        let lower = spec.to_ascii_lowercase();
+        let midnight = |date: NaiveDate| -> Result<i64, String> {
+            local_timestamp(date.and_hms_opt(0, 0, 0).expect("midnight is valid"), spec)
+        };
+
+        if let Some(raw) = lower.strip_prefix('@') {
+            return raw.parse::<i64>().map_err(|_| format!("invalid unix timestamp: {spec}"));
+        }
+        match lower.as_str() {
+            "now" => return Ok(now.timestamp()),
+            "today" => return midnight(now.date_naive()),
+            "yesterday" => return midnight(now.date_naive() - chrono::Days::new(1)),
+            _ => {}
+        }
+        if let Ok(weekday) = lower.parse::<Weekday>() {
+            let back =
+                (now.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+            return midnight(now.date_naive() - chrono::Days::new(back.into()));
+        }
+
+        if let Some(secs) = seconds_with_unit(&lower) {
+            return Ok(now.timestamp() - secs);
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+            return midnight(date);
+        }
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        {
+            if let Ok(datetime) = NaiveDateTime::parse_from_str(spec, format) {
+                return local_timestamp(datetime, spec);
+            }
+        }
+        Err(format!(
+            "unrecognized time '{spec}' (try 2h, 3d, yesterday, monday, 2025-01-31 or @<unix time>)"
+        ))
+    }
+
+    /// Parse a report period, a year (`2025`) or a month (`2025-03`), into
+    /// its local-time range `[start, end)` as unix timestamps.
+    pub fn parse_period(spec: &str) -> Result<(i64, i64), String> {
+        let spec = spec.trim();
+        let invalid = || format!("unrecognized period '{spec}' (try 2025 or 2025-03)");
+        let (start, end) = match spec.split_once('-') {
+            None => {
+                let year: i32 = spec.parse().map_err(|_| invalid())?;
+                (NaiveDate::from_ymd_opt(year, 1, 1), NaiveDate::from_ymd_opt(year + 1, 1, 1))
+            }
+            Some((year, month)) => {
+                let year: i32 = year.parse().map_err(|_| invalid())?;
+                let month: u32 = month.parse().map_err(|_| invalid())?;
+                let start = NaiveDate::from_ymd_opt(year, month, 1);
+                (start, start.and_then(|d| d.checked_add_months(chrono::Months::new(1))))
+            }
+        };
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err(invalid());
+        };
+        let midnight = |date: NaiveDate| {
+            local_timestamp(date.and_hms_opt(0, 0, 0).expect("midnight is valid"), spec)
+        };
+        Ok((midnight(start)?, midnight(end)?))
+    }
+
+    /// Parse a length of time for `--min-duration` style options into
+    /// seconds: a bare number of seconds or `90s`, `5m`, `2h`, `1d`, `1w`.
+    pub fn parse_duration_spec(spec: &str) -> Result<i64, String> {
+        let lower = spec.trim().to_ascii_lowercase();
+        lower
+            .parse::<i64>()
+            .ok()
+            .filter(|secs| *secs >= 0)
+            .or_else(|| seconds_with_unit(&lower))
+            .ok_or_else(|| format!("unrecognized duration '{spec}' (try 30, 90s, 5m or 2h)"))
+    }
+
+    // `<digits><s|m|h|d|w>` as a number of seconds.
+    fn seconds_with_unit(spec: &str) -> Option<i64> {
+        let split = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+        let n = spec[..split].parse::<i64>().ok()?;
+        let unit_secs = match &spec[split..] {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            "w" => 7 * 86400,
+            _ => return None,
+        };
+        Some(n.saturating_mul(unit_secs))
+    }
+
+    fn local_timestamp(datetime: NaiveDateTime, spec: &str) -> Result<i64, String> {
+        // Around DST transitions a local time may be ambiguous; take the
+        // earlier instant so ranges err on the inclusive side.
+        Local
+            .from_local_datetime(&datetime)
+            .earliest()
+            .map(|dt| dt.timestamp())
+            .ok_or_else(|| format!("nonexistent local time: {spec}"))
+    }
+
+    /// Seconds as `42s`, `3m 5s` or `1h 2m 3s`.
+    pub fn format_duration(secs: i64) -> String {
+        if secs < 60 {
+            format!("{secs}s")
+        } else if secs < 3600 {
+            format!("{}m {}s", secs / 60, secs % 60)
+        } else {
+            format!("{}h {}m {}s", secs / 3600, (secs % 3600) / 60, secs % 60)
+        }
+    }
+
+    /// The program a command line runs: its first word, skipping `VAR=value`
+    /// assignments and `sudo`/`env` wrappers (with their options), without
+    /// any leading directory. `None` for blank commands.
+    pub fn command_program(command: &[u8]) -> Option<String> {
+        let words = command_words(command);
+        let word = words.first()?;
+        let name = word.rsplit_once_str("/").map_or(word.as_slice(), |(_, name)| name);
+        Some(String::from_utf8_lossy(name).into_owned())
+    }
+
+    /// The words of a command line from its program on, skipping the same
+    /// assignments and wrappers as `command_program`.
+    pub fn command_words(command: &[u8]) -> Vec<Vec<u8>> {
+        let mut words = shell_words(command).into_iter().peekable();
+        while let Some(word) = words.next() {
+            let is_assignment = word.split_once_str("=").is_some_and(|(name, _)| {
+                !name.is_empty() && name.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_')
+            });
+            if is_assignment {
+                continue;
+            }
+            match word.as_slice() {
+                b"sudo" | b"env" => {
+                    // Skip wrapper options; these sudo options take a value.
+                    while let Some(option) = words.next_if(|w| w.starts_with(b"-")) {
+                        if matches!(
+                            option.as_slice(),
+                            b"-u" | b"-g" | b"-C" | b"-D" | b"-h" | b"-p" | b"-U"
+                        ) {
+                            words.next();
+                        }
+                    }
+                }
+                _ => return std::iter::once(word).chain(words).collect(),
+            }
+        }
+        Vec::new()
+    }
+
+    /// Edit distance between two byte strings, counting insertions,
+    /// deletions, substitutions and adjacent transpositions (`buidl` ->
+    /// `build`) as one edit each.
+    pub fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+        // Optimal string alignment: Levenshtein over three rolling rows.
+        let mut before: Vec<usize> = vec![0; b.len() + 1];
+        let mut prev: Vec<usize> = (0..=b.len()).collect();
+        let mut cur = vec![0; b.len() + 1];
+        for i in 1..=a.len() {
+            cur[0] = i;
+            for j in 1..=b.len() {
+                let cost = usize::from(a[i - 1] != b[j - 1]);
+                cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    cur[j] = cur[j].min(before[j - 2] + 1);
+                }
+            }
+            std::mem::swap(&mut before, &mut prev);
+            std::mem::swap(&mut prev, &mut cur);
+        }
+        prev[b.len()]
+    }
+
+    /// Byte ranges of the whitespace-separated words in a command, without
+    /// interpreting quotes.
+    pub fn word_spans(command: &[u8]) -> Vec<std::ops::Range<usize>> {
+        let mut spans = Vec::new();
+        let mut start = None;
+        for (i, b) in command.iter().enumerate() {
+            match (start, b.is_ascii_whitespace()) {
+                (None, false) => start = Some(i),
+                (Some(s), true) => {
+                    spans.push(s..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        spans.extend(start.map(|s| s..command.len()));
+        spans
+    }
+
+    /// Split on unquoted whitespace, removing quotes and backslash escapes.
+    /// Just enough shell syntax to find a command's leading words or read an
+    /// alias definition.
+    pub fn shell_words(command: &[u8]) -> Vec<Vec<u8>> {
+        let mut words = Vec::new();
+        let mut word: Option<Vec<u8>> = None;
+        let mut quote: Option<u8> = None;
+        let mut bytes = command.iter().copied();
+        while let Some(b) = bytes.next() {
+            match (quote, b) {
+                (Some(q), b) if b == q => quote = None,
+                (Some(b'"'), b'\\') | (None, b'\\') => {
+                    if let Some(next) = bytes.next() {
+                        word.get_or_insert_with(Vec::new).push(next);
+                    }
+                }
+                (Some(_), b) => word.get_or_insert_with(Vec::new).push(b),
+                (None, b'\'' | b'"') => {
+                    quote = Some(b);
+                    word.get_or_insert_with(Vec::new);
+                }
+                (None, b) if b.is_ascii_whitespace() => words.extend(word.take()),
+                (None, b) => word.get_or_insert_with(Vec::new).push(b),
+            }
+        }
+        words.extend(word);
+        words
+    }
+
     /// Determine if the executable is being invoked as pxhs (shorthand for pxh show)
     pub fn determine_is_pxhs(args: &[OsString]) -> bool {
         args.first()
@@ -1441,4 +2297,115 @@ mod tests {
         // Should give up roughly at the cap, not run forever.
         assert!(elapsed < Duration::from_millis(200), "took {elapsed:?}");
     }
+
+    #[test]
+    fn test_row_template() {
+        let row = Invocation {
+            command: BString::from("printf 'a\nb'"),
+            shellname: "zsh".into(),
+            working_directory: Some(BString::from("/tmp")),
+            session_id: 7,
+            exit_status: Some(1),
+            start_unix_timestamp: Some(100),
+            end_unix_timestamp: Some(103),
+            ..Default::default()
+        };
+        let template =
+            RowTemplate::parse("{{{session}}} {duration}s {status} {dir} {command}").unwrap();
+        assert_eq!(template.render(&row), "{7} 3s 1 /tmp printf 'a\\nb'");
+
+        let start = Local.timestamp_opt(100, 0).unwrap().format("%H:%M").to_string();
+        assert_eq!(RowTemplate::parse("{start:%H:%M}").unwrap().render(&row), start);
+        // Missing values render empty
+        assert_eq!(RowTemplate::parse("[{host}]").unwrap().render(&row), "[]");
+        // Doubled braces are literal; a backslash is just a backslash
+        let template = RowTemplate::parse(r"{{dir}} {{{{ }}\{dir}\n").unwrap();
+        assert_eq!(template.render(&row), r"{dir} {{ }\/tmp\n");
+
+        for bad in ["{nope}", "{command", "oops}", "{dir:%F}", "{start:%Q}", r"\{x\}"] {
+            assert!(RowTemplate::parse(bad).is_err(), "{bad} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_standard_views_and_sql_helpers() {
+        let conn = test_connection();
+        register_regexp(&conn).unwrap();
+        register_sql_helpers(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO command_history (session_id, full_command, shellname, hostname, start_unix_timestamp, end_unix_timestamp, exit_status)
+             VALUES (7, CAST(? AS BLOB), 'zsh', CAST('h' AS BLOB), 100, 130, 1)",
+            [b"make \xff".as_slice()],
+        )
+        .unwrap();
+
+        let (command, duration, hostname): (String, i64, String) = conn
+            .query_row("SELECT text(command), duration, hostname FROM v_history", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((command.as_str(), duration, hostname.as_str()), ("make \u{fffd}", 30, "h"));
+
+        let (commands, failures): (i64, i64) = conn
+            .query_row("SELECT commands, failures FROM v_sessions WHERE session_id = 7", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((commands, failures), (1, 1));
+
+        let expected = Local.timestamp_opt(100, 0).unwrap().format("%Y").to_string();
+        let (duration, year, matched): (Option<i64>, String, bool) = conn
+            .query_row(
+                "SELECT duration(start_unix_timestamp, end_unix_timestamp), fmt_time(start_unix_timestamp, '%Y'),
+                        full_command REGEXP '^make'
+                   FROM command_history",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((duration, year, matched), (Some(30), expected, true));
+        let missing: Option<i64> =
+            conn.query_row("SELECT duration(NULL, 5)", [], |r| r.get(0)).unwrap();
+        assert_eq!(missing, None);
+        assert!(conn.query_row("SELECT fmt_time(1, '%Q')", [], |r| r.get::<_, String>(0)).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_by_command() {
+        let run = |id, command: &str, start, end: Option<i64>, status| Invocation {
+            id: Some(id),
+            command: BString::from(command),
+            start_unix_timestamp: Some(start),
+            end_unix_timestamp: end,
+            exit_status: status,
+            ..Default::default()
+        };
+        // Newest first, as `show` loads them
+        let rows = vec![
+            run(4, "make", 400, Some(402), Some(0)),
+            run(3, "ls", 300, None, None),
+            run(2, "make", 200, Some(210), Some(2)),
+            run(1, "make", 100, None, Some(0)),
+        ];
+        let groups = aggregate_by_command(rows);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].command, "make");
+        assert_eq!(groups[0].id, Some(4), "the most recent run represents the group");
+        let stats = groups[0].stats.unwrap();
+        assert_eq!((stats.count, stats.first_seen, stats.last_seen), (3, Some(100), Some(400)));
+        assert_eq!(stats.success_rate(), Some(2.0 / 3.0));
+        assert_eq!(stats.mean_duration(), Some(6));
+
+        let stats = groups[1].stats.unwrap();
+        assert_eq!(stats.count, 1);
+        assert_eq!((stats.success_rate(), stats.mean_duration()), (None, None));
+    }
+
+    #[test]
+    fn test_delimited_escaping() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+        assert_eq!(tsv_escape("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
 }
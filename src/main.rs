@@ -14,11 +14,19 @@ use std::{
 use bstr::{BString, ByteSlice};
 use chrono::prelude::{Local, TimeZone};
 use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use pxh::recall::config::{SyncDirection, SyncFormat, SyncTarget, SyncTargetKind};
 use regex::bytes::Regex;
 use rusqlite::{Connection, OpenFlags, Result};
 use tempfile::NamedTempFile;
 
+mod aliases;
 mod doctor;
+mod fix;
+mod report;
+mod sql;
+mod stats;
+mod timesheet;
+mod workflows;
 
 #[derive(Clone, Debug, ValueEnum)]
 enum ConfidenceLevel {
@@ -28,6 +36,29 @@ enum ConfidenceLevel {
     All,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+enum ShowFormat {
+    #[default]
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+enum ShowSort {
+    /// Most recent last
+    #[default]
+    Time,
+    /// Longest running last
+    Duration,
+    /// Most frequent last (implies --unique)
+    Count,
+    /// Alphabetical
+    Command,
+}
+
 impl ConfidenceLevel {
     fn as_str(&self) -> &str {
         match self {
@@ -60,6 +91,247 @@ struct SyncOptions {
     /// Disable secret filtering during sync import
     #[serde(skip_serializing_if = "Option::is_none")]
     no_secret_filter: Option<bool>,
+    /// Incremental sync: "send", "receive" or "bidirectional", as seen by the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    direction: Option<String>,
+    /// Incremental sync: the client's local_machine_id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    machine_id: Option<u64>,
+    /// v3: stream compressions the client can use, in order of preference
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression: Option<Vec<pxh::sync_stream::Compression>>,
+}
+
+/// Incremental sync handshake, one JSON line each way after the options: a
+/// side's local_machine_id, the watermark it holds for the peer's, and where
+/// its unsealed rows start (see `pxh::sync::oldest_unsealed_start`).
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct SyncHello {
+    machine_id: Option<u64>,
+    watermark: Option<i64>,
+    unsealed_since: Option<i64>,
+    /// v3, from the server: the compression both sides' streams use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<pxh::sync_stream::Compression>,
+}
+
+/// Remote sync protocols, newest first; `handle_remote_sync` falls back
+/// through them for older peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyncProtocol {
+    /// v3: incremental, with framed, checksummed, optionally compressed
+    /// database streams
+    Framed,
+    /// v2: incremental, databases sent raw
+    Incremental,
+    /// Full snapshots, sent raw
+    Legacy,
+}
+
+/// Merges into the local database happen one at a time, however many
+/// hosts `pxh sync --remote` is talking to.
+static MERGE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+fn merge_lock() -> std::sync::MutexGuard<'static, ()> {
+    MERGE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Let a peer that hangs up fail only its own sync instead of killing us
+/// with SIGPIPE mid-run (`main` resets it to the default).
+fn ignore_sigpipe() {
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_IGN);
+    }
+}
+
+/// Kills an SSH child once a read from or write to it has been blocked for
+/// longer than the timeout -- the host never answering, or the transfer
+/// stalling -- so the run fails instead of hanging. Only I/O on pipes it
+/// [`watch`](Watchdog::watch)es counts: time spent merging locally doesn't,
+/// nor waiting for the peer to merge what we sent (see
+/// [`await_reply`](Watchdog::await_reply)). Dropping it disarms it.
+struct Watchdog {
+    _disarm: std::sync::mpsc::Sender<()>,
+    state: std::sync::Arc<WatchdogState>,
+    timeout: Duration,
+}
+
+#[derive(Default)]
+struct WatchdogState {
+    blocked_since: std::sync::Mutex<Option<std::time::Instant>>,
+    answered: std::sync::atomic::AtomicBool,
+    /// Set by [`Watchdog::await_reply`]: the next read may wait on the peer
+    /// merging what we sent.
+    awaiting_reply: std::sync::atomic::AtomicBool,
+    fired: std::sync::atomic::AtomicBool,
+}
+
+impl WatchdogState {
+    fn time<T>(&self, io: impl FnOnce() -> T) -> T {
+        let blocked = || self.blocked_since.lock().unwrap_or_else(|p| p.into_inner());
+        *blocked() = Some(std::time::Instant::now());
+        let result = io();
+        *blocked() = None;
+        result
+    }
+}
+
+impl Watchdog {
+    fn arm(pid: u32, timeout: Duration) -> Self {
+        let (disarm, disarmed) = std::sync::mpsc::channel::<()>();
+        let state = std::sync::Arc::new(WatchdogState::default());
+        let watched = state.clone();
+        let tick = timeout.min(Duration::from_secs(1));
+        std::thread::spawn(move || {
+            while disarmed.recv_timeout(tick) == Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+                let blocked_since =
+                    *watched.blocked_since.lock().unwrap_or_else(|p| p.into_inner());
+                if blocked_since.is_some_and(|since| since.elapsed() >= timeout) {
+                    watched.fired.store(true, std::sync::atomic::Ordering::SeqCst);
+                    unsafe {
+                        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                    }
+                    break;
+                }
+            }
+        });
+        Watchdog { _disarm: disarm, state, timeout }
+    }
+
+    /// `pipe`, with its reads and writes timed.
+    fn watch<T>(&self, pipe: T) -> Watched<T> {
+        Watched { inner: pipe, state: self.state.clone() }
+    }
+
+    /// Don't time the wait for the peer's next reply: having received all
+    /// we send, it merges before answering, however long that takes.
+    fn await_reply(&self) {
+        self.state.awaiting_reply.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Why the child was killed, if it was.
+    fn failure(&self) -> Option<String> {
+        if !self.state.fired.load(std::sync::atomic::Ordering::SeqCst) {
+            return None;
+        }
+        let secs = self.timeout.as_secs();
+        Some(if self.state.answered.load(std::sync::atomic::Ordering::SeqCst) {
+            format!("transfer stalled for {secs}s")
+        } else {
+            format!("no response within {secs}s")
+        })
+    }
+}
+
+/// A pipe to an SSH child whose reads and writes a [`Watchdog`] times.
+struct Watched<T> {
+    inner: T,
+    state: std::sync::Arc<WatchdogState>,
+}
+
+impl<R: Read> Read for Watched<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = if self.state.awaiting_reply.load(std::sync::atomic::Ordering::SeqCst) {
+            self.inner.read(buf)
+        } else {
+            self.state.time(|| self.inner.read(buf))
+        };
+        if read.as_ref().is_ok_and(|&n| n > 0) {
+            self.state.answered.store(true, std::sync::atomic::Ordering::SeqCst);
+            self.state.awaiting_reply.store(false, std::sync::atomic::Ordering::SeqCst);
+        }
+        read
+    }
+}
+
+impl<W: Write> Write for Watched<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.time(|| self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.time(|| self.inner.flush())
+    }
+}
+
+/// How one host of a multi-host `pxh sync --remote` went.
+struct HostResult {
+    outcome: Result<Option<pxh::sync::MergeStats>, String>,
+    elapsed: Duration,
+}
+
+/// The table `pxh sync` prints after syncing with several hosts.
+fn format_host_summary(hosts: &[String], results: &[HostResult]) -> String {
+    let width = hosts.iter().map(|h| h.len()).max().unwrap_or(0).max(4);
+    let mut out = format!(
+        "\nSync summary:\n  {:width$}  {:6}  {:>6}  {:>10}  {:>8}  {:>7}\n",
+        "HOST", "RESULT", "ADDED", "CONSIDERED", "FILTERED", "TIME"
+    );
+    for (host, result) in hosts.iter().zip(results) {
+        let time = format!("{:.1}s", result.elapsed.as_secs_f64());
+        let (status, stats, error) = match &result.outcome {
+            Ok(stats) => ("ok", *stats, None),
+            Err(e) => ("FAILED", None, Some(e)),
+        };
+        let [added, considered, filtered] = match stats {
+            Some(s) => [s.added, s.considered, s.filtered].map(|n| n.to_string()),
+            None => ["-", "-", "-"].map(String::from),
+        };
+        out.push_str(&format!(
+            "  {host:width$}  {status:6}  {added:>6}  {considered:>10}  {filtered:>8}  {time:>7}"
+        ));
+        if let Some(error) = error {
+            out.push_str(&format!("  {error}"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The last non-empty line a peer wrote to the captured stderr `log`.
+fn last_line(mut log: File) -> Option<String> {
+    use std::io::Seek;
+    let mut text = String::new();
+    log.seek(io::SeekFrom::Start(0)).ok()?;
+    log.read_to_string(&mut text).ok()?;
+    text.lines().rev().find(|l| !l.trim().is_empty()).map(|l| l.trim().to_string())
+}
+
+/// How a database travels on the wire: an 8-byte size and the raw file
+/// (v1/v2), or a framed stream (v3, see `pxh::sync_stream`).
+#[derive(Debug, Clone, Copy)]
+enum Framing {
+    Raw,
+    Framed(pxh::sync_stream::Compression),
+}
+
+/// Incremental sync: JSON line preceding each database sent. `max_id` is the
+/// sender's `MAX(id)` before building it -- the receiver's next watermark --
+/// or None when the receiver must not record one (e.g. `--since` filtering).
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct DeltaHeader {
+    max_id: Option<i64>,
+}
+
+fn write_json_line<W: Write, T: serde::Serialize>(
+    writer: &mut W,
+    value: &T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one JSON line; None at end of stream.
+fn read_json_line<R: io::BufRead, T: serde::de::DeserializeOwned>(
+    reader: &mut R,
+) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(line.trim())?))
 }
 
 // Regex compilation size limits for secret pattern matching
@@ -133,7 +405,19 @@ enum Commands {
     #[clap(about = "generate shell completions")]
     Completions(CompletionsCommand),
     #[clap(about = "show history statistics")]
-    Stats(StatsCommand),
+    Stats(stats::StatsCommand),
+    #[clap(about = "suggest aliases and shell functions for commands you type often")]
+    SuggestAliases(aliases::SuggestAliasesCommand),
+    #[clap(about = "suggest a correction for a failed command, learned from your history")]
+    Fix(fix::FixCommand),
+    #[clap(about = "write a Markdown or HTML summary of a year or month of history")]
+    Report(report::ReportCommand),
+    #[clap(about = "total time spent per project and day, from session activity")]
+    Timesheet(timesheet::TimesheetCommand),
+    #[clap(about = "find common multi-step command sequences and their transition graph")]
+    Workflows(workflows::WorkflowsCommand),
+    #[clap(about = "run a read-only SQL query against the history database")]
+    Sql(sql::SqlCommand),
     #[clap(visible_alias = "cfg", about = "edit or initialize configuration")]
     Config(ConfigCommand),
     #[clap(about = "diagnose common issues and produce diagnostic reports")]
@@ -172,14 +456,115 @@ struct ShowCommand {
     #[clap(
         short = 'S',
         long,
-        conflicts_with_all = ["here", "working_directory"],
         help = "Display only commands from the specified session (\"current\", \"last\", or a session ID)"
     )]
     session: Option<String>,
     #[clap(short = 'F', long, help = "Show only commands that exited with a non-zero status")]
     failed: bool,
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Show only commands started at or after TIME (e.g. 2h, 3d, yesterday, monday, 2025-01-31)"
+    )]
+    since: Option<String>,
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Show only commands started before TIME (same forms as --since)"
+    )]
+    until: Option<String>,
+    #[clap(
+        long,
+        value_name = "HOST",
+        value_delimiter = ',',
+        help = "Show only commands from these hosts; this host's name includes its configured aliases"
+    )]
+    host: Vec<String>,
+    #[clap(
+        long,
+        value_name = "USER",
+        value_delimiter = ',',
+        help = "Show only commands run by these users"
+    )]
+    user: Vec<String>,
+    #[clap(
+        long,
+        value_name = "SHELL",
+        value_delimiter = ',',
+        help = "Show only commands from these shells"
+    )]
+    shell: Vec<String>,
+    #[clap(
+        long,
+        value_name = "N[,M...]",
+        value_delimiter = ',',
+        allow_negative_numbers = true,
+        help = "Show only commands that exited with one of these statuses"
+    )]
+    exit_status: Vec<i64>,
+    #[clap(
+        long,
+        value_name = "DURATION",
+        help = "Show only commands that ran at least this long (e.g. 30, 90s, 5m)"
+    )]
+    min_duration: Option<String>,
+    #[clap(
+        long,
+        value_name = "DURATION",
+        help = "Show only commands that ran at most this long (same forms as --min-duration)"
+    )]
+    max_duration: Option<String>,
+    #[clap(long, value_enum, default_value_t = ShowSort::Time, help = "Order of the displayed entries")]
+    sort: ShowSort,
+    #[clap(
+        short = 'u',
+        long,
+        help = "Collapse identical commands, showing count, first and last seen, and success rate"
+    )]
+    unique: bool,
     #[clap(long, help = "Match patterns in any order instead of sequentially")]
     loosen: bool,
+    #[clap(
+        short = 'A',
+        long,
+        value_name = "N",
+        help = "Show N commands from the same session after each match"
+    )]
+    after_context: Option<usize>,
+    #[clap(
+        short = 'B',
+        long,
+        value_name = "N",
+        help = "Show N commands from the same session before each match"
+    )]
+    before_context: Option<usize>,
+    #[clap(
+        short = 'C',
+        long,
+        value_name = "N",
+        help = "Show N commands from the same session before and after each match"
+    )]
+    context: Option<usize>,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = ShowFormat::Table,
+        conflicts_with_all = ["template", "null"],
+        help = "Output format; json and ndjson use the `pxh export` encoding"
+    )]
+    format: ShowFormat,
+    #[clap(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with = "null",
+        help = "Print each entry with a template, e.g. '{start:%F %T} {duration} {dir} {command}'; {{ and }} print literal braces"
+    )]
+    template: Option<String>,
+    #[clap(
+        long,
+        help = "Print only the commands, each terminated by NUL (for xargs -0 or fzf --read0)"
+    )]
+    null: bool,
     #[clap(
         help = "One or more regular expressions to search through history entries; multiple values joined by `.*\\s.*`"
     )]
@@ -206,9 +591,14 @@ struct ImportCommand {
     dry_run: bool,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct SyncCommand {
-    #[clap(help = "Directory for sync operations (required for directory-based sync)")]
+    #[clap(subcommand)]
+    action: Option<SyncAction>,
+    #[clap(
+        help = "Directory for sync operations, or the name of a [[sync.targets]] entry (defaults to all targets, else [sync] directory in the config)"
+    )]
     dirname: Option<PathBuf>,
     #[clap(
         long,
@@ -216,8 +606,26 @@ struct SyncCommand {
         default_value_t = false
     )]
     export_only: bool,
-    #[clap(long, help = "Remote host to sync with via SSH")]
-    remote: Option<String>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Remote host to sync with via SSH; repeat or comma-separate to sync with several at once"
+    )]
+    remote: Vec<String>,
+    #[clap(
+        short = 'j',
+        long,
+        default_value_t = 4,
+        value_name = "N",
+        help = "Sync with up to N remote hosts at once"
+    )]
+    jobs: usize,
+    #[clap(
+        long,
+        value_name = "SECS",
+        help = "Give up on a remote host that hasn't answered, or stalls mid-transfer, for SECS (default 60 with several hosts)"
+    )]
+    connect_timeout: Option<u64>,
     #[clap(
         long,
         help = "Only send database to remote (no receive)",
@@ -239,8 +647,22 @@ struct SyncCommand {
         help = "SSH command to use for connection (like rsync's -e option)"
     )]
     ssh_cmd: String,
+    #[clap(
+        long,
+        conflicts_with = "ssh_cmd",
+        value_name = "TEMPLATE",
+        help = "Reach --remote with this command instead of ssh, e.g. \"kubectl exec -i {target} --\"; {target} is the --remote value, {command} the pxh command (default: appended as sh -c <command>)"
+    )]
+    transport: Option<String>,
     #[clap(long, default_value = "pxh", help = "Path to pxh binary on the remote host")]
     remote_pxh: String,
+    #[clap(
+        long,
+        requires = "remote",
+        conflicts_with_all = ["send_only", "since", "remote_db"],
+        help = "Import ~/.bash_history and ~/.zsh_history from --remote hosts that don't have pxh; later runs only read new lines"
+    )]
+    histfile_only: bool,
     #[clap(long, hide = true, help = "Internal: run in server mode")]
     server: bool,
     #[clap(
@@ -253,6 +675,42 @@ struct SyncCommand {
     stdin_stdout: bool,
     #[clap(long, help = "Disable automatic filtering of potential secrets during sync import")]
     no_secret_filter: bool,
+    #[clap(
+        long,
+        help = "Show how many rows each [sync.export] rule would withhold or redact, without syncing"
+    )]
+    export_dry_run: bool,
+    #[clap(
+        short = 'n',
+        long,
+        conflicts_with = "export_only",
+        help = "Show what each source would add, without writing anything locally or remotely"
+    )]
+    dry_run: bool,
+    /// One of several hosts synced at once: report through the summary,
+    /// not progress chatter.
+    #[clap(skip)]
+    parallel: bool,
+    /// (directory sync only) Merge the other databases without writing our
+    /// own; a `direction = "receive"` dir target.
+    #[clap(skip)]
+    import_only: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum SyncAction {
+    #[clap(about = "show every known machine, its row count and sync freshness")]
+    Status(SyncStatusCommand),
+}
+
+#[derive(Parser, Debug, Clone)]
+struct SyncStatusCommand {
+    #[clap(
+        long,
+        default_value_t = 14,
+        help = "Flag machines not merged, or without new commands, for this many days"
+    )]
+    stale_days: u32,
 }
 
 #[derive(Parser, Debug)]
@@ -342,6 +800,8 @@ struct ShellConfigCommand {
     shellname: String,
     #[clap(long, help = "Don't bind Ctrl-R to pxh recall")]
     no_ctrl_r: bool,
+    #[clap(long, help = "Define a command-not-found handler that offers learned corrections")]
+    command_not_found_fix: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -356,9 +816,6 @@ struct CompletionsCommand {
     shell: clap_complete::Shell,
 }
 
-#[derive(Parser, Debug)]
-struct StatsCommand {}
-
 #[derive(Parser, Debug)]
 struct ConfigCommand {
     #[clap(long, help = "Print the config file path")]
@@ -469,6 +926,8 @@ impl ShellConfigCommand {
         // Check if ctrl-r should be disabled (via flag or config)
         let config = pxh::recall::Config::load();
         let disable_ctrl_r = self.no_ctrl_r || config.shell.disable_ctrl_r;
+        let command_not_found_fix =
+            self.command_not_found_fix || config.shell.command_not_found_fix;
 
         let contents = match self.shellname.as_str() {
             "zsh" => String::from(include_str!("shell_configs/pxh.zsh")),
@@ -486,11 +945,13 @@ impl ShellConfigCommand {
             }
         };
 
-        // Remove ctrl-r binding section if disabled
-        let contents = if disable_ctrl_r {
+        // Remove the ctrl-r binding section if disabled, and the
+        // command-not-found handler unless enabled
+        let contents = if disable_ctrl_r || !command_not_found_fix {
             contents
                 .lines()
-                .filter(|line| !line.contains("PXH_CTRL_R_BINDING"))
+                .filter(|line| !(disable_ctrl_r && line.contains("PXH_CTRL_R_BINDING")))
+                .filter(|line| command_not_found_fix || !line.contains("PXH_FIX_HANDLER"))
                 .collect::<Vec<_>>()
                 .join("\n")
                 + "\n"
@@ -683,17 +1144,20 @@ impl MaintenanceCommand {
             let mut cleanup_count = 0;
 
             // Define the standard tables (excluding memory database tables)
-            let standard_tables = ["command_history", "settings", "sqlite_sequence"];
+            let standard_tables = [
+                "command_history",
+                "settings",
+                "sqlite_sequence",
+                "command_corrections",
+                "tombstones",
+            ];
 
             // Get all tables from the database
             let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%'
-                                        EXCEPT SELECT name FROM sqlite_master WHERE name IN (?1, ?2, ?3)")?;
+                                        EXCEPT SELECT name FROM sqlite_master WHERE name IN (?1, ?2, ?3, ?4, ?5)")?;
 
             let non_standard_tables: Vec<String> = stmt
-                .query_map(
-                    [&standard_tables[0], &standard_tables[1], &standard_tables[2]],
-                    |row| row.get(0),
-                )?
+                .query_map(standard_tables, |row| row.get(0))?
                 .collect::<Result<Vec<String>, _>>()?;
 
             for table_name in non_standard_tables {
@@ -771,6 +1235,41 @@ impl MaintenanceCommand {
                 println!("No non-standard indexes found to clean up");
             }
 
+            // Clean up non-standard views (except those prefixed with KEEP_)
+            // and restore any missing standard ones.
+            println!("Looking for non-standard views to clean up...");
+            let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type='view'")?;
+            let views: Vec<String> =
+                stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+
+            cleanup_count = 0;
+            for view_name in views {
+                if pxh::STANDARD_VIEWS.contains(&view_name.as_str()) {
+                    continue;
+                }
+                if view_name.starts_with("KEEP_") {
+                    println!("  Keeping user view: {view_name}");
+                    continue;
+                }
+
+                println!("  Dropping non-standard view: {view_name}");
+                let quoted = view_name.replace('"', "\"\"");
+                conn.execute(&format!("DROP VIEW IF EXISTS \"{quoted}\""), [])?;
+                cleanup_count += 1;
+            }
+            // Databases not yet migrated (e.g. foreign files) get the views
+            // from their migration instead; they may lack newer columns.
+            let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+            if version >= 4 {
+                pxh::create_standard_views(conn)?;
+            }
+
+            if cleanup_count > 0 {
+                println!("Cleaned up {cleanup_count} non-standard views");
+            } else {
+                println!("No non-standard views found to clean up");
+            }
+
             // Run ANALYZE to update statistics
             println!("Running ANALYZE...");
             conn.execute("ANALYZE", [])?;
@@ -877,89 +1376,6 @@ impl ConfigCommand {
     }
 }
 
-impl StatsCommand {
-    fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
-        let (total, unique): (i64, i64) = conn.query_row(
-            "SELECT COUNT(*), COUNT(DISTINCT full_command) FROM command_history",
-            [],
-            |r| Ok((r.get(0)?, r.get(1)?)),
-        )?;
-
-        let sessions: i64 =
-            conn.query_row("SELECT COUNT(DISTINCT session_id) FROM command_history", [], |r| {
-                r.get(0)
-            })?;
-
-        let (min_ts, max_ts): (Option<i64>, Option<i64>) = conn.query_row(
-            "SELECT MIN(start_unix_timestamp), MAX(start_unix_timestamp) FROM command_history",
-            [],
-            |r| Ok((r.get(0)?, r.get(1)?)),
-        )?;
-
-        let (success, with_status): (i64, i64) = conn.query_row(
-            "SELECT COUNT(CASE WHEN exit_status = 0 THEN 1 END), COUNT(exit_status) FROM command_history",
-            [],
-            |r| Ok((r.get(0)?, r.get(1)?)),
-        )?;
-
-        println!("Commands:   {total} total, {unique} unique");
-        println!("Sessions:   {sessions}");
-
-        if let (Some(min), Some(max)) = (min_ts, max_ts) {
-            if let (Some(min_date), Some(max_date)) =
-                (Local.timestamp_opt(min, 0).single(), Local.timestamp_opt(max, 0).single())
-            {
-                let days = (max - min) / 86400;
-                println!(
-                    "Period:     {} to {} ({days} days)",
-                    min_date.format("%Y-%m-%d"),
-                    max_date.format("%Y-%m-%d"),
-                );
-            } else {
-                println!("Period:     n/a (invalid timestamps in database)");
-            }
-        }
-
-        if with_status > 0 {
-            let pct = 100.0 * success as f64 / with_status as f64;
-            println!("Success:    {pct:.1}% (of {with_status} commands with exit status)");
-        }
-
-        // Shells
-        let mut stmt = conn.prepare(
-            "SELECT shellname, COUNT(*) as cnt FROM command_history GROUP BY shellname ORDER BY cnt DESC",
-        )?;
-        let shells: Vec<(String, i64)> =
-            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?.collect::<Result<_, _>>()?;
-        if !shells.is_empty() {
-            let shell_str: Vec<String> =
-                shells.iter().map(|(name, cnt)| format!("{name}: {cnt}")).collect();
-            println!("Shells:     {}", shell_str.join("  "));
-        }
-
-        // Top commands
-        let mut stmt = conn.prepare(
-            "SELECT full_command, COUNT(*) as cnt FROM command_history GROUP BY full_command ORDER BY cnt DESC LIMIT 10",
-        )?;
-        let top: Vec<(Vec<u8>, i64)> =
-            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?.collect::<Result<_, _>>()?;
-        if !top.is_empty() {
-            println!("\nTop commands:");
-            for (cmd_bytes, cnt) in &top {
-                let cmd = BString::new(cmd_bytes.clone());
-                let display = if cmd.len() > 60 {
-                    format!("{}...", cmd[..60].to_str_lossy())
-                } else {
-                    cmd.to_str_lossy().into_owned()
-                };
-                println!("  {cnt:>6}  {display}");
-            }
-        }
-
-        Ok(())
-    }
-}
-
 impl ScanCommand {
     fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
         // Handle directory mode
@@ -996,13 +1412,13 @@ impl ScanCommand {
             return Ok(());
         }
 
-        let db_extension = OsStr::new("db");
+        let key = pxh::encryption::SyncKey::from_config(&pxh::recall::config::Config::load())?;
         let mut entries: Vec<_> = Vec::new();
         let mut skipped_entries = 0usize;
         for entry_result in fs::read_dir(dir)? {
             match entry_result {
                 Ok(entry) => {
-                    if entry.path().extension() == Some(db_extension) {
+                    if is_sync_dir_entry(&entry.path()) {
                         entries.push(entry);
                     }
                 }
@@ -1014,7 +1430,7 @@ impl ScanCommand {
         }
 
         if entries.is_empty() {
-            println!("No .db files found in {}", dir.display());
+            println!("No .db, .db.enc or .segments entries found in {}", dir.display());
             return Ok(());
         }
 
@@ -1027,8 +1443,16 @@ impl ScanCommand {
 
         for entry in &entries {
             let path = entry.path();
+            let decrypted = match sync_dir_database(&path, key.as_ref()) {
+                Ok(decrypted) => decrypted,
+                Err(e) => {
+                    eprintln!("Warning: Failed to open {}: {e}", path.display());
+                    skipped_files += 1;
+                    continue;
+                }
+            };
             let conn = match Connection::open_with_flags(
-                &path,
+                decrypted.as_ref().map_or(path.as_path(), |temp| temp.path()),
                 OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
             ) {
                 Ok(c) => c,
@@ -1186,6 +1610,29 @@ pub fn build_secret_patterns(
     Ok((patterns, regex_set))
 }
 
+/// Whether `path` holds a history database in a sync directory: a sync file
+/// or a host's segment directory.
+fn is_sync_dir_entry(path: &Path) -> bool {
+    pxh::encryption::is_sync_file(path) || pxh::segments::is_segment_dir(path)
+}
+
+/// The database to open for an entry of a sync directory: `None` to use the
+/// file itself, a decrypted temp copy when it is encrypted, or a segment
+/// directory's segments combined.
+fn sync_dir_database(
+    path: &Path,
+    key: Option<&pxh::encryption::SyncKey>,
+) -> Result<Option<NamedTempFile>, Box<dyn std::error::Error>> {
+    if pxh::segments::is_segment_dir(path) {
+        return pxh::segments::combine_segments(path, key).map(Some);
+    }
+    if !pxh::encryption::is_encrypted(path) {
+        return Ok(None);
+    }
+    let key = key.ok_or("encrypted; set [sync] key_file in the config to read it")?;
+    pxh::encryption::decrypt_to_temp(key, path).map(Some)
+}
+
 /// The secret filter sync merges use: the built-in "critical" pattern set,
 /// or None when filtering is disabled (or no patterns are compiled in).
 fn sync_secret_filter(
@@ -1198,6 +1645,70 @@ fn sync_secret_filter(
     Ok((!patterns.is_empty()).then_some(regex_set))
 }
 
+/// The `[sync.export]` rules every outgoing database copy is put through.
+fn export_policy() -> Result<pxh::export_policy::ExportPolicy, Box<dyn std::error::Error>> {
+    pxh::export_policy::ExportPolicy::from_config(&pxh::recall::config::Config::load())
+}
+
+/// New commands listed per source by `pxh sync --dry-run`.
+const PREVIEW_SAMPLE_SIZE: usize = 5;
+
+/// A `pxh sync --dry-run` report for one source, one or more lines.
+/// `watermark_move` is the watermark we hold and the one a merge would
+/// record, when the source is tracked by one.
+fn format_merge_preview(
+    source: &str,
+    preview: &pxh::sync::MergePreview,
+    watermark_move: Option<(Option<i64>, Option<i64>)>,
+) -> String {
+    let mut msg = format!(
+        "Would merge from {source}: considered {} rows, {} new, {} filtered as secrets, {} unsealed rows sealed",
+        preview.considered, preview.new_rows, preview.filtered, preview.upgrades
+    );
+    if preview.deleted > 0 {
+        msg.push_str(&format!(", {} removed as deleted elsewhere", preview.deleted));
+    }
+    match watermark_move {
+        Some((from, to)) if from == to => msg.push_str("; watermark unchanged"),
+        Some((from, to)) => {
+            let show = |wm: Option<i64>| wm.map_or("none".to_string(), |wm| wm.to_string());
+            msg.push_str(&format!("; watermark {} -> {}", show(from), show(to)));
+        }
+        None => {}
+    }
+    msg.push('\n');
+    for command in &preview.sample {
+        msg.push_str(&format!("  + {command}\n"));
+    }
+    if preview.new_rows > preview.sample.len() as i64 {
+        msg.push_str(&format!(
+            "  ... and {} more\n",
+            preview.new_rows - preview.sample.len() as i64
+        ));
+    }
+    msg
+}
+
+/// The `done, ...` line printed after merging one sync source.
+fn merge_summary(stats: &pxh::sync::MergeStats) -> String {
+    let mut msg = if stats.filtered > 0 {
+        format!(
+            "done, considered {} rows, added {}, filtered {}",
+            stats.considered, stats.added, stats.filtered
+        )
+    } else {
+        format!("done, considered {} rows and added {}", stats.considered, stats.added)
+    };
+    if stats.deleted > 0 {
+        msg.push_str(&format!(", removed {} deleted elsewhere", stats.deleted));
+    }
+    msg
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
 /// True when both paths refer to the same file (by device + inode), so
 /// symlinked or hard-linked aliases of the same database are caught too.
 fn is_same_file(a: &Path, b: &Path) -> bool {
@@ -1433,7 +1944,7 @@ fn scrub_from_database(
     rowids_to_delete.dedup();
 
     for rowid in &rowids_to_delete {
-        conn.execute("DELETE FROM command_history WHERE rowid = ?", [rowid])?;
+        pxh::sync::delete_with_tombstones(conn, "rowid = ?", [rowid])?;
     }
 
     Ok(rowids_to_delete.len())
@@ -1461,17 +1972,124 @@ fn scrub_from_histfile(
 // Merge all (hopefully) pxh files ending in .db in the specified path
 // into the current database, then write an output with our hostname.
 
-impl SyncCommand {
-    /// Create a temporary database file with optional --since filtering
-    fn create_filtered_db_copy(
-        &self,
-        conn: &mut Connection,
-    ) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
-        // Create a temporary file with the database
-        let temp_file = NamedTempFile::new()?;
+impl SyncStatusCommand {
+    fn go(&self, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let machines = pxh::sync::machine_statuses(conn)?;
+        if machines.is_empty() {
+            println!("No history yet.");
+            return Ok(());
+        }
+        let own = pxh::read_local_machine_id(conn)
+            .or_else(|| pxh::recall::config::Config::load().host.machine_id);
+        let now = unix_now();
+        let stale_secs = self.stale_days as i64 * 86400;
+        let date = |ts: Option<i64>| {
+            ts.and_then(|ts| Local.timestamp_opt(ts, 0).single())
+                .map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+        };
+
+        let rows: Vec<[String; 7]> = machines
+            .iter()
+            .map(|m| {
+                let id = match m.machine_id {
+                    Some(mid) if Some(mid) == own => format!("{mid} (this machine)"),
+                    Some(mid) => mid.to_string(),
+                    None => "(none)".to_string(),
+                };
+                [
+                    id,
+                    m.hostnames.join(", "),
+                    m.rows.to_string(),
+                    date(m.last_command),
+                    m.watermark.map_or("-".to_string(), |w| w.to_string()),
+                    date(m.last_merged),
+                    m.via.clone().unwrap_or_else(|| "-".to_string()),
+                ]
+            })
+            .collect();
+        let header =
+            ["MACHINE", "HOSTS", "ROWS", "LAST COMMAND", "WATERMARK", "LAST MERGED", "VIA"];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| rows.iter().map(|r| r[i].len()).chain([header[i].len()]).max().unwrap_or(0))
+            .collect();
+        let print_row = |cells: &[&str]| {
+            let line: Vec<String> =
+                cells
+                    .iter()
+                    .zip(&widths)
+                    .enumerate()
+                    .map(|(i, (cell, &w))| {
+                        if i == 2 || i == 4 { format!("{cell:>w$}") } else { format!("{cell:<w$}") }
+                    })
+                    .collect();
+            println!("{}", line.join("  ").trim_end());
+        };
+        print_row(&header);
+        for row in &rows {
+            print_row(&row.each_ref().map(String::as_str));
+        }
+
+        let mut warnings = Vec::new();
+        for m in &machines {
+            let Some(mid) = m.machine_id else {
+                warnings.push(format!(
+                    "{} rows have no machine id (recorded or imported before machine ids); they can't be attributed to a machine",
+                    m.rows
+                ));
+                continue;
+            };
+            if let Some(sources) = &m.duplicate {
+                warnings.push(format!("{mid}: machine id claimed by both {sources}"));
+            }
+            if Some(mid) == own {
+                continue;
+            }
+            if let Some(merged) = m.last_merged
+                && now - merged > stale_secs
+            {
+                warnings
+                    .push(format!("{mid}: stale, last merged {} days ago", (now - merged) / 86400));
+            } else if m.last_merged.is_some()
+                && let Some(last) = m.last_command
+                && now - last > stale_secs
+            {
+                warnings.push(format!(
+                    "{mid}: stale, merged recently but no new commands in {} days",
+                    (now - last) / 86400
+                ));
+            }
+        }
+        if !warnings.is_empty() {
+            println!();
+            for warning in warnings {
+                println!("Warning: {warning}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyncCommand {
+    /// Create a temporary database file with optional --since filtering: a
+    /// complete copy, or given a peer's watermark and unsealed start, only
+    /// what that peer lacks.
+    fn create_filtered_db_copy(
+        &self,
+        conn: &mut Connection,
+        delta_for: Option<(i64, Option<i64>)>,
+    ) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+        // Create a temporary file with the database
+        let temp_file = NamedTempFile::new()?;
 
-        // Use VACUUM INTO to create a complete copy
-        conn.execute("VACUUM INTO ?", (temp_file.path().to_str(),))?;
+        match delta_for {
+            Some((watermark, unsealed_since)) => {
+                pxh::sync::write_delta_database(conn, temp_file.path(), watermark, unsealed_since)?;
+            }
+            // Use VACUUM INTO to create a complete copy
+            None => {
+                conn.execute("VACUUM INTO ?", (temp_file.path().to_str(),))?;
+            }
+        }
 
         if let Some(days) = self.since {
             // Open the temp database and delete old records
@@ -1496,23 +2114,178 @@ impl SyncCommand {
             drop(temp_conn); // Close the connection to flush all changes
         }
 
+        export_policy()?.apply_to_file(temp_file.path(), unix_now())?;
         Ok(temp_file)
     }
     fn go(&self, mut conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(SyncAction::Status(status)) = &self.action {
+            return status.go(&conn);
+        }
+        let config = pxh::recall::config::Config::load();
+        if self.export_dry_run {
+            return Self::show_export_policy(&mut conn, &config);
+        }
+        match self.selected_targets(&config)? {
+            Some(targets) => self.sync_targets(&mut conn, &targets),
+            None => self.sync_one(&mut conn),
+        }
+    }
+
+    /// The `[[sync.targets]]` to run: the one named by the positional
+    /// argument, or all of them when there are no arguments. `None` means
+    /// the flags alone describe the sync.
+    fn selected_targets(
+        &self,
+        config: &pxh::recall::config::Config,
+    ) -> Result<Option<Vec<SyncTarget>>, Box<dyn std::error::Error>> {
+        if self.server || self.stdin_stdout || !self.remote.is_empty() {
+            return Ok(None);
+        }
+        let targets = &config.sync.targets;
+        match &self.dirname {
+            Some(name) => match targets.iter().find(|t| Path::new(&t.name) == name) {
+                Some(target) => Ok(Some(vec![target.clone()])),
+                // Likely a mistyped target name; don't create a sync
+                // directory of that name instead.
+                None if !targets.is_empty() && !name.exists() && name.components().count() == 1 => {
+                    Err(format!(
+                        "no sync target named {} (to sync a new directory, write ./{})",
+                        name.display(),
+                        name.display()
+                    )
+                    .into())
+                }
+                None => Ok(None),
+            },
+            None if targets.is_empty() => Ok(None),
+            None => Ok(Some(targets.clone())),
+        }
+    }
+
+    /// The sync command one target stands for; options the target leaves
+    /// unset come from our own flags.
+    fn for_target(&self, target: &SyncTarget) -> Result<SyncCommand, Box<dyn std::error::Error>> {
+        let (remote, dirname) = match target.kind {
+            SyncTargetKind::Ssh => {
+                let host = target.host.clone().ok_or("ssh target has no host")?;
+                (Some(host), None)
+            }
+            SyncTargetKind::Dir => {
+                let path = target.path.as_deref().ok_or("dir target has no path")?;
+                (None, Some(pxh::recall::config::expand_home(path)))
+            }
+        };
+        let (send_only, receive_only) = match target.direction {
+            SyncDirection::Both => (self.send_only, self.receive_only),
+            SyncDirection::Send => (true, false),
+            SyncDirection::Receive => (false, true),
+        };
+        // Directory sync has no sending side to turn off; the same choices
+        // are exporting only or importing only.
+        let (send_only, receive_only, export_only, import_only) = match target.kind {
+            SyncTargetKind::Ssh => (send_only, receive_only, self.export_only, false),
+            SyncTargetKind::Dir => (false, false, self.export_only || send_only, receive_only),
+        };
+        Ok(SyncCommand {
+            action: None,
+            dirname,
+            export_only,
+            remote: remote.into_iter().collect(),
+            jobs: self.jobs,
+            connect_timeout: self.connect_timeout,
+            send_only,
+            receive_only,
+            remote_db: target
+                .remote_db
+                .as_deref()
+                .map(pxh::recall::config::expand_home)
+                .or_else(|| self.remote_db.clone()),
+            ssh_cmd: target.ssh_cmd.clone().unwrap_or_else(|| self.ssh_cmd.clone()),
+            transport: target.transport.clone().or_else(|| self.transport.clone()),
+            remote_pxh: target.remote_pxh.clone().unwrap_or_else(|| self.remote_pxh.clone()),
+            histfile_only: false,
+            server: false,
+            since: target.since.or(self.since),
+            stdin_stdout: false,
+            no_secret_filter: target.secret_filter.map_or(self.no_secret_filter, |on| !on),
+            export_dry_run: false,
+            dry_run: self.dry_run,
+            parallel: false,
+            import_only,
+        })
+    }
+
+    /// Sync each target in turn, carrying on past failures, then summarize.
+    /// Fails if any target did.
+    fn sync_targets(
+        &self,
+        conn: &mut Connection,
+        targets: &[SyncTarget],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        ignore_sigpipe();
+        let mut results = Vec::with_capacity(targets.len());
+        for target in targets {
+            println!("==> {}", target.name);
+            let result = self.for_target(target).and_then(|cmd| cmd.sync_one(conn));
+            if let Err(e) = &result {
+                eprintln!("{}: {e}", target.name);
+            }
+            results.push(result);
+        }
+
+        println!("\nSync summary:");
+        let width = targets.iter().map(|t| t.name.len()).max().unwrap_or(0);
+        for (target, result) in targets.iter().zip(&results) {
+            match result {
+                Ok(()) => println!("  {:width$}  ok", target.name),
+                Err(e) => println!("  {:width$}  FAILED: {e}", target.name),
+            }
+        }
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        if failed > 0 {
+            return Err(format!("{failed} of {} sync targets failed", targets.len()).into());
+        }
+        Ok(())
+    }
+
+    /// Report what the `[sync.export]` rules would keep back from the live
+    /// database.
+    fn show_export_policy(
+        conn: &mut Connection,
+        config: &pxh::recall::config::Config,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let policy = pxh::export_policy::ExportPolicy::from_config(config)?;
+        if policy.is_empty() {
+            println!("No [sync.export] rules configured; all history is exported.");
+            return Ok(());
+        }
+        let total: i64 =
+            conn.query_row("SELECT COUNT(*) FROM command_history", [], |r| r.get(0))?;
+        let report = policy.apply(conn, unix_now(), true)?;
+        println!("Export rules would withhold {} of {total} rows:", report.total_withheld());
+        for (rule, count) in &report.withheld {
+            println!("  {rule:<20} {count}");
+        }
+        println!("and redact {} more.", report.redacted);
+        Ok(())
+    }
+
+    /// Sync with the single peer or directory the flags describe.
+    fn sync_one(&self, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
         // Validate that --send-only and --receive-only are only used with remote sync
-        if (self.send_only || self.receive_only) && self.remote.is_none() && !self.stdin_stdout {
+        if (self.send_only || self.receive_only) && self.remote.is_empty() && !self.stdin_stdout {
             return Err(Box::from(
                 "--send-only and --receive-only flags require --remote or --stdin-stdout to be specified",
             ));
         }
 
         // Validate that --remote and directory are not used together
-        if self.remote.is_some() && self.dirname.is_some() {
+        if !self.remote.is_empty() && self.dirname.is_some() {
             return Err(Box::from("Cannot specify both --remote and a directory path"));
         }
 
         // Validate that --since is only used with remote sync
-        if self.since.is_some() && self.remote.is_none() && !self.stdin_stdout && !self.server {
+        if self.since.is_some() && self.remote.is_empty() && !self.stdin_stdout && !self.server {
             return Err(Box::from(
                 "--since requires --remote or --stdin-stdout (not supported in directory sync mode)",
             ));
@@ -1520,35 +2293,50 @@ impl SyncCommand {
 
         // If in server mode, handle sync protocol
         if self.server {
-            return self.handle_server_mode(&mut conn);
+            return self.handle_server_mode(conn);
+        }
+
+        if self.remote.len() > 1 {
+            if self.stdin_stdout {
+                return Err(Box::from("--stdin-stdout syncs with a single peer"));
+            }
+            return self.sync_remotes(conn);
         }
 
         // Handle remote sync if specified (either SSH or stdin/stdout)
-        if self.stdin_stdout || self.remote.is_some() {
-            return self.handle_remote_sync(&mut conn);
+        if self.stdin_stdout || !self.remote.is_empty() {
+            return self.handle_remote_sync(conn).map(|_| ());
         }
 
         // Original directory-based sync behavior requires dirname
-        let dirname =
-            self.dirname.as_ref().ok_or("Directory path is required for directory-based sync")?;
+        let config = pxh::recall::config::Config::load();
+        let dirname = self.dirname.clone().or_else(|| config.sync.directory_path()).ok_or(
+            "Directory path is required for directory-based sync (or set [sync] directory in config)",
+        )?;
+        let key = pxh::encryption::SyncKey::from_config(&config)?;
 
         if !dirname.exists() {
-            fs::create_dir(dirname)?;
+            if self.dry_run {
+                println!("Dry-run: {} does not exist yet; nothing to merge.", dirname.display());
+                return Ok(());
+            }
+            fs::create_dir(&dirname)?;
         }
-        let mut output_path = dirname.clone();
-        let config = pxh::recall::config::Config::load();
-        let hostname = pxh::resolve_hostname(&config, &conn);
-        output_path.push(format!("{}.db", hostname.to_str_lossy()));
+        let hostname = pxh::resolve_hostname(&config, conn).to_str_lossy().into_owned();
+        let plaintext_path = dirname.join(format!("{hostname}.db"));
+        let encrypted_path = pxh::encryption::encrypted_path(&dirname, &hostname);
+        let output_path = if key.is_some() { &encrypted_path } else { &plaintext_path };
         // TODO: vacuum seems to want a plain text string path, unlike
         // ATTACH below which takes an os_str as bytes, so we can't
         // use BString to get a vec<u8>.  Look into why this is and if
         // there is a workaround.
         let output_path_str =
             output_path.to_str().ok_or("Unable to represent output filename as a string")?;
+        let format = config.sync.format;
+        let own_segments = pxh::segments::segment_dir(&dirname, &hostname);
 
         if !self.export_only {
-            let entries = fs::read_dir(dirname)?;
-            let db_extension = OsStr::new("db");
+            let entries = fs::read_dir(&dirname)?;
             let secret_filter = sync_secret_filter(!self.no_secret_filter)?;
             // Track machine_ids seen this run so we can warn if two source
             // databases claim the same identity (cloned install, misconfig).
@@ -1556,28 +2344,145 @@ impl SyncCommand {
                 std::collections::HashMap::new();
             for entry in entries {
                 let path = entry?.path();
-                if path.extension() == Some(db_extension) && output_path != path {
-                    // Skip the live database itself (e.g. it lives inside the
-                    // sync dir): merging a database into itself is a no-op,
-                    // and ATTACHing our own file would self-deadlock now that
-                    // merge transactions take write locks up front.
-                    if conn.path().is_some_and(|own| is_same_file(Path::new(own), &path)) {
+                if path == own_segments {
+                    continue;
+                }
+                if pxh::segments::is_segment_dir(&path) {
+                    Self::sync_from_segment_dir(
+                        conn,
+                        &path,
+                        key.as_ref(),
+                        secret_filter.as_ref(),
+                        &mut seen_machine_ids,
+                        self.dry_run,
+                    )?;
+                    continue;
+                }
+                // Our own exports, in either form, hold nothing new. Hosts
+                // writing segments too are read from those.
+                if !pxh::encryption::is_sync_file(&path)
+                    || path == plaintext_path
+                    || path == encrypted_path
+                    || pxh::segments::segments_for_sync_file(&path).is_some()
+                {
+                    continue;
+                }
+                // Skip the live database itself (e.g. it lives inside the
+                // sync dir): merging a database into itself is a no-op,
+                // and ATTACHing our own file would self-deadlock now that
+                // merge transactions take write locks up front.
+                if conn.path().is_some_and(|own| is_same_file(Path::new(own), &path)) {
+                    continue;
+                }
+                if pxh::encryption::is_encrypted(&path) {
+                    let Some(key) = &key else {
+                        eprintln!(
+                            "Warning: Skipping {} (encrypted; set [sync] key_file in the config to read it)",
+                            path.display()
+                        );
                         continue;
-                    }
+                    };
+                    // Still uploading, truncated, or another key: skip it
+                    // rather than lose this machine's export too.
+                    let decrypted = match pxh::encryption::decrypt_to_temp(key, &path) {
+                        Ok(decrypted) => decrypted,
+                        Err(e) => {
+                            eprintln!("Warning: Skipping {}: {e}", path.display());
+                            continue;
+                        }
+                    };
                     Self::sync_from_directory_source(
-                        &mut conn,
+                        conn,
                         path,
+                        decrypted.path(),
                         secret_filter.as_ref(),
                         &mut seen_machine_ids,
+                        self.dry_run,
+                    )?;
+                } else {
+                    Self::sync_from_directory_source(
+                        conn,
+                        path.clone(),
+                        &path,
+                        secret_filter.as_ref(),
+                        &mut seen_machine_ids,
+                        self.dry_run,
                     )?;
                 }
             }
         }
 
-        // Create database copy without filtering (--since only applies to remote sync)
-        let temp_file = NamedTempFile::new_in(dirname.as_path())?;
-        conn.execute("VACUUM INTO ?", (temp_file.path().to_str(),))?;
-        temp_file.persist(output_path_str)?;
+        if self.dry_run {
+            let written = if format == SyncFormat::Segments { &own_segments } else { output_path };
+            println!("Dry-run: nothing merged, {} not written.", written.display());
+            return Ok(());
+        }
+        if self.import_only {
+            return Ok(());
+        }
+
+        let policy = export_policy()?;
+        if format != SyncFormat::Db {
+            let segment = pxh::segments::write_segment(
+                conn,
+                &own_segments,
+                &hostname,
+                key.as_ref(),
+                &policy,
+                unix_now(),
+            )?;
+            match segment {
+                Some(segment) if segment.snapshot => println!(
+                    "Wrote snapshot segment {} ({} rows) to {}",
+                    segment.name,
+                    segment.rows,
+                    own_segments.display()
+                ),
+                Some(segment) => println!(
+                    "Wrote segment {} ({} rows) to {}",
+                    segment.name,
+                    segment.rows,
+                    own_segments.display()
+                ),
+                None => println!("No new history for {}", own_segments.display()),
+            }
+            if format == SyncFormat::Segments {
+                // Readers prefer the segments; a stale whole copy would
+                // only hold history back from older pxh versions.
+                for stale in [&plaintext_path, &encrypted_path] {
+                    if stale.exists() {
+                        fs::remove_file(stale)?;
+                        println!("Removed {} (superseded by segments)", stale.display());
+                    }
+                }
+                return Ok(());
+            }
+        }
+
+        // Create database copy (--since only applies to remote sync). With
+        // encryption or [sync.export] rules, the unfinished copy stays in the
+        // system temp dir, never the (possibly cloud-synced) sync dir.
+        if let Some(key) = &key {
+            let temp_file = NamedTempFile::new()?;
+            conn.execute("VACUUM INTO ?", (temp_file.path().to_str(),))?;
+            policy.apply_to_file(temp_file.path(), unix_now())?;
+            pxh::encryption::encrypt_file(key, temp_file.path(), &encrypted_path)?;
+            if pxh::encryption::is_plaintext_database(&plaintext_path) {
+                fs::remove_file(&plaintext_path)?;
+                println!("Removed unencrypted {}", plaintext_path.display());
+            }
+        } else if !policy.is_empty() {
+            let staging = NamedTempFile::new()?;
+            conn.execute("VACUUM INTO ?", (staging.path().to_str(),))?;
+            policy.apply_to_file(staging.path(), unix_now())?;
+            let mut temp_file = NamedTempFile::new_in(dirname.as_path())?;
+            io::copy(&mut fs::File::open(staging.path())?, &mut temp_file)?;
+            temp_file.persist(output_path_str)?;
+        } else {
+            let temp_file = NamedTempFile::new_in(dirname.as_path())?;
+            conn.execute("VACUUM INTO ?", (temp_file.path().to_str(),))?;
+            temp_file.persist(output_path_str)?;
+        }
         if self.export_only {
             println!("Backed-up database to {output_path_str}");
         } else {
@@ -1587,7 +2492,304 @@ impl SyncCommand {
         Ok(())
     }
 
-    fn handle_remote_sync(&self, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+    /// Sync with the one remote peer; returns what merging its history did
+    /// (nothing on a dry run or when only sending).
+    fn handle_remote_sync(
+        &self,
+        conn: &mut Connection,
+    ) -> Result<Option<pxh::sync::MergeStats>, Box<dyn std::error::Error>> {
+        if let Some(host) = self.remote_host()
+            && !self.parallel
+        {
+            println!("Syncing with {host}...");
+        }
+        if self.histfile_only {
+            return self.harvest_histfiles(conn).map(Some);
+        }
+
+        let mut session = self.run_remote_sync(conn, SyncProtocol::Framed)?;
+        if session.is_none() {
+            if self.stdin_stdout {
+                return Err(Box::from("Peer does not support the v3 sync protocol"));
+            }
+            if !self.parallel {
+                eprintln!("Remote pxh predates framed sync streams; retrying with the v2 protocol");
+            }
+            session = self.run_remote_sync(conn, SyncProtocol::Incremental)?;
+        }
+        if session.is_none() && self.dry_run && self.send_only {
+            // A legacy peer would get a full copy; nothing to ask it for.
+            self.report_outgoing(conn, None)?;
+            session = Some(None);
+        }
+        if session.is_none() {
+            if !self.parallel {
+                eprintln!("Remote pxh predates incremental sync; retrying with full snapshots");
+            }
+            session = self.run_remote_sync(conn, SyncProtocol::Legacy)?;
+        }
+
+        if self.parallel {
+            // Reported in the summary instead.
+        } else if self.dry_run {
+            eprintln!("Dry-run: nothing was merged or sent.");
+        } else if !self.stdin_stdout {
+            println!("Sync completed successfully");
+        }
+
+        Ok(session.flatten())
+    }
+
+    fn remote_host(&self) -> Option<&str> {
+        self.remote.first().map(String::as_str)
+    }
+
+    /// What reaches the remote, for `pxh sync status`: `ssh`, or the
+    /// `--transport` program (`kubectl`, `docker`, ...).
+    fn transport_name(&self) -> String {
+        self.transport.as_deref().map_or_else(
+            || "ssh".to_string(),
+            |template| pxh::helpers::parse_ssh_command(template.trim()).0,
+        )
+    }
+
+    /// The program and arguments running `remote_command` on `host`: the ssh
+    /// command, or the `--transport` template.
+    fn remote_invocation(
+        &self,
+        host: &str,
+        remote_command: String,
+    ) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+        Ok(match &self.transport {
+            Some(template) => pxh::helpers::transport_command(template, host, &remote_command)?,
+            None => {
+                // Parse SSH command and arguments
+                let (ssh_cmd, mut ssh_args) = pxh::helpers::parse_ssh_command(&self.ssh_cmd);
+                ssh_args.extend([host.to_string(), remote_command]);
+                (ssh_cmd, ssh_args)
+            }
+        })
+    }
+
+    /// `--histfile-only`: import the lines of the remote's shell history
+    /// files added since the last run (see `pxh::harvest`), tagged with the
+    /// remote's hostname and user and put through the secret filter.
+    fn harvest_histfiles(
+        &self,
+        conn: &mut Connection,
+    ) -> Result<pxh::sync::MergeStats, Box<dyn std::error::Error>> {
+        use pxh::harvest::{self, HISTFILES};
+
+        let host = self.remote_host().ok_or("--histfile-only requires --remote")?;
+        let index = |shell: &str| HISTFILES.iter().position(|(s, _)| *s == shell);
+        let states: Vec<_> =
+            HISTFILES.iter().map(|(shell, _)| harvest::load_state(conn, host, shell)).collect();
+        let mut starts: Vec<u64> = states.iter().map(harvest::HarvestState::fetch_from).collect();
+        let mut listing = self.fetch_histfiles(host, &starts)?;
+        let mut rewritten = false;
+        for file in &listing.files {
+            if let Some(i) = index(&file.shell)
+                && harvest::take_new(&states[i], file).is_none()
+            {
+                starts[i] = 0;
+                rewritten = true;
+            }
+        }
+        if rewritten {
+            listing = self.fetch_histfiles(host, &starts)?;
+        }
+
+        let username = listing.username.clone().unwrap_or_else(|| BString::from("unknown"));
+        let hostname = listing.hostname.clone().unwrap_or_else(|| BString::from(host));
+        let secret_filter = sync_secret_filter(!self.no_secret_filter)?;
+        let mut stats = pxh::sync::MergeStats {
+            considered: 0,
+            added: 0,
+            filtered: 0,
+            deleted: 0,
+            new_max_id: None,
+        };
+        let mut invocations = vec![];
+        let mut updates = vec![];
+        for file in &listing.files {
+            let Some(i) = index(&file.shell) else { continue };
+            let (range, next) = harvest::take_new(&states[i], file)
+                .ok_or_else(|| format!("history on {host} changed while being read; try again"))?;
+            // A rewritten file whose anchor is gone is read from the start.
+            let reread = range.start == 0 && states[i].offset > 0;
+            let source = format!("{host}:~/{}", HISTFILES[i].1);
+            let session_id = harvest::session_id(host, &file.shell);
+            let mut parsed = harvest::parse_invocations(
+                &file.shell,
+                &file.data[range],
+                &source,
+                &hostname,
+                &username,
+                session_id,
+            );
+            stats.considered += parsed.len() as i64;
+            if reread {
+                parsed = harvest::drop_reimported(conn, &hostname, &username, session_id, parsed)?;
+            }
+            for invocation in parsed {
+                if secret_filter.as_ref().is_some_and(|f| f.is_match(&invocation.command)) {
+                    stats.filtered += 1;
+                } else {
+                    invocations.push(invocation);
+                }
+            }
+            updates.push((HISTFILES[i].0, next));
+        }
+
+        let _merging = merge_lock();
+        let count = |conn: &Connection| -> rusqlite::Result<i64> {
+            conn.query_row("SELECT COUNT(*) FROM command_history", [], |r| r.get(0))
+        };
+        let before = count(conn)?;
+        let tx = conn.transaction()?;
+        for invocation in &invocations {
+            invocation.insert(&tx)?;
+        }
+        stats.added = count(&tx)? - before;
+        let summary = format!(
+            "{} of {} new commands from {host} ({} filtered as secrets)",
+            stats.added, stats.considered, stats.filtered
+        );
+        if self.dry_run {
+            tx.rollback()?;
+            if !self.parallel {
+                println!("Dry-run: would import {summary}");
+            }
+            return Ok(stats);
+        }
+        for (shell, next) in &updates {
+            harvest::save_state(&tx, host, shell, next)?;
+        }
+        tx.commit()?;
+        if !self.parallel {
+            println!("Imported {summary}");
+        }
+        Ok(stats)
+    }
+
+    /// Run `pxh::harvest::remote_script` on `host` and parse what it prints.
+    fn fetch_histfiles(
+        &self,
+        host: &str,
+        starts: &[u64],
+    ) -> Result<pxh::harvest::Harvest, Box<dyn std::error::Error>> {
+        let (program, args) = self.remote_invocation(host, pxh::harvest::remote_script(starts))?;
+        let mut cmd = std::process::Command::new(&program);
+        cmd.args(&args).stdin(std::process::Stdio::null()).stdout(std::process::Stdio::piped());
+        let mut remote_stderr = None;
+        if self.parallel {
+            let log = tempfile::tempfile()?;
+            cmd.stderr(log.try_clone()?);
+            remote_stderr = Some(log);
+        }
+        let mut child = cmd.spawn().map_err(|e| format!("Failed to spawn {program}: {e}"))?;
+        let stdout = child.stdout.take().ok_or("Failed to get stdout from SSH process")?;
+
+        let watchdog = self.io_timeout().map(|t| Watchdog::arm(child.id(), t));
+        let mut stdout: Box<dyn Read> = match &watchdog {
+            Some(watchdog) => Box::new(watchdog.watch(stdout)),
+            None => Box::new(stdout),
+        };
+        let mut output = Vec::new();
+        let read = stdout.read_to_end(&mut output);
+        let status = child.wait()?;
+        if let Some(failure) = watchdog.as_ref().and_then(Watchdog::failure) {
+            return Err(failure.into());
+        }
+        read?;
+        if !status.success() {
+            let error = format!("{program} exited with {status}");
+            return Err(match remote_stderr.and_then(last_line) {
+                Some(remote) => format!("{error} ({remote})"),
+                None => error,
+            }
+            .into());
+        }
+        Ok(pxh::harvest::parse_output(&output)?)
+    }
+
+    /// How long a read from or write to an SSH peer may block, if limited.
+    fn io_timeout(&self) -> Option<Duration> {
+        self.connect_timeout.or(self.parallel.then_some(60)).map(Duration::from_secs)
+    }
+
+    /// Sync with every `--remote` host, up to `--jobs` at once, each over its
+    /// own connection to the database; merges still happen one at a time
+    /// (see `MERGE_LOCK`). Reports each host as it finishes, then a summary.
+    /// Fails if any host did.
+    fn sync_remotes(&self, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+        ignore_sigpipe();
+        let db_path = conn
+            .path()
+            .filter(|p| !p.is_empty())
+            .ok_or("syncing several hosts at once needs an on-disk database")?;
+        let workers = self.jobs.clamp(1, self.remote.len());
+        let connections = (0..workers)
+            .map(|_| pxh::sqlite_connection(&Some(PathBuf::from(db_path))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let hosts = &self.remote;
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let mut results: Vec<Option<HostResult>> = hosts.iter().map(|_| None).collect();
+        std::thread::scope(|scope| {
+            let (done, finished) = std::sync::mpsc::channel();
+            for mut worker_conn in connections {
+                let (done, next) = (done.clone(), &next);
+                scope.spawn(move || {
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        let Some(host) = hosts.get(i) else { break };
+                        let cmd = SyncCommand {
+                            remote: vec![host.clone()],
+                            parallel: true,
+                            ..self.clone()
+                        };
+                        let start = std::time::Instant::now();
+                        let outcome =
+                            cmd.handle_remote_sync(&mut worker_conn).map_err(|e| e.to_string());
+                        if done.send((i, HostResult { outcome, elapsed: start.elapsed() })).is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(done);
+            for (count, (i, result)) in finished.iter().enumerate() {
+                let status = match &result.outcome {
+                    Ok(Some(stats)) => format!("ok, added {}", stats.added),
+                    Ok(None) => "ok".to_string(),
+                    Err(e) => format!("FAILED: {e}"),
+                };
+                println!("[{}/{}] {}: {status}", count + 1, hosts.len(), hosts[i]);
+                results[i] = Some(result);
+            }
+        });
+
+        let results: Vec<HostResult> = results.into_iter().flatten().collect();
+        print!("{}", format_host_summary(hosts, &results));
+        let failed = results.iter().filter(|r| r.outcome.is_err()).count();
+        if failed > 0 {
+            return Err(format!("{failed} of {} remote hosts failed", hosts.len()).into());
+        }
+        Ok(())
+    }
+
+    /// One sync session with the peer. Incremental sessions exchange machine
+    /// ids and watermarks, then send only the rows the other side lacks.
+    /// Returns what merging the peer's history did, or None if the peer turns
+    /// out not to support `protocol` (an older pxh rejects the mode and hangs
+    /// up), so the caller can retry with an older one.
+    fn run_remote_sync(
+        &self,
+        conn: &mut Connection,
+        protocol: SyncProtocol,
+    ) -> Result<Option<Option<pxh::sync::MergeStats>>, Box<dyn std::error::Error>> {
         // Determine sync mode
         let mode = if self.send_only {
             "send"
@@ -1596,18 +2798,18 @@ impl SyncCommand {
         } else {
             "bidirectional"
         };
+        // A dry run only listens: the peer sends and merges nothing, and we
+        // preview instead of merging.
+        let wire_mode = if self.dry_run { "receive" } else { mode };
 
+        let mut remote_stderr: Option<File> = None;
         let mut child = if self.stdin_stdout {
             // For stdin/stdout mode (testing), we're already connected
             // Create a dummy child process that uses stdin/stdout
             None
         } else {
             // SSH mode
-            let host = self.remote.as_ref().ok_or("Remote host required for SSH sync")?;
-            println!("Syncing with {host}...");
-
-            // Parse SSH command and arguments
-            let (ssh_cmd, ssh_args) = pxh::helpers::parse_ssh_command(&self.ssh_cmd);
+            let host = self.remote_host().ok_or("Remote host required for SSH sync")?;
 
             let remote_db_path = self
                 .remote_db
@@ -1620,19 +2822,30 @@ impl SyncCommand {
             let remote_command =
                 pxh::helpers::build_remote_pxh_command(&self.remote_pxh, &pxh_args);
 
-            let mut cmd = std::process::Command::new(&ssh_cmd);
-            cmd.args(&ssh_args)
-                .arg(host)
-                .arg(&remote_command)
+            let (program, args) = self.remote_invocation(host, remote_command)?;
+            let mut cmd = std::process::Command::new(&program);
+            cmd.args(&args)
                 .stdin(std::process::Stdio::piped())
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::inherit()); // Map stderr to our stderr
+                .stdout(std::process::Stdio::piped());
+            if self.parallel {
+                // Keep it for the error message instead of interleaving it
+                // with other hosts' output.
+                let log = tempfile::tempfile()?;
+                cmd.stderr(log.try_clone()?);
+                remote_stderr = Some(log);
+            } else {
+                cmd.stderr(std::process::Stdio::inherit()); // Map stderr to our stderr
+            }
 
-            Some(cmd.spawn().map_err(|e| format!("Failed to spawn SSH command: {e}"))?)
+            Some(cmd.spawn().map_err(|e| format!("Failed to spawn {program}: {e}"))?)
         };
+        let watchdog = child
+            .as_ref()
+            .zip(self.io_timeout())
+            .map(|(child, timeout)| Watchdog::arm(child.id(), timeout));
 
         // Handle stdin/stdout directly or through SSH child process
-        let (mut stdin_writer, mut stdout_reader) = if self.stdin_stdout {
+        let (stdin_writer, stdout_reader) = if self.stdin_stdout {
             // Use actual stdin/stdout
             (
                 Box::new(std::io::stdout()) as Box<dyn Write>,
@@ -1643,50 +2856,170 @@ impl SyncCommand {
             if let Some(ref mut child) = child {
                 let stdin = child.stdin.take().ok_or("Failed to get stdin from SSH process")?;
                 let stdout = child.stdout.take().ok_or("Failed to get stdout from SSH process")?;
-                (Box::new(stdin) as Box<dyn Write>, Box::new(stdout) as Box<dyn Read>)
+                match &watchdog {
+                    Some(watchdog) => (
+                        Box::new(watchdog.watch(stdin)) as Box<dyn Write>,
+                        Box::new(watchdog.watch(stdout)) as Box<dyn Read>,
+                    ),
+                    None => (Box::new(stdin) as Box<dyn Write>, Box::new(stdout) as Box<dyn Read>),
+                }
             } else {
                 return Err(Box::from("No child process available"));
             }
         };
-
-        // Send mode to server
-        stdin_writer.write_all(mode.as_bytes())?;
-        stdin_writer.write_all(b"\n")?;
-        stdin_writer.flush()?;
-
-        // Execute the appropriate sync operations
-        match mode {
-            "send" => {
-                self.send_database(&mut stdin_writer, conn)?;
-                drop(stdin_writer);
-            }
-            "receive" => {
-                // For receive-only, we need to close stdin
-                drop(stdin_writer);
-                self.receive_database(&mut stdout_reader, conn)?;
-            }
-            "bidirectional" => {
-                self.send_database(&mut stdin_writer, conn)?;
-                // Close stdin to signal we're done sending
-                drop(stdin_writer);
-                self.receive_database(&mut stdout_reader, conn)?;
-            }
-            _ => unreachable!(),
-        }
+        let stdout_reader = io::BufReader::new(stdout_reader);
+        let session = self.exchange(
+            conn,
+            protocol,
+            mode,
+            wire_mode,
+            stdin_writer,
+            stdout_reader,
+            watchdog.as_ref(),
+        );
 
         // Wait for child process if using SSH
         if let Some(mut child) = child {
             let status = child.wait()?;
-            if !status.success() {
-                return Err(Box::from("Remote sync failed"));
+            if let Some(failure) = watchdog.as_ref().and_then(Watchdog::failure) {
+                return Err(failure.into());
+            }
+            let failed = match &session {
+                Ok(result) => result.is_some() && !status.success(),
+                Err(_) => true,
+            };
+            if failed {
+                let error = session
+                    .err()
+                    .map_or_else(|| "Remote sync failed".to_string(), |e| e.to_string());
+                return Err(match remote_stderr.and_then(last_line) {
+                    Some(remote) => format!("{error} ({remote})"),
+                    None => error,
+                }
+                .into());
             }
         }
+        session
+    }
 
-        if !self.stdin_stdout {
-            println!("Sync completed successfully");
+    /// Handshake per `protocol` and exchange history over the peer's pipes;
+    /// see `run_remote_sync`. The watchdog, if any, is told when we are
+    /// done sending.
+    #[allow(clippy::too_many_arguments)]
+    fn exchange(
+        &self,
+        conn: &mut Connection,
+        protocol: SyncProtocol,
+        mode: &str,
+        wire_mode: &str,
+        mut stdin_writer: Box<dyn Write>,
+        mut stdout_reader: io::BufReader<Box<dyn Read>>,
+        watchdog: Option<&Watchdog>,
+    ) -> Result<Option<Option<pxh::sync::MergeStats>>, Box<dyn std::error::Error>> {
+        let mut merged = None;
+        if protocol != SyncProtocol::Legacy {
+            let framed = protocol == SyncProtocol::Framed;
+            let options = SyncOptions {
+                direction: Some(wire_mode.to_string()),
+                machine_id: pxh::read_local_machine_id(conn),
+                compression: framed.then(|| pxh::sync_stream::SUPPORTED_COMPRESSION.to_vec()),
+                ..Default::default()
+            };
+            let mode_line: &[u8] = if framed { b"incremental-v3\n" } else { b"incremental-v2\n" };
+            stdin_writer.write_all(mode_line)?;
+            write_json_line(&mut stdin_writer, &options)?;
+
+            let Some(hello) = read_json_line::<_, SyncHello>(&mut stdout_reader)? else {
+                return Ok(None);
+            };
+            if self.dry_run && mode == "send" {
+                // The peer's watermark was all we needed; hang up before it
+                // sends anything.
+                let (_, delta_for) = Self::delta_for(conn, &hello)?;
+                self.report_outgoing(conn, delta_for)?;
+                return Ok(Some(None));
+            }
+            let ours = SyncHello {
+                machine_id: options.machine_id,
+                watermark: hello.machine_id.and_then(|mid| pxh::sync::sync_watermark(conn, mid)),
+                unsealed_since: pxh::sync::oldest_unsealed_start(conn)?,
+                compression: None,
+            };
+            write_json_line(&mut stdin_writer, &ours)?;
+            let framing = if framed {
+                Framing::Framed(hello.compression.unwrap_or_default())
+            } else {
+                Framing::Raw
+            };
+
+            if mode != "receive" {
+                if self.dry_run {
+                    let (_, delta_for) = Self::delta_for(conn, &hello)?;
+                    self.report_outgoing(conn, delta_for)?;
+                } else {
+                    self.send_delta(&mut stdin_writer, conn, &hello, framing)?;
+                }
+            }
+            // Close stdin to signal we're done sending
+            drop(stdin_writer);
+            if let Some(watchdog) = watchdog
+                && wire_mode != "receive"
+            {
+                watchdog.await_reply();
+            }
+            if mode != "send" {
+                let options = SyncOptions {
+                    no_secret_filter: Some(self.no_secret_filter),
+                    ..Default::default()
+                };
+                merged = self.receive_delta(
+                    &mut stdout_reader,
+                    conn,
+                    &options,
+                    hello.machine_id,
+                    framing,
+                )?;
+            }
+        } else if self.dry_run {
+            // Send-only dry runs don't connect to legacy peers at all (see
+            // `handle_remote_sync`).
+            stdin_writer.write_all(b"receive\n")?;
+            drop(stdin_writer);
+            if mode != "receive" {
+                self.report_outgoing(conn, None)?;
+            }
+            merged = self.receive_database(&mut stdout_reader, conn)?;
+        } else {
+            // Send mode to server
+            stdin_writer.write_all(mode.as_bytes())?;
+            stdin_writer.write_all(b"\n")?;
+            stdin_writer.flush()?;
+
+            // Execute the appropriate sync operations
+            match mode {
+                "send" => {
+                    self.send_database(&mut stdin_writer, conn)?;
+                    drop(stdin_writer);
+                }
+                "receive" => {
+                    // For receive-only, we need to close stdin
+                    drop(stdin_writer);
+                    merged = self.receive_database(&mut stdout_reader, conn)?;
+                }
+                "bidirectional" => {
+                    self.send_database(&mut stdin_writer, conn)?;
+                    // Close stdin to signal we're done sending
+                    drop(stdin_writer);
+                    if let Some(watchdog) = watchdog {
+                        watchdog.await_reply();
+                    }
+                    merged = self.receive_database(&mut stdout_reader, conn)?;
+                }
+                _ => unreachable!(),
+            }
         }
 
-        Ok(())
+        Ok(Some(merged))
     }
 
     /// Sync from one source DB during a directory-mode merge. Reads the source's
@@ -1694,19 +3027,34 @@ impl SyncCommand {
     /// settings, then merges only rows above that watermark. Falls back to a
     /// full scan if the source doesn't advertise a `local_machine_id` (older
     /// pxh, never ran `install`). Tracks duplicate machine_ids across this run
-    /// and warns -- two source databases shouldn't share identity.
+    /// and warns -- two source databases shouldn't share identity. `db_path` is
+    /// the file actually read (a decrypted temp copy for encrypted sources);
+    /// `path` is the file in the sync directory, used in messages.
     fn sync_from_directory_source(
         conn: &mut Connection,
         path: PathBuf,
+        db_path: &Path,
         secret_filter: Option<&regex::bytes::RegexSet>,
         seen_machine_ids: &mut std::collections::HashMap<u64, PathBuf>,
+        dry_run: bool,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Quick read-only peek at the source for its machine_id. Open read-only
         // so we don't touch the file before the merge function runs its own
         // schema setup with a regular connection.
         let mut source_machine_id = {
-            let other = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok();
-            other.as_ref().and_then(pxh::read_local_machine_id)
+            let other = if dry_run {
+                // Immutable: not even -wal/-shm files appear next to the source.
+                let path = db_path.to_string_lossy();
+                let path = path.replace('%', "%25").replace('?', "%3f").replace('#', "%23");
+                let uri = format!("file:{path}?immutable=1");
+                Connection::open_with_flags(
+                    uri,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+                )
+            } else {
+                Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            };
+            other.ok().as_ref().and_then(pxh::read_local_machine_id)
         };
 
         if let Some(mid) = source_machine_id
@@ -1717,6 +3065,13 @@ impl SyncCommand {
                 prev.display(),
                 path.display()
             );
+            if !dry_run {
+                pxh::sync::record_duplicate_machine_id(
+                    conn,
+                    mid,
+                    Some(&format!("{} and {}", prev.display(), path.display())),
+                )?;
+            }
             // Skip watermark + machine_id-keyed bookkeeping for this source --
             // the two DBs share an identity, so their id spaces aren't
             // comparable. INSERT OR IGNORE still keeps the merge correct.
@@ -1726,8 +3081,21 @@ impl SyncCommand {
         // Resolve watermark for this source (None means full-scan fallback).
         let watermark = source_machine_id.and_then(|mid| pxh::sync::sync_watermark(conn, mid));
 
+        if dry_run {
+            let preview = pxh::sync::preview_merge_from_file(
+                conn,
+                db_path,
+                secret_filter,
+                watermark,
+                PREVIEW_SAMPLE_SIZE,
+            )?;
+            let watermark_move = source_machine_id.map(|_| (watermark, preview.new_max_id));
+            print!("{}", format_merge_preview(&path.to_string_lossy(), &preview, watermark_move));
+            return Ok(());
+        }
+
         print!("Syncing from {}...", path.to_string_lossy());
-        let stats = pxh::sync::merge_database_from_file(conn, &path, secret_filter, watermark)?;
+        let stats = pxh::sync::merge_database_from_file(conn, db_path, secret_filter, watermark)?;
 
         // Sanity check: if the source's max(id) regressed below our watermark,
         // the source DB was probably restored from backup or rebuilt. The merge
@@ -1739,18 +3107,98 @@ impl SyncCommand {
             eprintln!(" (notice: source max(id) {new_max} < watermark {wm}; resetting watermark)");
         }
 
-        if stats.filtered > 0 {
-            println!(
-                "done, considered {} rows, added {}, filtered {}",
-                stats.considered, stats.added, stats.filtered
+        println!("{}", merge_summary(&stats));
+
+        // Persist the new watermark when we have a machine_id key for it.
+        if let Some(mid) = source_machine_id {
+            if let Some(new_max) = stats.new_max_id {
+                pxh::sync::set_sync_watermark(conn, mid, new_max)?;
+            }
+            // The first of two sources sharing an id clears the flag; the
+            // second, later in this run, sets it again.
+            pxh::sync::record_duplicate_machine_id(conn, mid, None)?;
+            pxh::sync::record_sync_source(conn, mid, &path.to_string_lossy())?;
+        }
+        Ok(())
+    }
+
+    /// Merge the segments of another machine's `<host>.segments` directory
+    /// not merged before. A segment that can't be read yet (still uploading,
+    /// or compacted away mid-sync) is skipped with a warning and retried next
+    /// sync; the source's watermark only advances once all are in.
+    fn sync_from_segment_dir(
+        conn: &mut Connection,
+        seg_dir: &Path,
+        key: Option<&pxh::encryption::SyncKey>,
+        secret_filter: Option<&regex::bytes::RegexSet>,
+        seen_machine_ids: &mut std::collections::HashMap<u64, PathBuf>,
+        dry_run: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(manifest) = pxh::segments::read_manifest(seg_dir)? else {
+            return Ok(());
+        };
+        let mut source_machine_id = manifest.machine_id;
+        if let Some(mid) = source_machine_id
+            && let Some(prev) = seen_machine_ids.insert(mid, seg_dir.to_path_buf())
+        {
+            eprintln!(
+                "Warning: {} and {} both report machine_id {mid} -- one of them is likely a clone or misconfigured.",
+                prev.display(),
+                seg_dir.display()
             );
-        } else {
-            println!("done, considered {} rows and added {}", stats.considered, stats.added);
+            if !dry_run {
+                pxh::sync::record_duplicate_machine_id(
+                    conn,
+                    mid,
+                    Some(&format!("{} and {}", prev.display(), seg_dir.display())),
+                )?;
+            }
+            source_machine_id = None;
         }
 
-        // Persist the new watermark when we have a machine_id key for it.
-        if let (Some(mid), Some(new_max)) = (source_machine_id, stats.new_max_id) {
-            pxh::sync::set_sync_watermark(conn, mid, new_max)?;
+        let mut consumed = pxh::segments::consumed_segments(conn, seg_dir);
+        let mut complete = true;
+        for segment in &manifest.segments {
+            if consumed.contains(&segment.name) {
+                continue;
+            }
+            let segment_path = seg_dir.join(&segment.name);
+            let file = match pxh::segments::open_segment(seg_dir, segment, key) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("Warning: Skipping {} for now: {e}", segment_path.display());
+                    complete = false;
+                    continue;
+                }
+            };
+            if dry_run {
+                let preview = pxh::sync::preview_merge_from_file(
+                    conn,
+                    file.path(),
+                    secret_filter,
+                    None,
+                    PREVIEW_SAMPLE_SIZE,
+                )?;
+                print!("{}", format_merge_preview(&segment_path.to_string_lossy(), &preview, None));
+                continue;
+            }
+            print!("Syncing from {}...", segment_path.display());
+            let stats =
+                pxh::sync::merge_database_from_file(conn, file.path(), secret_filter, None)?;
+            println!("{}", merge_summary(&stats));
+            consumed.insert(segment.name.clone());
+        }
+        if dry_run {
+            return Ok(());
+        }
+        pxh::segments::set_consumed_segments(conn, seg_dir, &manifest, &consumed)?;
+
+        if let Some(mid) = source_machine_id {
+            if complete && let Some(last_id) = manifest.last_id() {
+                pxh::sync::set_sync_watermark(conn, mid, last_id)?;
+            }
+            pxh::sync::record_duplicate_machine_id(conn, mid, None)?;
+            pxh::sync::record_sync_source(conn, mid, &seg_dir.to_string_lossy())?;
         }
         Ok(())
     }
@@ -1766,14 +3214,15 @@ impl SyncCommand {
 
         let mode = mode.trim();
 
-        // Check for v2 protocol modes
-        let (base_mode, options) = if mode.ends_with("-v2") {
+        // Check for v2 protocol modes (v3 only changes how databases travel)
+        let versioned = mode.strip_suffix("-v2").or_else(|| mode.strip_suffix("-v3"));
+        let (base_mode, options) = if let Some(base_mode) = versioned {
             // v2 protocol: read options JSON from next line
             let mut options_json = String::new();
             std::io::stdin().read_line(&mut options_json)?;
             let options: SyncOptions = serde_json::from_str(options_json.trim())
                 .map_err(|e| format!("Failed to parse v2 protocol options: {e}. Client and server may have incompatible versions."))?;
-            (mode.strip_suffix("-v2").unwrap(), options)
+            (base_mode, options)
         } else {
             (mode, SyncOptions::default())
         };
@@ -1781,7 +3230,12 @@ impl SyncCommand {
         match base_mode {
             "send" => {
                 // Server receives database from client
-                self.receive_database_with_options(&mut std::io::stdin(), conn, &options)?;
+                self.receive_database_with_options(
+                    &mut std::io::stdin(),
+                    conn,
+                    &options,
+                    Framing::Raw,
+                )?;
             }
             "receive" => {
                 // Server sends database to client
@@ -1789,9 +3243,15 @@ impl SyncCommand {
             }
             "bidirectional" => {
                 // Server receives then sends
-                self.receive_database_with_options(&mut std::io::stdin(), conn, &options)?;
+                self.receive_database_with_options(
+                    &mut std::io::stdin(),
+                    conn,
+                    &options,
+                    Framing::Raw,
+                )?;
                 self.send_database(&mut std::io::stdout(), conn)?;
             }
+            "incremental" => self.serve_incremental(conn, &options, mode.ends_with("-v3"))?,
             "scrub" => {
                 // Remote scrub: execute scrub and return result
                 let result = self.execute_remote_scrub(conn, &options)?;
@@ -1803,6 +3263,43 @@ impl SyncCommand {
         Ok(())
     }
 
+    /// Server side of an incremental sync session (see `run_remote_sync`).
+    /// `framed` sessions (v3) pick a compression from the client's offer.
+    fn serve_incremental(
+        &self,
+        conn: &mut Connection,
+        options: &SyncOptions,
+        framed: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let client = options.machine_id;
+        let compression = framed.then(|| {
+            pxh::sync_stream::negotiate(options.compression.as_deref().unwrap_or_default())
+        });
+        let hello = SyncHello {
+            machine_id: pxh::read_local_machine_id(conn),
+            watermark: client.and_then(|mid| pxh::sync::sync_watermark(conn, mid)),
+            unsealed_since: pxh::sync::oldest_unsealed_start(conn)?,
+            compression,
+        };
+        write_json_line(&mut std::io::stdout(), &hello)?;
+        let framing = compression.map_or(Framing::Raw, Framing::Framed);
+
+        let stdin = std::io::stdin();
+        let mut reader = stdin.lock();
+        // A send-only dry run hangs up once it has our hello.
+        let Some(peer) = read_json_line::<_, SyncHello>(&mut reader)? else {
+            return Ok(());
+        };
+        let direction = options.direction.as_deref().unwrap_or("bidirectional");
+        if direction != "receive" {
+            self.receive_delta(&mut reader, conn, options, client, framing)?;
+        }
+        if direction != "send" {
+            self.send_delta(&mut std::io::stdout(), conn, &peer, framing)?;
+        }
+        Ok(())
+    }
+
     /// Execute scrub operation as requested by remote client
     fn execute_remote_scrub(
         &self,
@@ -1860,7 +3357,74 @@ impl SyncCommand {
         conn: &mut Connection,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Create filtered database copy
-        let temp_file = self.create_filtered_db_copy(conn)?;
+        let temp_file = self.create_filtered_db_copy(conn, None)?;
+        Self::send_file(writer, &temp_file, Framing::Raw)
+    }
+
+    /// Incremental counterpart of `send_database`: a header, then only the
+    /// rows the peer described by `peer` lacks.
+    fn send_delta<W: Write>(
+        &self,
+        writer: &mut W,
+        conn: &mut Connection,
+        peer: &SyncHello,
+        framing: Framing,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (max_id, delta_for) = Self::delta_for(conn, peer)?;
+        // A --since copy leaves out older rows above the watermark, so the
+        // peer must not advance it.
+        let header = DeltaHeader { max_id: max_id.filter(|_| self.since.is_none()) };
+        write_json_line(writer, &header)?;
+        let temp_file = self.create_filtered_db_copy(conn, delta_for)?;
+        Self::send_file(writer, &temp_file, framing)
+    }
+
+    /// Our newest id, and the watermark and unsealed start to build the
+    /// peer's delta from (`None` for everything).
+    #[allow(clippy::type_complexity)]
+    fn delta_for(
+        conn: &Connection,
+        peer: &SyncHello,
+    ) -> Result<(Option<i64>, Option<(i64, Option<i64>)>), Box<dyn std::error::Error>> {
+        let max_id: Option<i64> =
+            conn.query_row("SELECT MAX(id) FROM command_history", [], |r| r.get(0))?;
+        // A watermark past our newest row means this database was restored or
+        // rebuilt since the peer last synced; send everything.
+        let watermark = peer.watermark.filter(|wm| max_id.is_some_and(|max| *wm <= max));
+        Ok((max_id, watermark.map(|wm| (wm, peer.unsealed_since))))
+    }
+
+    /// Dry-run stand-in for sending: build what we would send and say how
+    /// many rows it holds.
+    fn report_outgoing(
+        &self,
+        conn: &mut Connection,
+        delta_for: Option<(i64, Option<i64>)>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let copy = self.create_filtered_db_copy(conn, delta_for)?;
+        let rows: i64 = Connection::open(copy.path())?.query_row(
+            "SELECT COUNT(*) FROM command_history",
+            [],
+            |r| r.get(0),
+        )?;
+        eprintln!("Would send {rows} rows to {}", self.peer_name());
+        Ok(())
+    }
+
+    fn peer_name(&self) -> &str {
+        self.remote_host().unwrap_or("peer")
+    }
+
+    fn send_file<W: Write>(
+        writer: &mut W,
+        temp_file: &NamedTempFile,
+        framing: Framing,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Framing::Framed(compression) = framing {
+            let mut file = File::open(temp_file.path())?;
+            pxh::sync_stream::write_stream(writer, &mut file, compression)?;
+            return Ok(());
+        }
 
         // Get file size
         let metadata = std::fs::metadata(temp_file.path())?;
@@ -1880,18 +3444,86 @@ impl SyncCommand {
         &self,
         reader: &mut R,
         conn: &mut Connection,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<Option<pxh::sync::MergeStats>, Box<dyn std::error::Error>> {
         let options =
             SyncOptions { no_secret_filter: Some(self.no_secret_filter), ..Default::default() };
-        self.receive_database_with_options(reader, conn, &options)
+        if self.dry_run {
+            self.preview_received(reader, conn, &options, None, Framing::Raw)?;
+            return Ok(None);
+        }
+        self.receive_database_with_options(reader, conn, &options, Framing::Raw).map(Some)
     }
 
-    fn receive_database_with_options<R: Read>(
+    /// Incremental counterpart of `receive_database`: merge what the peer
+    /// sends and record its watermark under the peer's machine id.
+    fn receive_delta<R: io::BufRead>(
         &self,
         reader: &mut R,
         conn: &mut Connection,
         options: &SyncOptions,
+        peer: Option<u64>,
+        framing: Framing,
+    ) -> Result<Option<pxh::sync::MergeStats>, Box<dyn std::error::Error>> {
+        let header: DeltaHeader =
+            read_json_line(reader)?.ok_or("Peer closed the connection before sending history")?;
+        if self.dry_run {
+            let watermark_move = peer
+                .map(|mid| (pxh::sync::sync_watermark(conn, mid), header.max_id))
+                .filter(|_| header.max_id.is_some());
+            self.preview_received(reader, conn, options, watermark_move, framing)?;
+            return Ok(None);
+        }
+        let stats = self.receive_database_with_options(reader, conn, options, framing)?;
+        let _merging = merge_lock();
+        if let Some(mid) = peer {
+            if let Some(max_id) = header.max_id {
+                pxh::sync::set_sync_watermark(conn, mid, max_id)?;
+            }
+            let via = match self.remote_host() {
+                Some(host) => format!("{}:{host}", self.transport_name()),
+                None if self.server => "ssh (incoming)".to_string(),
+                None => "stdin/stdout".to_string(),
+            };
+            pxh::sync::record_sync_source(conn, mid, &via)?;
+        }
+        Ok(Some(stats))
+    }
+
+    /// Dry-run counterpart of `receive_database_with_options`.
+    fn preview_received<R: Read>(
+        &self,
+        reader: &mut R,
+        conn: &Connection,
+        options: &SyncOptions,
+        watermark_move: Option<(Option<i64>, Option<i64>)>,
+        framing: Framing,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = Self::receive_to_temp(reader, framing)?;
+        let secret_filter = sync_secret_filter(!options.no_secret_filter.unwrap_or(false))?;
+        let preview = pxh::sync::preview_merge_from_file(
+            conn,
+            temp_file.path(),
+            secret_filter.as_ref(),
+            None,
+            PREVIEW_SAMPLE_SIZE,
+        )?;
+        eprint!("{}", format_merge_preview(self.peer_name(), &preview, watermark_move));
+        Ok(())
+    }
+
+    /// Read one database from the peer into a temp file, streaming it
+    /// rather than holding it in memory.
+    fn receive_to_temp<R: Read>(
+        reader: &mut R,
+        framing: Framing,
+    ) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+        let mut temp_file = tempfile::NamedTempFile::new()?;
+        if let Framing::Framed(compression) = framing {
+            pxh::sync_stream::read_stream(reader, &mut temp_file, compression, MAX_SYNC_DB_SIZE)?;
+            temp_file.flush()?;
+            return Ok(temp_file);
+        }
+
         // Receive database size (8 bytes)
         let mut size_bytes = [0u8; 8];
         reader.read_exact(&mut size_bytes)?;
@@ -1906,19 +3538,30 @@ impl SyncCommand {
         }
 
         // Receive database data
-        let mut data = vec![0u8; size as usize];
-        reader.read_exact(&mut data)?;
+        let received = io::copy(&mut reader.take(size), &mut temp_file)?;
+        if received != size {
+            return Err("Peer closed the connection in the middle of a database".into());
+        }
+        temp_file.flush()?;
+        Ok(temp_file)
+    }
 
-        // Create temporary file for the received database
-        let temp_file = tempfile::NamedTempFile::new()?;
-        std::fs::write(temp_file.path(), &data)?;
+    fn receive_database_with_options<R: Read>(
+        &self,
+        reader: &mut R,
+        conn: &mut Connection,
+        options: &SyncOptions,
+        framing: Framing,
+    ) -> Result<pxh::sync::MergeStats, Box<dyn std::error::Error>> {
+        let temp_file = Self::receive_to_temp(reader, framing)?;
+        let _merging = merge_lock();
 
         // Determine if we should filter secrets
         let secret_filter = sync_secret_filter(!options.no_secret_filter.unwrap_or(false))?;
 
-        // Use the merge function with optional secret filtering.
-        // SSH/stdin-stdout sync streams a full DB snapshot each time, so we
-        // don't apply a watermark here -- pass None for full-scan behavior.
+        // Use the merge function with optional secret filtering. The sender
+        // already left out what we have (incremental) or sent everything
+        // (legacy), so merge all of it -- pass None for full-scan behavior.
         let stats = pxh::sync::merge_database_from_file(
             conn,
             temp_file.path(),
@@ -1940,8 +3583,27 @@ impl SyncCommand {
         if filtered_count > 0 {
             msg.push_str(&format!(", filtered {filtered_count} potential secrets"));
         }
-        eprintln!("{msg}");
-        Ok(())
+        if stats.deleted > 0 {
+            msg.push_str(&format!(", removed {} deleted elsewhere", stats.deleted));
+        }
+        if !self.parallel {
+            eprintln!("{msg}");
+        }
+        Ok(stats)
+    }
+}
+
+// Rows to present, with an optional marker per row (see
+// `PrintableCommand::expand_context`).
+type MarkedRows = (Vec<pxh::Invocation>, Option<Vec<pxh::RowMarker>>);
+
+fn standard_fields(verbose: bool, failed: bool) -> Vec<&'static str> {
+    if verbose {
+        vec!["start_time", "duration", "session", "context", "status", "command"]
+    } else if failed {
+        vec!["start_time", "status", "command"]
+    } else {
+        vec!["start_time", "command"]
     }
 }
 
@@ -1958,11 +3620,30 @@ trait PrintableCommand {
         rows: Vec<pxh::Invocation>,
     ) -> Result<Vec<pxh::Invocation>, Box<dyn std::error::Error>>;
 
+    // Optionally surround the final rows with related ones (e.g. session
+    // neighbours), returning a marker per row to tell them apart.
+    fn expand_context(
+        &self,
+        _conn: &Connection,
+        rows: Vec<pxh::Invocation>,
+    ) -> Result<MarkedRows, Box<dyn std::error::Error>> {
+        Ok((rows, None))
+    }
+
+    fn output_format(&self) -> pxh::OutputFormat {
+        pxh::OutputFormat::Table
+    }
+
+    // Table columns, as keys of `pxh::displayers`.
+    fn display_fields(&self) -> Vec<&'static str> {
+        standard_fields(self.verbose(), self.failed())
+    }
+
     fn present_results(&self, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
         // Now that we have the relevant rows, just present the output
         let mut stmt = conn.prepare(
 	r#"
-SELECT session_id, full_command, shellname, working_directory, hostname, username, exit_status, start_unix_timestamp, end_unix_timestamp
+SELECT h.id, session_id, full_command, shellname, working_directory, hostname, username, exit_status, start_unix_timestamp, end_unix_timestamp
   FROM memdb.show_results sr, command_history h
  WHERE sr.ch_rowid = h.rowid
 ORDER BY ch_start_unix_timestamp DESC, ch_id DESC
@@ -1971,14 +3652,14 @@ ORDER BY ch_start_unix_timestamp DESC, ch_id DESC
         let rows: Result<Vec<pxh::Invocation>, _> =
             stmt.query_map([], pxh::Invocation::from_row)?.collect();
         let rows = self.extra_filter_step(rows?)?;
-        let fields: &[&str] = if self.verbose() {
-            &["start_time", "duration", "session", "context", "status", "command"]
-        } else if self.failed() {
-            &["start_time", "status", "command"]
-        } else {
-            &["start_time", "command"]
-        };
-        pxh::present_results_human_readable(fields, &rows, self.suppress_headers())?;
+        let (rows, markers) = self.expand_context(conn, rows)?;
+        pxh::write_results(
+            &self.output_format(),
+            &self.display_fields(),
+            &rows,
+            markers.as_deref(),
+            self.suppress_headers(),
+        )?;
         Ok(())
     }
 }
@@ -2046,13 +3727,13 @@ impl ScrubCommand {
             (vec![], regex::bytes::RegexSet::empty())
         };
 
-        let db_extension = OsStr::new("db");
+        let key = pxh::encryption::SyncKey::from_config(&pxh::recall::config::Config::load())?;
         let mut entries: Vec<_> = Vec::new();
         let mut skipped_entries = 0usize;
         for entry_result in fs::read_dir(dir)? {
             match entry_result {
                 Ok(entry) => {
-                    if entry.path().extension() == Some(db_extension) {
+                    if is_sync_dir_entry(&entry.path()) {
                         entries.push(entry);
                     }
                 }
@@ -2064,7 +3745,7 @@ impl ScrubCommand {
         }
 
         if entries.is_empty() {
-            println!("No .db files found in {}", dir.display());
+            println!("No .db, .db.enc or .segments entries found in {}", dir.display());
             return Ok(());
         }
 
@@ -2073,13 +3754,24 @@ impl ScrubCommand {
         // First pass: count total matches for confirmation
         let mut total_matches = 0;
         let mut skipped_files = 0usize;
-        let mut file_match_counts: Vec<(PathBuf, Vec<ScanMatch>)> = Vec::new();
+        let mut file_match_counts: Vec<(PathBuf, Option<NamedTempFile>, Vec<ScanMatch>)> =
+            Vec::new();
 
         for entry in &entries {
             let path = entry.path();
             print!("Scanning {}...", path.display());
 
-            let conn = match Connection::open(&path) {
+            let decrypted = match sync_dir_database(&path, key.as_ref()) {
+                Ok(decrypted) => decrypted,
+                Err(e) => {
+                    eprintln!(" failed to open: {e}");
+                    skipped_files += 1;
+                    continue;
+                }
+            };
+            let conn = match Connection::open(
+                decrypted.as_ref().map_or(path.as_path(), |temp| temp.path()),
+            ) {
                 Ok(c) => c,
                 Err(e) => {
                     eprintln!(" failed to open: {e}");
@@ -2128,7 +3820,7 @@ impl ScrubCommand {
             println!(" {} entries", matches.len());
             total_matches += matches.len();
             if !matches.is_empty() {
-                file_match_counts.push((path, matches));
+                file_match_counts.push((path, decrypted, matches));
             }
         }
 
@@ -2183,10 +3875,24 @@ impl ScrubCommand {
         let mut total_scrubbed = 0;
         let mut files_modified = 0;
 
-        for (path, matches) in file_match_counts {
-            let conn = Connection::open(&path)?;
-            let count = scrub_from_database(&conn, &matches)?;
-            total_scrubbed += count;
+        for (path, decrypted, matches) in file_match_counts {
+            let Some(decrypted) = decrypted else {
+                let conn = Connection::open(&path)?;
+                total_scrubbed += scrub_from_database(&conn, &matches)?;
+                files_modified += 1;
+                continue;
+            };
+            // VACUUM the decrypted copy so scrubbed rows don't survive in free
+            // pages, then seal it back over the original.
+            let conn = Connection::open(decrypted.path())?;
+            total_scrubbed += scrub_from_database(&conn, &matches)?;
+            conn.execute("VACUUM", ())?;
+            drop(conn);
+            if pxh::segments::is_segment_dir(&path) {
+                pxh::segments::replace_with_snapshot(&path, decrypted.path(), key.as_ref())?;
+            } else if let Some(key) = &key {
+                pxh::encryption::encrypt_file(key, decrypted.path(), &path)?;
+            }
             files_modified += 1;
         }
 
@@ -2266,7 +3972,7 @@ impl ScrubCommand {
                 None
             },
             scrub_dry_run: if self.dry_run { Some(true) } else { None },
-            no_secret_filter: None,
+            ..Default::default()
         };
 
         stdin_writer.write_all(b"scrub-v2\n")?;
@@ -2393,8 +4099,9 @@ ORDER BY start_unix_timestamp DESC, id DESC"#,
         }
 
         let tx = conn.transaction()?;
-        tx.execute(
-            "DELETE FROM command_history WHERE rowid IN (SELECT ch_rowid FROM memdb.show_results)",
+        pxh::sync::delete_with_tombstones(
+            &tx,
+            "rowid IN (SELECT ch_rowid FROM memdb.show_results)",
             (),
         )?;
         tx.commit()?;
@@ -2434,12 +4141,31 @@ impl PrintableCommand for ShowCommand {
             })
             .collect();
         let regexes = regexes?;
-        Ok(rows
-            .into_iter()
-            .filter(|row| match_all_regexes(row, &regexes))
-            .rev()
-            .take(self.display_limit())
-            .collect())
+        // Rows arrive newest first; filtering, aggregating and sorting all
+        // happen before --limit so it keeps the top entries for the order.
+        let mut rows: Vec<pxh::Invocation> =
+            rows.into_iter().filter(|row| match_all_regexes(row, &regexes)).collect();
+        if self.aggregate() {
+            rows = pxh::aggregate_by_command(rows);
+        }
+        match self.sort {
+            ShowSort::Time => {}
+            ShowSort::Duration => rows.sort_by_key(|row| {
+                std::cmp::Reverse(match row.stats {
+                    Some(stats) => stats.mean_duration(),
+                    None => row.duration(),
+                })
+            }),
+            ShowSort::Count => {
+                rows.sort_by_key(|row| std::cmp::Reverse(row.stats.map_or(1, |s| s.count)))
+            }
+            ShowSort::Command => {
+                rows.sort_by(|a, b| a.command.cmp(&b.command));
+                return Ok(rows.into_iter().take(self.display_limit()).collect());
+            }
+        }
+        // Best entries end up last, nearest the prompt.
+        Ok(rows.into_iter().take(self.display_limit()).rev().collect())
     }
 
     fn verbose(&self) -> bool {
@@ -2452,9 +4178,126 @@ impl PrintableCommand for ShowCommand {
     fn display_limit(&self) -> usize {
         self.limit
     }
+    fn display_fields(&self) -> Vec<&'static str> {
+        if self.aggregate() {
+            let mut fields = vec!["count", "first_seen", "last_seen", "success_rate", "command"];
+            if self.verbose || self.sort == ShowSort::Duration {
+                fields.insert(4, "avg_duration");
+            }
+            return fields;
+        }
+        let mut fields = standard_fields(self.verbose, self.failed);
+        let by_duration = self.sort == ShowSort::Duration
+            || self.min_duration.is_some()
+            || self.max_duration.is_some();
+        if by_duration && !fields.contains(&"duration") {
+            fields.insert(1, "duration");
+        }
+        fields
+    }
+    fn output_format(&self) -> pxh::OutputFormat {
+        if self.null {
+            return pxh::OutputFormat::Null;
+        }
+        if let Some(template) = &self.template {
+            return pxh::OutputFormat::Template(template.clone());
+        }
+        match self.format {
+            ShowFormat::Table => pxh::OutputFormat::Table,
+            ShowFormat::Json => pxh::OutputFormat::Json,
+            ShowFormat::Ndjson => pxh::OutputFormat::Ndjson,
+            ShowFormat::Csv => pxh::OutputFormat::Csv,
+            ShowFormat::Tsv => pxh::OutputFormat::Tsv,
+        }
+    }
     fn failed(&self) -> bool {
         self.failed
     }
+
+    // Expand each match with its session neighbours in id order, grep -C
+    // style. Overlapping or adjacent windows in the same session merge into
+    // one group.
+    fn expand_context(
+        &self,
+        conn: &Connection,
+        rows: Vec<pxh::Invocation>,
+    ) -> Result<MarkedRows, Box<dyn std::error::Error>> {
+        let (before, after) = self.context_window();
+        if before == 0 && after == 0 {
+            return Ok((rows, None));
+        }
+
+        let neighbours = |sql: &str, session_id: i64, id: i64, n: usize| {
+            conn.prepare_cached(sql)?
+                .query_map((session_id, id, n as i64), pxh::Invocation::from_row)?
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let mut out: Vec<pxh::Invocation> = Vec::new();
+        let mut markers: Vec<pxh::RowMarker> = Vec::new();
+        // Last row id printed per session, and the session of the group
+        // currently being built
+        let mut printed: std::collections::HashMap<i64, i64> = std::collections::HashMap::new();
+        let mut group: Option<i64> = None;
+        for hit in rows {
+            let Some(hit_id) = hit.id else { continue };
+            let mut preceding = neighbours(
+                "SELECT * FROM command_history WHERE session_id = ? AND id < ?
+                  ORDER BY id DESC LIMIT ?",
+                hit.session_id,
+                hit_id,
+                before + 1,
+            )?;
+            preceding.reverse();
+            // One row beyond the window tells us whether it directly
+            // follows the current group, which grep also merges.
+            let beyond = (preceding.len() > before).then(|| preceding.remove(0));
+            let following = neighbours(
+                "SELECT * FROM command_history WHERE session_id = ? AND id > ?
+                  ORDER BY id ASC LIMIT ?",
+                hit.session_id,
+                hit_id,
+                after,
+            )?;
+
+            let first_id =
+                beyond.as_ref().or(preceding.first()).and_then(|r| r.id).unwrap_or(hit_id);
+            let session_id = hit.session_id;
+            let printed_to = printed.get(&session_id).copied();
+            if printed_to.is_some_and(|last_id| hit_id <= last_id) {
+                // The hit was already printed as context; promote it.
+                if let Some(pos) = out.iter().rposition(|r| r.id == Some(hit_id)) {
+                    markers[pos].matched = true;
+                }
+            }
+
+            // Rows of this session printed earlier, even in another group,
+            // are not repeated.
+            let mut group_start =
+                group != Some(session_id) || printed_to.is_none_or(|last_id| first_id > last_id);
+            let last_id = printed_to.unwrap_or(i64::MIN);
+            let window = preceding
+                .into_iter()
+                .map(|r| (r, false))
+                .chain(std::iter::once((hit, true)))
+                .chain(following.into_iter().map(|r| (r, false)));
+            let mut new_last = last_id;
+            for (row, matched) in window {
+                let id = row.id.unwrap_or(i64::MIN);
+                if id <= last_id {
+                    continue;
+                }
+                new_last = id;
+                markers.push(pxh::RowMarker { matched, group_start });
+                group_start = false;
+                out.push(row);
+            }
+            if new_last > last_id {
+                printed.insert(session_id, new_last);
+                group = Some(session_id);
+            }
+        }
+        Ok((out, Some(markers)))
+    }
 }
 
 impl ShowCommand {
@@ -2463,6 +4306,26 @@ impl ShowCommand {
     fn query_limit(&self) -> usize {
         self.sql_limit.unwrap_or(self.limit)
     }
+
+    /// Whether matching rows are collapsed per command (`--unique`, or
+    /// implied by `--sort count`).
+    fn aggregate(&self) -> bool {
+        self.unique || self.sort == ShowSort::Count
+    }
+
+    /// Whether every SQL match must be fetched for client-side filtering,
+    /// aggregation or sorting before `--limit` applies.
+    fn needs_all_matches(&self) -> bool {
+        self.loosen || self.aggregate() || self.sort != ShowSort::Time
+    }
+
+    /// (before, after) session neighbours to show around each match;
+    /// -A/-B override -C for their side.
+    fn context_window(&self) -> (usize, usize) {
+        let before = self.before_context.or(self.context).unwrap_or(0);
+        let after = self.after_context.or(self.context).unwrap_or(0);
+        (before, after)
+    }
 }
 
 impl ShowCommand {
@@ -2478,21 +4341,23 @@ impl ShowCommand {
 
         let pattern = if self.ignore_case { format!("(?i){pattern}") } else { pattern };
 
-        conn.execute("DELETE FROM memdb.show_results", ())?;
+        if self.aggregate() && self.context_window() != (0, 0) {
+            return Err("--unique (or --sort count) cannot be combined with -A/-B/-C".into());
+        }
+        // Context merges each session's windows in id order; another order
+        // would interleave (and drop) them.
+        if self.sort != ShowSort::Time && self.context_window() != (0, 0) {
+            return Err("--sort other than time cannot be combined with -A/-B/-C".into());
+        }
+        // Only the table marks matches and separates groups.
+        if self.output_format() != pxh::OutputFormat::Table && self.context_window() != (0, 0) {
+            return Err("-A/-B/-C cannot be combined with --format, --template or --null".into());
+        }
 
-        let here = self.here || self.working_directory.is_some();
-        let failed_clause =
-            if self.failed { " AND exit_status IS NOT NULL AND exit_status != 0" } else { "" };
+        conn.execute("DELETE FROM memdb.show_results", ())?;
 
-        let working_directory = self.working_directory.as_ref().map_or_else(
-            || {
-                env::var_os("PWD")
-                    .map(PathBuf::from)
-                    .or_else(|| env::current_dir().ok())
-                    .unwrap_or_default()
-            },
-            |v| v.clone(),
-        );
+        let mut conditions = vec!["full_command REGEXP ?".to_string()];
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(pattern)];
 
         if let Some(ref maybe_session) = self.session {
             let session_id: i64 = match maybe_session.as_str() {
@@ -2509,52 +4374,116 @@ impl ShowCommand {
                 )?,
                 id => id.parse::<i64>()?,
             };
+            conditions.push("session_id = ?".into());
+            params.push(Box::new(session_id));
+        }
 
-            conn.execute(
-                &format!(
-                    r#"
-INSERT INTO memdb.show_results (ch_rowid, ch_start_unix_timestamp, ch_id)
-SELECT rowid, start_unix_timestamp, id
-  FROM command_history h
- WHERE full_command REGEXP ? AND session_id = ?{failed_clause}
-ORDER BY start_unix_timestamp DESC, id DESC
-LIMIT ?"#
-                ),
-                (pattern, session_id, self.query_limit() as i64),
-            )?;
-        } else if here {
-            conn.execute(
-                &format!(
-                    r#"
-INSERT INTO memdb.show_results (ch_rowid, ch_start_unix_timestamp, ch_id)
-SELECT rowid, start_unix_timestamp, id
-  FROM command_history h
- WHERE working_directory = CAST(? as blob)
-   AND full_command REGEXP ?{failed_clause}
-ORDER BY start_unix_timestamp DESC, id DESC
-LIMIT ?"#
-                ),
-                (working_directory.as_os_str().as_bytes(), pattern, self.query_limit() as i64),
-            )?;
-        } else {
-            conn.execute(
-                &format!(
-                    r#"
+        if self.here || self.working_directory.is_some() {
+            let working_directory =
+                self.working_directory.as_ref().map_or_else(here_directory, |v| v.clone());
+            conditions.push("working_directory = CAST(? as blob)".into());
+            params.push(Box::new(working_directory.as_os_str().as_bytes().to_vec()));
+        }
+
+        if self.failed {
+            conditions.push("exit_status IS NOT NULL AND exit_status != 0".into());
+        }
+
+        let now = chrono::Local::now();
+        if let Some(ref since) = self.since {
+            conditions.push("start_unix_timestamp >= ?".into());
+            params.push(Box::new(pxh::helpers::parse_time_spec(since, now)?));
+        }
+        if let Some(ref until) = self.until {
+            conditions.push("start_unix_timestamp < ?".into());
+            params.push(Box::new(pxh::helpers::parse_time_spec(until, now)?));
+        }
+
+        if let Some(ref min) = self.min_duration {
+            conditions.push("end_unix_timestamp - start_unix_timestamp >= ?".into());
+            params.push(Box::new(pxh::helpers::parse_duration_spec(min)?));
+        }
+        if let Some(ref max) = self.max_duration {
+            conditions.push("end_unix_timestamp - start_unix_timestamp <= ?".into());
+            params.push(Box::new(pxh::helpers::parse_duration_spec(max)?));
+        }
+
+        if !self.host.is_empty() {
+            let hosts = expand_host_names(&self.host);
+            push_in_condition(&mut conditions, &mut params, "hostname", "CAST(? as blob)", hosts);
+        }
+        if !self.user.is_empty() {
+            let users: Vec<Vec<u8>> = self.user.iter().map(|u| u.as_bytes().to_vec()).collect();
+            push_in_condition(&mut conditions, &mut params, "username", "CAST(? as blob)", users);
+        }
+        if !self.shell.is_empty() {
+            push_in_condition(&mut conditions, &mut params, "shellname", "?", self.shell.clone());
+        }
+        if !self.exit_status.is_empty() {
+            push_in_condition(
+                &mut conditions,
+                &mut params,
+                "exit_status",
+                "?",
+                self.exit_status.clone(),
+            );
+        }
+
+        params.push(Box::new(self.query_limit() as i64));
+        conn.execute(
+            &format!(
+                r#"
 INSERT INTO memdb.show_results (ch_rowid, ch_start_unix_timestamp, ch_id)
 SELECT rowid, start_unix_timestamp, id
   FROM command_history h
- WHERE full_command REGEXP ?{failed_clause}
+ WHERE {}
 ORDER BY start_unix_timestamp DESC, id DESC
-LIMIT ?"#
-                ),
-                (pattern, self.query_limit() as i64),
-            )?;
-        }
+LIMIT ?"#,
+                conditions.join("\n   AND ")
+            ),
+            rusqlite::params_from_iter(params.iter()),
+        )?;
 
         self.present_results(&conn)
     }
 }
 
+// The directory `--here` refers to: $PWD (which keeps symlinks as the
+// shell recorded them), falling back to the process working directory.
+fn here_directory() -> PathBuf {
+    env::var_os("PWD").map(PathBuf::from).or_else(|| env::current_dir().ok()).unwrap_or_default()
+}
+
+// Hostnames for a `--host` filter. Naming this host (or any alias) selects
+// all of its names, so history recorded under an old hostname still shows up.
+fn expand_host_names(names: &[String]) -> Vec<Vec<u8>> {
+    let this_host = pxh::effective_host_set(&pxh::recall::config::Config::load());
+    let mut hosts: Vec<BString> = Vec::new();
+    for name in names {
+        let name = BString::from(name.as_bytes());
+        let expanded = if this_host.contains(&name) { this_host.clone() } else { vec![name] };
+        for host in expanded {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts.into_iter().map(Vec::from).collect()
+}
+
+// Append `column IN (placeholder, ...)` with one bound parameter per value.
+fn push_in_condition<T: rusqlite::ToSql + 'static>(
+    conditions: &mut Vec<String>,
+    params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+    column: &str,
+    placeholder: &str,
+    values: Vec<T>,
+) {
+    let placeholders = vec![placeholder; values.len()].join(", ");
+    conditions.push(format!("{column} IN ({placeholders})"));
+    params.extend(values.into_iter().map(|v| Box::new(v) as Box<dyn rusqlite::ToSql>));
+}
+
 fn match_all_regexes(row: &pxh::Invocation, regexes: &[Regex]) -> bool {
     regexes.iter().all(|regex| regex.is_match(row.command.as_slice()))
 }
@@ -2631,7 +4560,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             if cmd.limit == 0 {
                 cmd.limit = i32::MAX as usize;
             }
-            if cmd.loosen {
+            if cmd.needs_all_matches() {
                 cmd.sql_limit = Some(i32::MAX as usize);
             }
             cmd.go(make_conn_full()?)?;
@@ -2660,6 +4589,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Stats(cmd) => {
             cmd.go(make_conn()?)?;
         }
+        Commands::Report(cmd) => {
+            cmd.go(make_conn()?)?;
+        }
+        Commands::Timesheet(cmd) => {
+            cmd.go(make_conn()?)?;
+        }
+        Commands::Workflows(cmd) => {
+            cmd.go(make_conn()?)?;
+        }
+        Commands::Fix(cmd) => {
+            cmd.go(make_conn()?)?;
+        }
+        Commands::SuggestAliases(cmd) => {
+            cmd.go(make_conn()?)?;
+        }
         Commands::Insert(cmd) => {
             // Load config before make_conn() since migrate_host_settings
             // (called during connection setup) may modify the config file
@@ -2690,12 +4634,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 end_unix_timestamp: cmd.end_unix_timestamp,
                 session_id: cmd.session_id,
                 machine_id: config.host.machine_id,
+                ..Default::default()
             };
             // Short busy_timeout: let our own jittered retry loop handle contention
             // so a single waiter can't burn the full timeout while others slip past.
             conn.busy_timeout(Duration::from_millis(100))?;
             pxh::with_write_retry(&mut conn, Duration::from_secs(1), |tx| invocation.insert(tx))?;
         }
+        Commands::Sql(cmd) => {
+            // Bring the schema (and its views) up to date first; the query
+            // itself runs on a separate read-only connection.
+            drop(make_conn()?);
+            cmd.go(&args.db)?;
+        }
         Commands::Doctor(cmd) => {
             let conn = make_conn().ok();
             cmd.go(conn, &args.db)?;
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -46,6 +47,11 @@ pub struct Config {
     pub shell: ShellConfig,
     #[serde(default)]
     pub history: HistoryConfig,
+    /// Named projects for `pxh timesheet`, keyed by name
+    #[serde(default)]
+    pub projects: BTreeMap<String, ProjectPaths>,
+    #[serde(default)]
+    pub sync: SyncConfig,
 }
 
 /// Configuration for host identity
@@ -57,12 +63,159 @@ pub struct HostConfig {
     pub aliases: Vec<String>,
 }
 
+/// Directories belonging to a project: one path or a list, each covering
+/// everything beneath it. A leading `~` is the home directory.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ProjectPaths {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ProjectPaths {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let paths = match self {
+            ProjectPaths::One(path) => std::slice::from_ref(path),
+            ProjectPaths::Many(paths) => paths.as_slice(),
+        };
+        paths.iter().map(|path| expand_home(path)).collect()
+    }
+}
+
+/// A config path with a leading `~` or `~/` replaced by the home directory.
+/// Other users' homes (`~alice/...`) are left as written.
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = if path == "~" { Some("") } else { path.strip_prefix("~/") };
+    match (rest, home::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Configuration for directory sync
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Default directory for `pxh sync` (e.g. a Dropbox folder)
+    pub directory: Option<String>,
+    /// File holding the passphrase or key that encrypts sync files; keep it
+    /// outside the sync directory
+    pub key_file: Option<String>,
+    /// How this machine writes its history to the sync directory
+    pub format: SyncFormat,
+    /// Named remotes for `pxh sync` with no arguments (all, in order) or
+    /// `pxh sync <name>`
+    pub targets: Vec<SyncTarget>,
+    /// What history may leave this machine
+    pub export: SyncExportConfig,
+}
+
+/// Rules applied to every database this machine sends or writes for sync.
+/// Withheld rows never leave the machine; redacted ones leave with a
+/// placeholder in place of the username or directory.
+///
+/// Peers still advance their watermark for this machine past withheld rows,
+/// so relaxing a rule later only releases rows recorded after the change;
+/// older ones need a full resync (clearing the peers' watermarks).
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct SyncExportConfig {
+    /// Withhold commands run in or under these directories
+    pub exclude_directories: Vec<String>,
+    /// Withhold commands from hosts matching these regexes
+    pub exclude_hosts: Vec<String>,
+    /// Withhold commands matching these regexes
+    pub exclude_commands: Vec<String>,
+    /// Withhold commands older than this many days
+    pub max_age_days: Option<u32>,
+    /// Replace every username with a placeholder
+    pub redact_usernames: bool,
+    /// Replace working directories in or under these with a placeholder
+    pub redact_directories: Vec<String>,
+}
+
+/// One `[[sync.targets]]` entry. Unset options fall back to the `pxh sync`
+/// command-line defaults.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SyncTarget {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: SyncTargetKind,
+    /// SSH host (`type = "ssh"`)
+    pub host: Option<String>,
+    /// Sync directory (`type = "dir"`)
+    pub path: Option<String>,
+    pub ssh_cmd: Option<String>,
+    /// Command template reaching `host` instead of ssh (see `pxh sync --transport`)
+    pub transport: Option<String>,
+    pub remote_db: Option<String>,
+    pub remote_pxh: Option<String>,
+    #[serde(default)]
+    pub direction: SyncDirection,
+    /// Only sync commands from the last N days (ssh only)
+    pub since: Option<u32>,
+    /// Filter potential secrets on import (default true)
+    pub secret_filter: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncTargetKind {
+    Ssh,
+    Dir,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncDirection {
+    #[default]
+    Both,
+    Send,
+    Receive,
+}
+
+/// `db` rewrites one whole database per machine each sync; `segments`
+/// appends small immutable files instead; `both` writes both while older
+/// pxh versions still read the directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncFormat {
+    #[default]
+    Db,
+    Segments,
+    Both,
+}
+
+impl SyncConfig {
+    pub fn directory_path(&self) -> Option<PathBuf> {
+        self.directory.as_deref().map(expand_home)
+    }
+
+    /// `directory` followed by the path of every `dir` target, without repeats.
+    pub fn directory_paths(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = self.directory_path().into_iter().collect();
+        let targets = self.targets.iter().filter(|t| t.kind == SyncTargetKind::Dir);
+        for path in targets.filter_map(|t| t.path.as_deref()).map(expand_home) {
+            if !dirs.contains(&path) {
+                dirs.push(path);
+            }
+        }
+        dirs
+    }
+
+    pub fn key_file_path(&self) -> Option<PathBuf> {
+        self.key_file.as_deref().map(expand_home)
+    }
+}
+
 /// Configuration for shell integration
 #[derive(Debug, Deserialize, Serialize, Default)]
 #[serde(default)]
 pub struct ShellConfig {
     /// Disable Ctrl-R binding (keep shell's default behavior)
     pub disable_ctrl_r: bool,
+    /// Offer learned corrections (`pxh fix`) from a command-not-found handler
+    pub command_not_found_fix: bool,
 }
 
 /// Configuration for the recall TUI
@@ -99,8 +252,14 @@ pub struct PreviewConfig {
     pub show_exit_status: bool,
     pub show_hostname: bool,
     pub show_duration: bool,
+    /// Commands shown before and after the selection in the session-context
+    /// preview (toggled with Ctrl-O), at most `MAX_CONTEXT_LINES`
+    pub context_lines: usize,
 }
 
+/// Upper bound on `[recall.preview] context_lines`.
+pub const MAX_CONTEXT_LINES: usize = 10;
+
 impl Default for PreviewConfig {
     fn default() -> Self {
         PreviewConfig {
@@ -109,6 +268,7 @@ impl Default for PreviewConfig {
             show_exit_status: true,
             show_hostname: false,
             show_duration: true,
+            context_lines: 3,
         }
     }
 }
@@ -161,8 +321,12 @@ impl Config {
 
     pub fn load_from_path(path: &PathBuf) -> Option<Self> {
         let content = fs::read_to_string(path).ok()?;
-        match toml::from_str(&content) {
-            Ok(config) => Some(config),
+        match toml::from_str::<Config>(&content) {
+            Ok(mut config) => {
+                let preview = &mut config.recall.preview;
+                preview.context_lines = preview.context_lines.min(MAX_CONTEXT_LINES);
+                Some(config)
+            }
             Err(e) => {
                 eprintln!("pxh: warning: failed to parse {}: {e}", path.display());
                 eprintln!("pxh: using default configuration");
@@ -226,6 +390,7 @@ mod tests {
         assert!(config.recall.preview.show_exit_status);
         assert!(!config.recall.preview.show_hostname);
         assert!(config.recall.preview.show_duration);
+        assert_eq!(config.recall.preview.context_lines, 3);
         assert!(!config.history.ignore_patterns.is_empty());
         assert!(config.history.ignore_patterns.contains(&"^ls$".to_string()));
     }
@@ -241,6 +406,7 @@ result_limit = 1000
 [recall.preview]
 show_directory = false
 show_hostname = true
+context_lines = 5
 "#;
         let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(config.recall.keymap, "vim");
@@ -248,6 +414,7 @@ show_hostname = true
         assert_eq!(config.recall.result_limit, 1000);
         assert!(!config.recall.preview.show_directory);
         assert!(config.recall.preview.show_hostname);
+        assert_eq!(config.recall.preview.context_lines, 5);
         // Defaults should be preserved for unspecified fields
         assert!(config.recall.preview.show_timestamp);
         assert!(config.recall.preview.show_exit_status);
@@ -287,6 +454,15 @@ aliases = ["other-host"]
         assert_eq!(config.host.aliases, vec!["other-host"]);
     }
 
+    #[test]
+    fn test_load_clamps_context_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[recall.preview]\ncontext_lines = 100000\n").unwrap();
+        let config = Config::load_from_path(&path).unwrap();
+        assert_eq!(config.recall.preview.context_lines, MAX_CONTEXT_LINES);
+    }
+
     #[test]
     fn test_update_config_preserves_existing() {
         let dir = tempfile::tempdir().unwrap();
@@ -385,6 +561,63 @@ keymap = "vim"
         assert!(set.is_ok(), "default patterns should all be valid regexes");
     }
 
+    #[test]
+    fn test_parse_projects_config() {
+        let toml = r#"
+[projects]
+acme = "/work/acme"
+internal = ["/src/tools", "~/infra"]
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.projects["acme"].paths(), vec![PathBuf::from("/work/acme")]);
+        let internal = config.projects["internal"].paths();
+        assert_eq!(internal[0], PathBuf::from("/src/tools"));
+        if let Some(home) = home::home_dir() {
+            assert_eq!(internal[1], home.join("infra"));
+        }
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let Some(home) = home::home_dir() else { return };
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/keys/pxh"), home.join("keys/pxh"));
+        assert_eq!(expand_home("~alice/keys"), PathBuf::from("~alice/keys"));
+        assert_eq!(expand_home("/srv/~/pxh"), PathBuf::from("/srv/~/pxh"));
+        assert_eq!(expand_home("keys"), PathBuf::from("keys"));
+    }
+
+    #[test]
+    fn test_parse_sync_targets() {
+        let toml = r#"
+[[sync.targets]]
+name = "server"
+type = "ssh"
+host = "me@server"
+ssh_cmd = "ssh -p 2222"
+direction = "send"
+since = 30
+
+[[sync.targets]]
+name = "dropbox"
+type = "dir"
+path = "~/Dropbox/pxh"
+secret_filter = false
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let [server, dropbox] = config.sync.targets.as_slice() else { panic!("two targets") };
+        assert_eq!(server.kind, SyncTargetKind::Ssh);
+        assert_eq!(server.host.as_deref(), Some("me@server"));
+        assert_eq!(server.direction, SyncDirection::Send);
+        assert_eq!(server.since, Some(30));
+        assert_eq!(dropbox.kind, SyncTargetKind::Dir);
+        assert_eq!(dropbox.direction, SyncDirection::Both);
+        assert_eq!(dropbox.secret_filter, Some(false));
+        if let Some(home) = home::home_dir() {
+            assert_eq!(config.sync.directory_paths(), vec![home.join("Dropbox/pxh")]);
+        }
+    }
+
     #[test]
     fn test_invalid_toml_returns_none() {
         let dir = tempfile::TempDir::new().unwrap();
@@ -194,6 +194,55 @@ SELECT id, full_command, start_unix_timestamp, working_directory,
         Ok(entries)
     }
 
+    /// Load up to `n` commands before and after row `id` in the same
+    /// session, in execution (id) order with row `id` itself included.
+    /// Both halves walk `idx_session_id_desc`, so this stays cheap on large
+    /// histories. Returns an empty list if the row no longer exists.
+    pub fn session_context(
+        &self,
+        id: i64,
+        n: usize,
+    ) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+        const COLUMNS: &str = "id, full_command, start_unix_timestamp, working_directory,
+                               hostname, exit_status,
+                               CASE WHEN end_unix_timestamp IS NOT NULL
+                                    THEN end_unix_timestamp - start_unix_timestamp
+                                    ELSE NULL END as duration";
+        let session_id: Option<i64> = match self.conn.query_row(
+            "SELECT session_id FROM command_history WHERE id = ?",
+            [id],
+            |row| row.get(0),
+        ) {
+            Ok(session_id) => Some(session_id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let Some(session_id) = session_id else {
+            return Ok(Vec::new());
+        };
+
+        let limit = n as i64;
+        let mut before: Vec<HistoryEntry> = self
+            .conn
+            .prepare(&format!(
+                "SELECT {COLUMNS} FROM command_history
+                  WHERE session_id = ? AND id < ? ORDER BY id DESC LIMIT ?"
+            ))?
+            .query_map(rusqlite::params![session_id, id, limit], |row| self.row_to_entry(row))?
+            .collect::<Result<_, _>>()?;
+        before.reverse();
+        let rest: Vec<HistoryEntry> = self
+            .conn
+            .prepare(&format!(
+                "SELECT {COLUMNS} FROM command_history
+                  WHERE session_id = ? AND id >= ? ORDER BY id ASC LIMIT ?"
+            ))?
+            .query_map(rusqlite::params![session_id, id, limit + 1], |row| self.row_to_entry(row))?
+            .collect::<Result<_, _>>()?;
+        before.extend(rest);
+        Ok(before)
+    }
+
     /// Delete all history entries matching a command (trimmed), since the
     /// recall list is deduplicated by command text.  Returns the number of
     /// rows deleted.
@@ -202,8 +251,9 @@ SELECT id, full_command, start_unix_timestamp, working_directory,
         command: &str,
     ) -> Result<usize, Box<dyn std::error::Error>> {
         let trimmed = command.trim_end();
-        let deleted = self.conn.execute(
-            "DELETE FROM command_history WHERE rtrim(CAST(full_command AS text)) = ?",
+        let deleted = crate::sync::delete_with_tombstones(
+            &self.conn,
+            "rtrim(CAST(full_command AS text)) = ?",
             [trimmed],
         )?;
         Ok(deleted)
@@ -1104,6 +1154,33 @@ mod tests {
         assert_eq!(entries[0].command, "other cmd");
     }
 
+    #[test]
+    fn test_session_context_stays_within_session() {
+        let conn = test_db();
+        for (session, cmd) in
+            [(1, "cd proj"), (2, "other-a"), (1, "export X=1"), (1, "make"), (2, "other-b")]
+        {
+            conn.execute(
+                "INSERT INTO command_history (session_id, full_command, shellname, start_unix_timestamp)
+                 VALUES (?, CAST(? AS blob), 'bash', 1000)",
+                rusqlite::params![session, cmd],
+            )
+            .unwrap();
+        }
+        let engine =
+            SearchEngine::new(conn, PathBuf::from("/tmp"), vec![BString::from("host1")], 100);
+
+        let around = |id: i64, n: usize| -> Vec<String> {
+            engine.session_context(id, n).unwrap().into_iter().map(|e| e.command).collect()
+        };
+        // Row 3 is "export X=1" in session 1.
+        assert_eq!(around(3, 5), ["cd proj", "export X=1", "make"]);
+        assert_eq!(around(3, 1), ["cd proj", "export X=1", "make"]);
+        assert_eq!(around(4, 1), ["export X=1", "make"]);
+        assert_eq!(around(2, 3), ["other-a", "other-b"]);
+        assert!(around(99, 3).is_empty(), "a deleted row has no context");
+    }
+
     #[test]
     fn test_format_relative_time_days() {
         let now =
@@ -21,6 +21,7 @@ use super::command::{FilterMode, HostFilter};
 use super::config::{KeymapMode, PreviewConfig, RecallConfig};
 use super::engine::{HistoryEntry, SearchEngine, format_relative_time};
 use super::query::RecallQuery;
+use crate::helpers::format_duration;
 
 const SCROLL_MARGIN: usize = 5;
 
@@ -115,6 +116,15 @@ fn sanitize_for_display(s: &str) -> String {
 const PREVIEW_HEIGHT: usize = 5; // Height of preview pane in lines
 const FLASH_DURATION_MS: u64 = 100; // Duration of visual flash in milliseconds
 
+/// What the preview pane shows for the selected entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PreviewMode {
+    /// Directory, time, exit status, duration and host of the selection.
+    Details,
+    /// The commands run just before and after the selection in its session.
+    Session,
+}
+
 /// Deduplicate history entries by command string, keeping the most recent occurrence.
 /// Entries are already sorted by timestamp (most recent first), so we keep the first
 /// occurrence of each command, accumulating the number of collapsed duplicates in
@@ -333,16 +343,6 @@ fn highlight_command_with_indices(
     spans
 }
 
-fn format_duration(secs: i64) -> String {
-    if secs < 60 {
-        format!("{secs}s")
-    } else if secs < 3600 {
-        format!("{}m {}s", secs / 60, secs % 60)
-    } else {
-        format!("{}h {}m {}s", secs / 3600, (secs % 3600) / 60, secs % 60)
-    }
-}
-
 /// The pure recall UI state machine: entries, cache, query, selection,
 /// scroll, keymaps, and frame rendering -- everything except the terminal
 /// itself. Constructible without a tty, so key handling, scroll math, cache
@@ -362,11 +362,23 @@ pub struct RecallState {
     keymap_mode: KeymapMode,
     show_preview: bool,
     preview_config: PreviewConfig,
+    preview_mode: PreviewMode,
+    /// Session neighbours of the last previewed row, keyed by that row's id
+    /// so the pane only queries when the selection actually changes.
+    session_context: Option<(i64, Vec<HistoryEntry>)>,
+    /// Row id to center the session preview on when it differs from the
+    /// deduplicated entry's (most recent) row -- set by jumping to a
+    /// neighbour so repeated jumps keep walking the same session.
+    context_anchor: Option<(String, i64)>,
     shell_mode: bool, // When true, outputs command for shell execution; when false, prints details
     db_query_used: Option<RecallQuery>, // Tracks the query passed to the last DB load
     entry_cache: EntryCache, // Previous candidate sets, restored on backspace
     flash_until: Option<Instant>, // For visual feedback on unrecognized keys
     status_message: Option<(String, Instant)>,
+    /// Entries marked for bulk actions, keyed by command text so marks
+    /// survive query edits and filter reloads.
+    marked: Vec<HistoryEntry>,
+    confirm_bulk_delete: bool, // Waiting for y/n before deleting all marked entries
     needs_redraw: bool,
 }
 
@@ -506,7 +518,11 @@ impl RecallTui {
                         KeyAction::Select | KeyAction::Edit | KeyAction::EditBeginning => {
                             self.terminal.cleanup()?;
                             if !self.state.shell_mode {
-                                self.state.print_entry_details();
+                                if self.state.marked.is_empty() {
+                                    self.state.print_entry_details();
+                                } else if let Some(script) = self.state.get_output_command() {
+                                    println!("{script}");
+                                }
                                 return Ok(None);
                             }
                             let prefix = match action {
@@ -516,7 +532,7 @@ impl RecallTui {
                             };
                             let result = self
                                 .state
-                                .get_selected_command()
+                                .get_output_command()
                                 .map(|cmd| format!("{prefix}:{cmd}"));
                             return Ok(result);
                         }
@@ -583,11 +599,16 @@ impl RecallState {
             keymap_mode: config.initial_keymap_mode(),
             show_preview: config.show_preview,
             preview_config: config.preview.clone(),
+            preview_mode: PreviewMode::Details,
+            session_context: None,
+            context_anchor: None,
             shell_mode,
             db_query_used,
             entry_cache: EntryCache::default(),
             flash_until: None,
             status_message: None,
+            marked: Vec::new(),
+            confirm_bulk_delete: false,
             needs_redraw: true,
         })
     }
@@ -618,7 +639,22 @@ impl RecallState {
 
     fn results_height(&self) -> usize {
         let base = self.term_height.saturating_sub(2) as usize;
-        if self.show_preview { base.saturating_sub(PREVIEW_HEIGHT) } else { base }
+        if self.show_preview { base.saturating_sub(self.preview_height()) } else { base }
+    }
+
+    fn preview_height(&self) -> usize {
+        match self.preview_mode {
+            PreviewMode::Details => PREVIEW_HEIGHT,
+            // Separator plus the selection and its neighbours on each side
+            PreviewMode::Session => 2 * self.context_lines() + 2,
+        }
+    }
+
+    /// Commands shown on each side of the selection in the session preview:
+    /// as configured, but leaving at least half the screen to the results.
+    fn context_lines(&self) -> usize {
+        let max_preview = self.term_height.saturating_sub(2) as usize / 2;
+        self.preview_config.context_lines.min(max_preview.saturating_sub(2) / 2)
     }
 
     fn adjust_scroll_for_selection(&mut self) {
@@ -761,9 +797,16 @@ impl RecallState {
         self.flash_until.is_some_and(|until| Instant::now() < until)
     }
 
-    /// Stage the selected command for a clipboard copy. The terminal adapter
-    /// performs the actual OSC 52 write; state only records the feedback.
+    /// Stage the selected command (or every marked command, one per line)
+    /// for a clipboard copy. The terminal adapter performs the actual OSC 52
+    /// write; state only records the feedback.
     fn copy_selected(&mut self) -> KeyAction {
+        if !self.marked.is_empty() {
+            let text = self.marked_commands().join("\n");
+            let msg = format!("(copied {})", self.marked.len());
+            self.status_message = Some((msg, Instant::now() + Duration::from_secs(1)));
+            return KeyAction::Copy(text);
+        }
         match self.get_selected_command() {
             Some(cmd) => {
                 self.status_message =
@@ -774,6 +817,69 @@ impl RecallState {
         }
     }
 
+    fn is_marked(&self, entry: &HistoryEntry) -> bool {
+        self.marked.iter().any(|m| m.command == entry.command)
+    }
+
+    /// Toggle the mark on the selected entry and advance to the next older
+    /// one, so consecutive commands can be marked by holding the key.
+    fn toggle_mark_selected(&mut self) {
+        let Some(entry) = self
+            .filtered_indices
+            .get(self.selected_index)
+            .and_then(|(idx, _)| self.entries.get(*idx))
+        else {
+            return;
+        };
+        if let Some(pos) = self.marked.iter().position(|m| m.command == entry.command) {
+            self.marked.remove(pos);
+        } else {
+            self.marked.push(entry.clone());
+        }
+        self.move_selection_up();
+    }
+
+    /// Marked commands in the order they were originally run (oldest
+    /// first), which is the order a runbook or script wants them in.
+    fn marked_commands(&self) -> Vec<String> {
+        let mut marked: Vec<&HistoryEntry> = self.marked.iter().collect();
+        marked.sort_by_key(|e| e.timestamp.unwrap_or(i64::MIN));
+        marked.into_iter().map(|e| e.command.clone()).collect()
+    }
+
+    /// Ask for confirmation before Ctrl-K deletes every marked entry; the
+    /// next key answers the prompt (see `handle_key`).
+    fn request_bulk_delete(&mut self) {
+        self.confirm_bulk_delete = true;
+        self.status_message = None;
+    }
+
+    /// Delete all database rows for every marked command, then drop them
+    /// from the live set. Cached candidate sets are cleared for the same
+    /// reason as in `delete_selected_entry`.
+    fn delete_marked_entries(&mut self) {
+        let mut deleted_rows = 0;
+        let mut deleted: Vec<String> = Vec::new();
+        for entry in std::mem::take(&mut self.marked) {
+            match self.engine.delete_entries_by_command(&entry.command) {
+                Ok(n) => {
+                    deleted_rows += n;
+                    deleted.push(entry.command);
+                }
+                // Keep failed deletions marked so the user can retry.
+                Err(_) => self.marked.push(entry),
+            }
+        }
+        if !self.marked.is_empty() {
+            self.flash();
+        }
+        self.entry_cache.clear();
+        self.entries.retain(|e| !deleted.contains(&e.command));
+        self.update_filtered_indices();
+        self.status_message =
+            Some((format!("(deleted {deleted_rows})"), Instant::now() + Duration::from_secs(1)));
+    }
+
     /// Delete all database rows matching the selected entry's command text,
     /// since the recall list is deduplicated by command.
     fn delete_selected_entry(&mut self) {
@@ -820,6 +926,85 @@ impl RecallState {
         self.reload_entries();
     }
 
+    fn toggle_preview_mode(&mut self) {
+        self.preview_mode = match self.preview_mode {
+            PreviewMode::Details => PreviewMode::Session,
+            PreviewMode::Session => PreviewMode::Details,
+        };
+        self.show_preview = true;
+        self.adjust_scroll_for_selection();
+    }
+
+    /// The row the session preview centers on: the jump anchor if it still
+    /// matches the selection, otherwise the selected entry's own row.
+    fn context_row_id(&self) -> Option<i64> {
+        let entry = self
+            .filtered_indices
+            .get(self.selected_index)
+            .and_then(|(idx, _)| self.entries.get(*idx))?;
+        match self.context_anchor {
+            Some((ref cmd, id)) if *cmd == entry.command => Some(id),
+            _ => Some(entry.id),
+        }
+    }
+
+    /// Load the selection's session neighbours unless they are already cached.
+    fn refresh_session_context(&mut self) {
+        let Some(id) = self.context_row_id() else {
+            self.session_context = None;
+            return;
+        };
+        if self.session_context.as_ref().is_some_and(|(cached, _)| *cached == id) {
+            return;
+        }
+        let n = self.context_lines();
+        let rows = self.engine.session_context(id, n).unwrap_or_default();
+        self.session_context = Some((id, rows));
+    }
+
+    /// Move the selection to the command run `delta` steps away from the
+    /// current one in its session (negative = earlier). Clears the query if
+    /// the neighbour is filtered out; reports it if it isn't loaded at all
+    /// (e.g. outside the directory filter or the result window).
+    fn jump_to_session_neighbor(&mut self, delta: isize) {
+        self.refresh_session_context();
+        let Some((anchor, ref rows)) = self.session_context else {
+            self.flash();
+            return;
+        };
+        let target = rows
+            .iter()
+            .position(|e| e.id == anchor)
+            .and_then(|pos| pos.checked_add_signed(delta))
+            .and_then(|pos| rows.get(pos))
+            .cloned();
+        let Some(target) = target else {
+            self.flash();
+            return;
+        };
+
+        let find = |state: &Self| {
+            state
+                .filtered_indices
+                .iter()
+                .position(|(idx, _)| state.entries[*idx].command == target.command)
+        };
+        let mut found = find(self);
+        if found.is_none() && !self.query.is_empty() {
+            self.query.clear();
+            self.update_filtered_indices();
+            found = find(self);
+        }
+        let Some(index) = found else {
+            self.status_message =
+                Some(("(not in list)".to_string(), Instant::now() + Duration::from_secs(1)));
+            return;
+        };
+        self.selected_index = index;
+        self.adjust_scroll_for_selection();
+        self.context_anchor = Some((target.command, target.id));
+    }
+
     fn print_entry_details(&self) {
         let Some(entry) = self
             .filtered_indices
@@ -895,7 +1080,25 @@ impl RecallState {
             .map(|e| e.command.clone())
     }
 
+    /// The text handed back on Enter/Tab: the selected command, or every
+    /// marked command when there are marks -- chained with `&&` for the
+    /// shell's line buffer, one per line otherwise.
+    fn get_output_command(&self) -> Option<String> {
+        if self.marked.is_empty() {
+            return self.get_selected_command();
+        }
+        let separator = if self.shell_mode { " && " } else { "\n" };
+        Some(self.marked_commands().join(separator))
+    }
+
     fn handle_key(&mut self, key: KeyEvent) -> Result<KeyAction, Box<dyn std::error::Error>> {
+        if self.confirm_bulk_delete {
+            self.confirm_bulk_delete = false;
+            if matches!(key.code, KeyCode::Char('y' | 'Y')) && key.modifiers.is_empty() {
+                self.delete_marked_entries();
+            }
+            return Ok(KeyAction::Continue);
+        }
         match self.keymap_mode {
             KeymapMode::Emacs => self.handle_key_emacs(key),
             KeymapMode::VimInsert => self.handle_key_vim_insert(key),
@@ -915,6 +1118,14 @@ impl RecallState {
                 self.move_selection_up();
                 Some(KeyAction::Continue)
             }
+            KeyCode::Up if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.jump_to_session_neighbor(-1);
+                Some(KeyAction::Continue)
+            }
+            KeyCode::Down if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.jump_to_session_neighbor(1);
+                Some(KeyAction::Continue)
+            }
             KeyCode::Up => {
                 self.move_selection_up();
                 Some(KeyAction::Continue)
@@ -952,6 +1163,10 @@ impl RecallState {
             KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 Some(KeyAction::Edit)
             }
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.toggle_preview_mode();
+                Some(KeyAction::Continue)
+            }
             KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 Some(KeyAction::EditBeginning)
             }
@@ -959,7 +1174,17 @@ impl RecallState {
                 Some(self.copy_selected())
             }
             KeyCode::Char('k') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.delete_selected_entry();
+                if self.marked.is_empty() {
+                    self.delete_selected_entry();
+                } else {
+                    self.request_bulk_delete();
+                }
+                Some(KeyAction::Continue)
+            }
+            // Terminals report Ctrl-Space as NUL, which crossterm decodes
+            // as a control-modified space.
+            KeyCode::Char(' ') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.toggle_mark_selected();
                 Some(KeyAction::Continue)
             }
             _ => None,
@@ -1077,6 +1302,10 @@ impl RecallState {
                 self.delete_last_char();
                 Ok(KeyAction::Continue)
             }
+            KeyCode::Char(' ') => {
+                self.toggle_mark_selected();
+                Ok(KeyAction::Continue)
+            }
             KeyCode::Char(_) if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 self.flash();
                 Ok(KeyAction::Continue)
@@ -1088,6 +1317,7 @@ impl RecallState {
     // Helper methods for selection movement and query editing
 
     fn move_selection_up(&mut self) {
+        self.context_anchor = None;
         if self.selected_index + 1 < self.filtered_indices.len() {
             self.selected_index += 1;
             self.adjust_scroll_for_selection();
@@ -1095,6 +1325,7 @@ impl RecallState {
     }
 
     fn move_selection_down(&mut self) {
+        self.context_anchor = None;
         if self.selected_index > 0 {
             self.selected_index -= 1;
             self.adjust_scroll_for_selection();
@@ -1102,6 +1333,7 @@ impl RecallState {
     }
 
     fn page_up(&mut self) {
+        self.context_anchor = None;
         let page = self.results_height().saturating_sub(2);
         let max_index = self.filtered_indices.len().saturating_sub(1);
         self.selected_index = (self.selected_index + page).min(max_index);
@@ -1109,6 +1341,7 @@ impl RecallState {
     }
 
     fn page_down(&mut self) {
+        self.context_anchor = None;
         let page = self.results_height().saturating_sub(2);
         self.selected_index = self.selected_index.saturating_sub(page);
         self.adjust_scroll_for_selection();
@@ -1246,6 +1479,72 @@ impl RecallState {
         Ok(())
     }
 
+    /// Draw the session-context preview: the selection centered with up to
+    /// `context_lines()` commands from the same session above and below it.
+    fn draw_session_preview<W: Write>(
+        &self,
+        w: &mut W,
+        start_y: u16,
+        width: u16,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let n = self.context_lines();
+
+        queue!(w, MoveTo(0, start_y), Clear(ClearType::CurrentLine))?;
+        queue!(w, SetForegroundColor(Color::DarkGrey))?;
+        let label = "─ session (Alt-↑↓ jump) ";
+        let fill = (width as usize).saturating_sub(label.chars().count());
+        write!(w, "{label}{}", "─".repeat(fill))?;
+        queue!(w, ResetColor)?;
+        for row in 0..=2 * n {
+            queue!(w, MoveTo(0, start_y + 1 + row as u16), Clear(ClearType::CurrentLine))?;
+        }
+
+        let Some((anchor, ref rows)) = self.session_context else {
+            return Ok(());
+        };
+        let Some(anchor_pos) = rows.iter().position(|e| e.id == anchor) else {
+            return Ok(());
+        };
+
+        for (pos, entry) in rows.iter().enumerate() {
+            // Rows fetched before the terminal shrank may not fit.
+            if pos + n < anchor_pos || pos > anchor_pos + n {
+                continue;
+            }
+            // Keep the selection on the middle row even at session edges.
+            let row = n + pos - anchor_pos;
+            let is_anchor = pos == anchor_pos;
+            queue!(w, MoveTo(0, start_y + 1 + row as u16))?;
+
+            let time_str = entry
+                .timestamp
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                .map(|dt| dt.format("%H:%M:%S").to_string())
+                .unwrap_or_else(|| "--:--:--".to_string());
+            let failed = entry.exit_status.is_some_and(|s| s != 0);
+
+            if is_anchor {
+                queue!(w, SetAttribute(Attribute::Bold))?;
+                write!(w, "> ")?;
+            } else {
+                queue!(w, SetForegroundColor(Color::DarkGrey))?;
+                write!(w, "  ")?;
+            }
+            write!(w, "{time_str}  ")?;
+            if failed {
+                queue!(w, SetForegroundColor(Color::Red))?;
+            } else if !is_anchor {
+                queue!(w, ResetColor)?;
+            }
+            let max_len = (width as usize).saturating_sub(12);
+            let cmd: String = sanitize_for_display(&entry.command).chars().take(max_len).collect();
+            write!(w, "{cmd}")?;
+            queue!(w, SetAttribute(Attribute::Reset), ResetColor)?;
+        }
+
+        Ok(())
+    }
+
     /// Render a full frame into `w` using the current dimensions (see
     /// `set_dimensions`). Generic over the writer so tests can render into a
     /// buffer; the live path hands in a buffered /dev/tty.
@@ -1283,6 +1582,7 @@ impl RecallState {
             let entry = &self.entries[idx];
             let time_str = format_relative_time(entry.timestamp);
             let is_selected = entry_index == self.selected_index;
+            let is_marked = self.is_marked(entry);
 
             // Calculate quick-select number (1-9) relative to selection
             // Alt-1 = selected, Alt-2 = selected+1 (next older), etc.
@@ -1304,14 +1604,21 @@ impl RecallState {
                 queue!(w, ResetColor)?;
                 if is_selected {
                     queue!(w, SetBackgroundColor(Color::DarkGrey))?;
-                    write!(w, ">")?;
-                } else {
-                    write!(w, " ")?;
+                }
+            } else {
+                write!(w, " ")?;
+            }
+            if is_marked {
+                queue!(w, SetForegroundColor(Color::Green))?;
+                write!(w, "*")?;
+                queue!(w, ResetColor)?;
+                if is_selected {
+                    queue!(w, SetBackgroundColor(Color::DarkGrey))?;
                 }
             } else if is_selected {
-                write!(w, " >")?;
+                write!(w, ">")?;
             } else {
-                write!(w, "  ")?;
+                write!(w, " ")?;
             }
 
             queue!(w, SetForegroundColor(Color::DarkGrey))?;
@@ -1341,7 +1648,7 @@ impl RecallState {
 
             // Sanitize and truncate command to fit (handle UTF-8 safely)
             let safe_cmd = sanitize_for_display(&entry.command);
-            let prefix_len = 9; // "n>" + " XXx  "
+            let prefix_len = 9; // "n>" (or "n*" when marked) + " XXx  "
             let max_cmd_len = term_width.saturating_sub(prefix_len + suffix_len as u16) as usize;
 
             // Render command with highlighted fuzzy matches
@@ -1373,7 +1680,13 @@ impl RecallState {
 
         // Draw preview pane if enabled
         if self.show_preview {
-            self.draw_preview(w, preview_start_y, term_width)?;
+            match self.preview_mode {
+                PreviewMode::Details => self.draw_preview(w, preview_start_y, term_width)?,
+                PreviewMode::Session => {
+                    self.refresh_session_context();
+                    self.draw_session_preview(w, preview_start_y, term_width)?;
+                }
+            }
         }
 
         // Draw input line
@@ -1401,7 +1714,11 @@ impl RecallState {
             }
             FilterMode::Global => "[Global]".to_string(),
         };
-        let mode_str = format!("{host_str} {dir_str}");
+        let mode_str = if self.marked.is_empty() {
+            format!("{host_str} {dir_str}")
+        } else {
+            format!("[{} marked] {host_str} {dir_str}", self.marked.len())
+        };
         let mode_x = term_width.saturating_sub(mode_str.len() as u16 + 1);
         queue!(w, MoveTo(mode_x, input_y), SetForegroundColor(Color::Cyan))?;
         write!(w, "{mode_str}")?;
@@ -1417,13 +1734,18 @@ impl RecallState {
         }
         let help_text = match self.keymap_mode {
             KeymapMode::Emacs => {
-                "↑↓/^R Nav  Enter Run  ^A/^E Edit  ^G Dir  ^H Host  ^C/^D Quit  Alt-1-9"
+                "↑↓/^R Nav  Enter Run  ^A/^E Edit  ^G Dir  ^H Host  ^␣ Mark  ^O Ctx  ^C/^D Quit  Alt-1-9"
             }
             KeymapMode::VimInsert | KeymapMode::VimNormal => {
-                "j/k Nav  Enter Run  ^A/^E Edit  ^G Dir  ^H Host  ^C/^D Quit  Esc Mode  Alt-1-9"
+                "j/k Nav  Enter Run  ^A/^E Edit  ^G Dir  ^H Host  ␣ Mark  ^O Ctx  ^C/^D Quit  Esc Mode  Alt-1-9"
             }
         };
-        write!(w, "{help_text}")?;
+        if self.confirm_bulk_delete {
+            queue!(w, ResetColor, SetForegroundColor(Color::Yellow))?;
+            write!(w, "Delete all history for {} marked commands? (y/n)", self.marked.len())?;
+        } else {
+            write!(w, "{help_text}")?;
+        }
         queue!(w, ResetColor)?;
 
         // Show status message if active
@@ -1910,6 +2232,73 @@ mod tests {
         assert!(state.status_message.is_some(), "copy should surface '(copied)' feedback");
     }
 
+    #[test]
+    fn test_marked_commands_output_in_run_order() {
+        // Newest first in the list: "deploy" is selected initially.
+        let mut state = test_state(&["restart db", "ls", "check logs", "deploy"]);
+        let ctrl_space = |state: &mut super::RecallState| {
+            press(state, KeyCode::Char(' '), KeyModifiers::CONTROL)
+        };
+        ctrl_space(&mut state); // marks "deploy", advances to "check logs"
+        press(&mut state, KeyCode::Up, KeyModifiers::NONE);
+        press(&mut state, KeyCode::Up, KeyModifiers::NONE); // past "ls"
+        ctrl_space(&mut state); // marks "restart db"
+        type_str(&mut state, "check");
+        ctrl_space(&mut state); // marks "check logs" under a different query
+        assert_eq!(state.marked.len(), 3);
+
+        assert_eq!(press(&mut state, KeyCode::Enter, KeyModifiers::NONE), super::KeyAction::Select);
+        assert_eq!(
+            state.get_output_command().as_deref(),
+            Some("restart db && check logs && deploy"),
+            "shell mode chains marked commands oldest-first"
+        );
+        state.shell_mode = false;
+        assert_eq!(state.get_output_command().as_deref(), Some("restart db\ncheck logs\ndeploy"));
+        match press(&mut state, KeyCode::Char('y'), KeyModifiers::CONTROL) {
+            super::KeyAction::Copy(text) => assert_eq!(text, "restart db\ncheck logs\ndeploy"),
+            other => panic!("expected Copy action, got {other:?}"),
+        }
+
+        // Toggling again unmarks.
+        ctrl_space(&mut state);
+        assert_eq!(state.marked.len(), 2);
+        assert!(!state.marked.iter().any(|e| e.command == "check logs"));
+    }
+
+    #[test]
+    fn test_vim_normal_space_marks() {
+        let mut state = test_state(&["one", "two"]);
+        state.keymap_mode = KeymapMode::VimNormal;
+        press(&mut state, KeyCode::Char(' '), KeyModifiers::NONE);
+        assert_eq!(state.marked.len(), 1);
+        assert!(state.query.is_empty(), "space in normal mode must not edit the query");
+    }
+
+    #[test]
+    fn test_bulk_delete_requires_confirmation() {
+        let mut state = test_state(&["keep", "drop-a", "drop-b"]);
+        press(&mut state, KeyCode::Char(' '), KeyModifiers::CONTROL);
+        press(&mut state, KeyCode::Char(' '), KeyModifiers::CONTROL);
+        assert_eq!(state.marked.len(), 2);
+
+        // Anything but 'y' cancels the prompt and keeps everything.
+        press(&mut state, KeyCode::Char('k'), KeyModifiers::CONTROL);
+        assert!(state.confirm_bulk_delete);
+        press(&mut state, KeyCode::Char('n'), KeyModifiers::NONE);
+        assert!(!state.confirm_bulk_delete);
+        assert_eq!(visible_commands(&state).len(), 3);
+        assert!(state.query.is_empty(), "the answer key must not reach the query");
+
+        press(&mut state, KeyCode::Char('k'), KeyModifiers::CONTROL);
+        press(&mut state, KeyCode::Char('y'), KeyModifiers::NONE);
+        assert_eq!(visible_commands(&state), ["keep"]);
+        assert!(state.marked.is_empty());
+        let remaining =
+            state.engine.load_entries(FilterMode::Global, HostFilter::AllHosts, None).unwrap();
+        assert_eq!(remaining.len(), 1, "marked rows must be deleted from the database");
+    }
+
     #[test]
     fn test_selection_stays_visible_and_scroll_clamps() {
         let commands: Vec<String> = (0..50).map(|i| format!("cmd-{i:02}")).collect();
@@ -1982,6 +2371,56 @@ mod tests {
         state.draw(&mut zero).unwrap();
     }
 
+    #[test]
+    fn test_session_preview_jumps_between_neighbours() {
+        let mut state = test_state(&["cd proj", "export X=1", "make", "make test"]);
+        let normal_height = state.results_height();
+        press(&mut state, KeyCode::Char('o'), KeyModifiers::CONTROL);
+        assert_eq!(state.preview_mode, super::PreviewMode::Session);
+        assert!(state.results_height() < normal_height, "context pane is taller");
+
+        let mut frame: Vec<u8> = Vec::new();
+        state.draw(&mut frame).unwrap();
+        let rendered = String::from_utf8_lossy(&frame);
+        assert!(rendered.contains("session"), "context pane header drawn");
+        assert!(rendered.contains("export X=1"), "neighbour drawn in the pane");
+
+        // A filtered-out neighbour clears the query so it can be selected.
+        type_str(&mut state, "test");
+        assert_eq!(visible_commands(&state), ["make test"]);
+        press(&mut state, KeyCode::Up, KeyModifiers::ALT);
+        assert!(state.query.is_empty());
+        assert_eq!(state.get_selected_command().as_deref(), Some("make"));
+        press(&mut state, KeyCode::Up, KeyModifiers::ALT);
+        press(&mut state, KeyCode::Up, KeyModifiers::ALT);
+        assert_eq!(state.get_selected_command().as_deref(), Some("cd proj"));
+        // At the start of the session there is nothing earlier to jump to.
+        press(&mut state, KeyCode::Up, KeyModifiers::ALT);
+        assert_eq!(state.get_selected_command().as_deref(), Some("cd proj"));
+        press(&mut state, KeyCode::Down, KeyModifiers::ALT);
+        assert_eq!(state.get_selected_command().as_deref(), Some("export X=1"));
+
+        press(&mut state, KeyCode::Char('o'), KeyModifiers::CONTROL);
+        assert_eq!(state.results_height(), normal_height);
+    }
+
+    #[test]
+    fn test_session_preview_fits_the_terminal() {
+        let mut state = test_state(&["cd proj", "make", "make test"]);
+        state.preview_config.context_lines = 1000;
+        press(&mut state, KeyCode::Char('o'), KeyModifiers::CONTROL);
+        assert_eq!(state.preview_height(), 2 * 4 + 2, "at most half of 24 rows");
+        assert!(state.results_height() >= state.preview_height());
+        let mut frame: Vec<u8> = Vec::new();
+        state.draw(&mut frame).unwrap();
+
+        // Shrinking below what was fetched must not break the drawing.
+        state.set_dimensions(80, 8);
+        assert_eq!(state.preview_height(), 2);
+        let mut frame: Vec<u8> = Vec::new();
+        state.draw(&mut frame).unwrap();
+    }
+
     fn cache_snapshot(db_query: Option<&str>, n_entries: usize) -> super::EntrySnapshot {
         use crate::recall::engine::HistoryEntry;
         let entries = (0..n_entries)
@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use clap::{Parser, ValueEnum};
+use rusqlite::Connection;
+
+use crate::{
+    stats::{Count, Scope, text, top_programs, truncated},
+    timesheet::active_intervals,
+};
+
+/// Gaps between commands longer than this count as idle, not project time
+/// (the same default as `pxh timesheet --idle`).
+const IDLE_SECS: i64 = 15 * 60;
+/// Minimum runs in the previous period for a tool to count as abandoned.
+const ABANDONED_MIN_RUNS: i64 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReportCommand {
+    #[clap(
+        long,
+        value_name = "PERIOD",
+        help = "Year (2025) or month (2025-03) to report on; defaults to the current year"
+    )]
+    pub period: Option<String>,
+    #[clap(long, value_enum, default_value_t = ReportFormat::Markdown, help = "Output format")]
+    pub format: ReportFormat,
+    #[clap(
+        long,
+        help = "Leave out paths, hostnames and command arguments so the report can be shared"
+    )]
+    pub anonymize: bool,
+    #[clap(
+        long,
+        value_name = "HOST",
+        value_delimiter = ',',
+        help = "Only include commands from these hosts; this host's name includes its configured aliases"
+    )]
+    pub host: Vec<String>,
+    #[clap(short, long, default_value_t = 10, help = "Entries to show in each list")]
+    pub limit: usize,
+}
+
+// A distinct command line and how often it ran.
+type CommandCount = (Vec<u8>, i64);
+
+struct Entry {
+    session_id: i64,
+    command: Vec<u8>,
+    working_directory: Option<Vec<u8>>,
+    hostname: Option<Vec<u8>>,
+    start: i64,
+    end: Option<i64>,
+    exit_status: Option<i64>,
+}
+
+/// A rendered-format-independent report: a title, summary lines and tables.
+struct Report {
+    title: String,
+    summary: Vec<(String, String)>,
+    sections: Vec<Section>,
+}
+
+struct Section {
+    title: &'static str,
+    note: &'static str,
+    headers: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+impl ReportCommand {
+    pub fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let label = self.period.clone().unwrap_or_else(|| Local::now().format("%Y").to_string());
+        let (start, end) = pxh::helpers::parse_period(&label)?;
+        let report = self.build(&conn, &label, start, end)?;
+        match self.format {
+            ReportFormat::Markdown => print!("{}", render_markdown(&report)),
+            ReportFormat::Html => print!("{}", render_html(&report)),
+        }
+        Ok(())
+    }
+
+    fn scope(&self, start: Option<i64>, end: i64) -> Result<Scope, Box<dyn std::error::Error>> {
+        let start = start.map(|t| format!("@{t}"));
+        Scope::new(start.as_deref(), Some(&format!("@{end}")), &self.host, false)
+    }
+
+    // Per-command run counts within [start, end) (or everything before end).
+    fn command_counts(
+        &self,
+        conn: &Connection,
+        start: Option<i64>,
+        end: i64,
+    ) -> Result<Vec<CommandCount>, Box<dyn std::error::Error>> {
+        self.scope(start, end)?.query(
+            conn,
+            "SELECT full_command, COUNT(*) FROM command_history WHERE {scope} GROUP BY full_command",
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+    }
+
+    fn build(
+        &self,
+        conn: &Connection,
+        label: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Report, Box<dyn std::error::Error>> {
+        let entries: Vec<Entry> = self.scope(Some(start), end)?.query(
+            conn,
+            "SELECT session_id, full_command, working_directory, hostname, start_unix_timestamp,
+                    end_unix_timestamp, exit_status
+               FROM command_history WHERE {scope} ORDER BY session_id, start_unix_timestamp, id",
+            |r| {
+                Ok(Entry {
+                    session_id: r.get(0)?,
+                    command: r.get(1)?,
+                    working_directory: r.get(2)?,
+                    hostname: r.get(3)?,
+                    start: r.get(4)?,
+                    end: r.get(5)?,
+                    exit_status: r.get(6)?,
+                })
+            },
+        )?;
+
+        let mut by_command: HashMap<&[u8], i64> = HashMap::new();
+        for entry in &entries {
+            *by_command.entry(&entry.command).or_default() += 1;
+        }
+        let by_command: Vec<(Vec<u8>, i64)> =
+            by_command.into_iter().map(|(c, n)| (c.to_vec(), n)).collect();
+        let tools = top_programs(&by_command, usize::MAX);
+
+        let mut sections = vec![Section {
+            title: "Most-used tools",
+            note: "",
+            headers: &["Tool", "Runs"],
+            rows: tools.iter().take(self.limit).map(count_row).collect(),
+        }];
+
+        let used_before: HashSet<String> =
+            top_programs(&self.command_counts(conn, None, start)?, usize::MAX)
+                .into_iter()
+                .map(|c| c.name)
+                .collect();
+        sections.push(Section {
+            title: "Newly adopted tools",
+            note: "First used during this period.",
+            headers: &["Tool", "Runs"],
+            rows: tools
+                .iter()
+                .filter(|tool| !used_before.contains(&tool.name))
+                .take(self.limit)
+                .map(count_row)
+                .collect(),
+        });
+
+        let used_now: HashSet<&str> = tools.iter().map(|c| c.name.as_str()).collect();
+        let previous = self.command_counts(conn, Some(start - (end - start)), start)?;
+        sections.push(Section {
+            title: "Abandoned tools",
+            note: "Used in the previous period of the same length, but not in this one.",
+            headers: &["Tool", "Runs before"],
+            rows: top_programs(&previous, usize::MAX)
+                .iter()
+                .filter(|c| c.count >= ABANDONED_MIN_RUNS && !used_now.contains(c.name.as_str()))
+                .take(self.limit)
+                .map(count_row)
+                .collect(),
+        });
+
+        let mut projects: Vec<(String, i64)> = project_time(&entries).into_iter().collect();
+        projects.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sections.push(Section {
+            title: "Time per project",
+            note: "Estimated from session activity, ignoring idle gaps over 15 minutes.",
+            headers: &["Project", "Time"],
+            rows: projects
+                .iter()
+                .enumerate()
+                .take(self.limit)
+                .map(|(i, (project, secs))| {
+                    let project =
+                        if self.anonymize { format!("project {}", i + 1) } else { project.clone() };
+                    vec![project, pxh::helpers::format_duration(*secs)]
+                })
+                .collect(),
+        });
+
+        sections.push(Section {
+            title: "Notable failures",
+            note: "",
+            headers: &["Command", "Failures", "Runs"],
+            rows: self.failures(&entries),
+        });
+
+        let days = active_days(&entries);
+        let mut summary = vec![
+            ("Commands".to_string(), entries.len().to_string()),
+            (
+                "Sessions".to_string(),
+                entries.iter().map(|e| e.session_id).collect::<HashSet<_>>().len().to_string(),
+            ),
+            ("Active days".to_string(), days.len().to_string()),
+        ];
+        let with_status: Vec<i64> = entries.iter().filter_map(|e| e.exit_status).collect();
+        if !with_status.is_empty() {
+            let ok = with_status.iter().filter(|s| **s == 0).count();
+            let pct = 100.0 * ok as f64 / with_status.len() as f64;
+            summary.push(("Success rate".to_string(), format!("{pct:.1}%")));
+        }
+        if let Some((first, last)) = longest_streak(&days) {
+            let length = (last - first).num_days() + 1;
+            summary.push((
+                "Longest streak".to_string(),
+                format!(
+                    "{length} days ({} to {})",
+                    first.format("%Y-%m-%d"),
+                    last.format("%Y-%m-%d")
+                ),
+            ));
+        }
+        if let Some((day, count)) = days.iter().max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0))) {
+            summary.push((
+                "Busiest day".to_string(),
+                format!("{} ({count} commands)", day.format("%A %Y-%m-%d")),
+            ));
+        }
+        if !self.anonymize {
+            let hosts: HashSet<String> = entries.iter().map(|e| text(e.hostname.clone())).collect();
+            let mut hosts: Vec<String> = hosts.into_iter().collect();
+            hosts.sort();
+            summary.push(("Hosts".to_string(), hosts.join(", ")));
+        }
+
+        Ok(Report { title: format!("Shell history report: {label}"), summary, sections })
+    }
+
+    // Commands with the most failures; only the program when anonymizing.
+    fn failures(&self, entries: &[Entry]) -> Vec<Vec<String>> {
+        let mut failures: HashMap<String, (i64, i64)> = HashMap::new();
+        for entry in entries {
+            let Some(status) = entry.exit_status else { continue };
+            let key = if self.anonymize {
+                match pxh::helpers::command_program(&entry.command) {
+                    Some(program) => program,
+                    None => continue,
+                }
+            } else {
+                truncated(&text(Some(entry.command.clone())), 80)
+            };
+            let counts = failures.entry(key).or_default();
+            counts.0 += i64::from(status != 0);
+            counts.1 += 1;
+        }
+        let mut failures: Vec<(String, (i64, i64))> =
+            failures.into_iter().filter(|(_, (failed, _))| *failed > 0).collect();
+        failures.sort_by(|a, b| b.1.0.cmp(&a.1.0).then_with(|| a.0.cmp(&b.0)));
+        failures
+            .into_iter()
+            .take(self.limit)
+            .map(|(command, (failed, runs))| vec![command, failed.to_string(), runs.to_string()])
+            .collect()
+    }
+}
+
+fn count_row(count: &Count) -> Vec<String> {
+    vec![count.name.clone(), count.count.to_string()]
+}
+
+// Commands per local calendar day.
+fn active_days(entries: &[Entry]) -> BTreeMap<NaiveDate, i64> {
+    let mut days = BTreeMap::new();
+    for entry in entries {
+        if let Some(time) = Local.timestamp_opt(entry.start, 0).single() {
+            *days.entry(time.date_naive()).or_default() += 1;
+        }
+    }
+    days
+}
+
+// First and last day of the longest run of consecutive active days.
+fn longest_streak(days: &BTreeMap<NaiveDate, i64>) -> Option<(NaiveDate, NaiveDate)> {
+    let mut best: Option<(NaiveDate, NaiveDate)> = None;
+    let mut current: Option<(NaiveDate, NaiveDate)> = None;
+    for &day in days.keys() {
+        current = match current {
+            Some((first, last)) if last.succ_opt() == Some(day) => Some((first, day)),
+            _ => Some((day, day)),
+        };
+        let (first, last) = current.expect("just set");
+        if best.is_none_or(|(b_first, b_last)| last - first > b_last - b_first) {
+            best = current;
+        }
+    }
+    best
+}
+
+/// Attribute session time to projects, counting active time the same way as
+/// `pxh timesheet` with an idle limit of `IDLE_SECS`. `entries` must be
+/// ordered by session, then start time.
+fn project_time(entries: &[Entry]) -> HashMap<String, i64> {
+    let spans: Vec<_> = entries.iter().map(|e| (e.session_id, e.start, e.end)).collect();
+    let mut roots: HashMap<&[u8], String> = HashMap::new();
+    let mut time: HashMap<String, i64> = HashMap::new();
+    for (start, end, i) in active_intervals(&spans, IDLE_SECS) {
+        let Some(dir) = entries[i].working_directory.as_deref() else { continue };
+        let project = roots
+            .entry(dir)
+            .or_insert_with(|| project_root(Path::new(std::ffi::OsStr::from_bytes(dir))));
+        *time.entry(project.clone()).or_default() += end - start;
+    }
+    time.retain(|_, secs| *secs > 0);
+    time
+}
+
+// The nearest enclosing git checkout, else the directory itself, shown
+// relative to the home directory.
+fn project_root(dir: &Path) -> String {
+    display_path(git_root(dir).unwrap_or(dir))
+}
+
+/// The nearest enclosing git checkout. A checkout at $HOME itself
+/// (dotfiles) is not treated as a project.
+pub(crate) fn git_root(dir: &Path) -> Option<&Path> {
+    let home = home::home_dir();
+    dir.ancestors()
+        .take_while(|d| Some(*d) != home.as_deref() && d.parent().is_some())
+        .find(|d| d.join(".git").exists())
+}
+
+/// A path with the home directory abbreviated to `~`.
+pub(crate) fn display_path(path: &Path) -> String {
+    let home = home::home_dir();
+    match home.and_then(|home| path.strip_prefix(&home).ok().map(PathBuf::from)) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".into(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+fn render_markdown(report: &Report) -> String {
+    let cell = |s: &str| s.replace('|', "\\|").replace('\n', " ");
+    let mut out = format!("# {}\n\n", report.title);
+    for (key, value) in &report.summary {
+        out.push_str(&format!("- **{key}:** {}\n", cell(value)));
+    }
+    for section in &report.sections {
+        out.push_str(&format!("\n## {}\n\n", section.title));
+        if !section.note.is_empty() {
+            out.push_str(&format!("_{}_\n\n", section.note));
+        }
+        if section.rows.is_empty() {
+            out.push_str("None.\n");
+            continue;
+        }
+        out.push_str(&format!("| {} |\n", section.headers.join(" | ")));
+        let align: Vec<&str> =
+            (0..section.headers.len()).map(|i| if i == 0 { "---" } else { "---:" }).collect();
+        out.push_str(&format!("| {} |\n", align.join(" | ")));
+        for row in &section.rows {
+            let cells: Vec<String> = row.iter().map(|c| cell(c)).collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn render_html(report: &Report) -> String {
+    let title = html_escape(&report.title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; }} \
+         table {{ border-collapse: collapse; }} th, td {{ padding: 0.2em 0.8em; }} \
+         td:not(:first-child) {{ text-align: right; }} code {{ white-space: pre-wrap; }}</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    for (key, value) in &report.summary {
+        out.push_str(&format!(
+            "<li><strong>{}:</strong> {}</li>\n",
+            html_escape(key),
+            html_escape(value)
+        ));
+    }
+    out.push_str("</ul>\n");
+    for section in &report.sections {
+        out.push_str(&format!("<h2>{}</h2>\n", html_escape(section.title)));
+        if !section.note.is_empty() {
+            out.push_str(&format!("<p><em>{}</em></p>\n", html_escape(section.note)));
+        }
+        if section.rows.is_empty() {
+            out.push_str("<p>None.</p>\n");
+            continue;
+        }
+        out.push_str("<table>\n<tr>");
+        for header in section.headers {
+            out.push_str(&format!("<th>{}</th>", html_escape(header)));
+        }
+        out.push_str("</tr>\n");
+        for row in &section.rows {
+            out.push_str("<tr>");
+            for (i, value) in row.iter().enumerate() {
+                if i == 0 {
+                    out.push_str(&format!("<td><code>{}</code></td>", html_escape(value)));
+                } else {
+                    out.push_str(&format!("<td>{}</td>", html_escape(value)));
+                }
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,511 @@
+//! Append-only segment format for directory sync (`[sync] format`).
+//!
+//! Instead of rewriting one whole database per machine on every sync, a
+//! machine appends small immutable segment files to its own
+//! `<host>.segments/` directory. Each segment is a gzip-compressed pxh
+//! database holding only what changed since the previous one, encrypted
+//! like any other sync file when a key is configured, so readers merge it
+//! with the same code, secret filter and tombstone handling as a `.db`.
+//!
+//! `manifest.json` lists the segments in order with a checksum of each
+//! file's stored bytes, and is replaced atomically only after the segment
+//! it names is on disk. Readers remember which segment names they have
+//! merged and fetch only the rest; a segment that is missing or fails its
+//! checksum (a cloud client mid-upload) is retried next sync.
+//!
+//! Segments are never modified. Every [`COMPACT_AFTER`] segments, or when
+//! the machine has deleted history since the last segment, the writer
+//! instead writes one snapshot of its whole history, points the manifest at
+//! it alone and removes the older files -- deleted rows don't linger in old
+//! segments.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use blake2::{Blake2b, Digest, digest::consts::U32};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::{
+    encryption::{self, SyncKey},
+    export_policy::ExportPolicy,
+};
+
+/// Suffix of a machine's segment directory inside the sync directory.
+pub const SEGMENTS_SUFFIX: &str = ".segments";
+const MANIFEST: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+/// Segments a writer accumulates before folding them into one snapshot.
+pub const COMPACT_AFTER: usize = 20;
+
+/// One segment file, as listed in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentEntry {
+    pub name: String,
+    /// Increases with every segment written, snapshots included, so a name
+    /// is never reused.
+    pub seq: u64,
+    /// `MAX(id)` of the writer's database when the segment was written.
+    pub last_id: i64,
+    pub rows: usize,
+    /// BLAKE2b-256 of the file's bytes as stored, in hex.
+    pub checksum: String,
+    /// Whether the segment holds the writer's whole history.
+    #[serde(default)]
+    pub snapshot: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub machine_id: Option<u64>,
+    pub hostname: String,
+    pub segments: Vec<SegmentEntry>,
+    /// Rows still running when the last segment was written; the next
+    /// segment carries them again once sealed.
+    #[serde(default)]
+    pub unsealed_ids: Vec<i64>,
+    /// Tombstones recorded from this time on are in no segment yet.
+    #[serde(default)]
+    pub tombstones_since: i64,
+}
+
+impl Manifest {
+    /// The writer's `MAX(id)` as of its latest segment.
+    pub fn last_id(&self) -> Option<i64> {
+        self.segments.last().map(|s| s.last_id)
+    }
+}
+
+/// A host's segment directory in the sync directory `dir`.
+pub fn segment_dir(dir: &Path, hostname: &str) -> PathBuf {
+    dir.join(format!("{hostname}{SEGMENTS_SUFFIX}"))
+}
+
+/// Whether `path` is a segment directory with a manifest to read.
+pub fn is_segment_dir(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(SEGMENTS_SUFFIX))
+        && path.join(MANIFEST).is_file()
+}
+
+/// The segment directory holding the same host's history as the sync file
+/// `path` (`<host>.db` or `<host>.db.enc`), if there is one. Readers prefer
+/// the segments: both are written while a machine migrates.
+pub fn segments_for_sync_file(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let host =
+        name.strip_suffix(encryption::ENCRYPTED_SUFFIX).or_else(|| name.strip_suffix(".db"))?;
+    let dir = segment_dir(path.parent()?, host);
+    is_segment_dir(&dir).then_some(dir)
+}
+
+pub fn read_manifest(seg_dir: &Path) -> Result<Option<Manifest>, Box<dyn std::error::Error>> {
+    let data = match fs::read(seg_dir.join(MANIFEST)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let manifest: Manifest = serde_json::from_slice(&data)
+        .map_err(|e| format!("{}: invalid manifest: {e}", seg_dir.display()))?;
+    if manifest.version > MANIFEST_VERSION {
+        return Err(format!(
+            "{}: manifest version {} is newer than this pxh supports; upgrade pxh",
+            seg_dir.display(),
+            manifest.version
+        )
+        .into());
+    }
+    Ok(Some(manifest))
+}
+
+fn write_manifest(seg_dir: &Path, manifest: &Manifest) -> Result<(), Box<dyn std::error::Error>> {
+    let mut temp_file = NamedTempFile::new_in(seg_dir)?;
+    serde_json::to_writer_pretty(&mut temp_file, manifest)?;
+    temp_file.write_all(b"\n")?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(seg_dir.join(MANIFEST))?;
+    Ok(())
+}
+
+fn checksum(data: &[u8]) -> String {
+    Blake2b::<U32>::digest(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Append a segment with this machine's history since its last one to
+/// `seg_dir`, compacting when due. `policy` applies as to any export.
+/// Returns the new segment, or `None` when there was nothing to write.
+pub fn write_segment(
+    conn: &Connection,
+    seg_dir: &Path,
+    hostname: &str,
+    key: Option<&SyncKey>,
+    policy: &ExportPolicy,
+    now: i64,
+) -> Result<Option<SegmentEntry>, Box<dyn std::error::Error>> {
+    fs::create_dir_all(seg_dir)?;
+    let mut manifest = read_manifest(seg_dir)?.unwrap_or_default();
+    let machine_id = crate::read_local_machine_id(conn);
+    let max_id: i64 =
+        conn.query_row("SELECT COALESCE(MAX(id), 0) FROM command_history", (), |r| r.get(0))?;
+    let deleted_since: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM tombstones WHERE deleted_unix_timestamp >= ?)",
+        [manifest.tombstones_since],
+        |r| r.get(0),
+    )?;
+    let snapshot = match manifest.last_id() {
+        None => true,
+        // A restored or replaced database: its ids no longer line up.
+        Some(last_id) => {
+            last_id > max_id
+                || manifest.machine_id != machine_id
+                || manifest.segments.len() >= COMPACT_AFTER
+                || deleted_since
+                // Encryption turned on (or off): replace every segment
+                // rather than leave plaintext ones behind.
+                || manifest.segments.iter().any(|s| s.name.ends_with(".enc") != key.is_some())
+        }
+    };
+
+    // Built outside the sync directory, like every other export.
+    let staging = tempfile::Builder::new().prefix("pxh-segment-").suffix(".db").tempfile()?;
+    if snapshot {
+        crate::sync::write_delta_database(conn, staging.path(), i64::MIN, None)?;
+    } else {
+        let last_id = manifest.last_id().unwrap_or_default();
+        let mut condition = "id > ?".to_string();
+        if !manifest.unsealed_ids.is_empty() {
+            let ids: Vec<String> = manifest.unsealed_ids.iter().map(i64::to_string).collect();
+            condition
+                .push_str(&format!(" OR (exit_status IS NOT NULL AND id IN ({}))", ids.join(",")));
+        }
+        let rows = crate::sync::write_history_database(
+            conn,
+            staging.path(),
+            &condition,
+            [last_id],
+            false,
+        )?;
+        if rows == 0 {
+            return Ok(None);
+        }
+    }
+    policy.apply_to_file(staging.path(), now)?;
+    let seq = manifest.segments.last().map_or(1, |s| s.seq + 1);
+    let entry = store_segment(seg_dir, staging.path(), seq, max_id, snapshot, key)?;
+
+    if snapshot {
+        manifest.segments.clear();
+    }
+    manifest.segments.push(entry.clone());
+    manifest.version = MANIFEST_VERSION;
+    manifest.machine_id = machine_id;
+    manifest.hostname = hostname.to_string();
+    manifest.unsealed_ids = crate::sync::unsealed_ids(conn)?;
+    manifest.tombstones_since = now;
+    write_manifest(seg_dir, &manifest)?;
+    remove_unlisted(seg_dir, &manifest)?;
+    Ok(Some(entry))
+}
+
+/// Compress (and encrypt, with `key`) the history database `db_path` into
+/// segment `seq` of `seg_dir`.
+fn store_segment(
+    seg_dir: &Path,
+    db_path: &Path,
+    seq: u64,
+    last_id: i64,
+    snapshot: bool,
+    key: Option<&SyncKey>,
+) -> Result<SegmentEntry, Box<dyn std::error::Error>> {
+    let rows: i64 =
+        Connection::open(db_path)?
+            .query_row("SELECT COUNT(*) FROM command_history", (), |r| r.get(0))?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&fs::read(db_path)?)?;
+    let mut data = encoder.finish()?;
+    let mut name = format!("{seq:08}.db.gz");
+    if let Some(key) = key {
+        data = encryption::encrypt(key, &data)?;
+        name.push_str(".enc");
+    }
+    let entry = SegmentEntry {
+        name,
+        seq,
+        last_id,
+        rows: rows as usize,
+        checksum: checksum(&data),
+        snapshot,
+    };
+    let mut temp_file = NamedTempFile::new_in(seg_dir)?;
+    temp_file.write_all(&data)?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(seg_dir.join(&entry.name))?;
+    Ok(entry)
+}
+
+/// Fold every segment `seg_dir`'s manifest lists, in order, into one
+/// history database: the writer's history as a reader that merged them all
+/// has it. Fails if any segment can't be read.
+pub fn combine_segments(
+    seg_dir: &Path,
+    key: Option<&SyncKey>,
+) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+    let manifest = read_manifest(seg_dir)?.ok_or("no segment manifest")?;
+    let combined = tempfile::Builder::new().prefix("pxh-sync-").suffix(".db").tempfile()?;
+    let conn = Connection::open(combined.path())?;
+    crate::initialize_base_schema(&conn)?;
+    crate::run_schema_migrations(&conn)?;
+
+    use std::os::unix::ffi::OsStrExt;
+    for entry in &manifest.segments {
+        let segment =
+            open_segment(seg_dir, entry, key).map_err(|e| format!("{}: {e}", entry.name))?;
+        crate::run_schema_migrations(&Connection::open(segment.path())?)?;
+        conn.execute("ATTACH DATABASE ? AS segment", (segment.path().as_os_str().as_bytes(),))?;
+        // Later segments carry sealed versions of rows seen running before.
+        let result = conn.execute_batch(
+            "INSERT OR REPLACE INTO main.command_history (
+                 id, session_id, full_command, shellname, hostname, username,
+                 working_directory, exit_status, start_unix_timestamp, end_unix_timestamp,
+                 machine_id)
+             SELECT id, session_id, full_command, shellname, hostname, username,
+                 working_directory, exit_status, start_unix_timestamp, end_unix_timestamp,
+                 machine_id
+             FROM segment.command_history;
+             INSERT OR IGNORE INTO main.tombstones (row_hash, deleted_unix_timestamp)
+             SELECT row_hash, deleted_unix_timestamp FROM segment.tombstones;",
+        );
+        conn.execute("DETACH DATABASE segment", ())?;
+        result?;
+    }
+    Ok(combined)
+}
+
+/// Replace every segment in `seg_dir` with one snapshot of `db_path` (say,
+/// a scrubbed [`combine_segments`] copy), encrypted if the segments were.
+pub fn replace_with_snapshot(
+    seg_dir: &Path,
+    db_path: &Path,
+    key: Option<&SyncKey>,
+) -> Result<SegmentEntry, Box<dyn std::error::Error>> {
+    let mut manifest = read_manifest(seg_dir)?.ok_or("no segment manifest")?;
+    let key = if manifest.segments.iter().any(|s| s.name.ends_with(".enc")) {
+        Some(key.ok_or("encrypted; set [sync] key_file in the config to rewrite it")?)
+    } else {
+        None
+    };
+    let seq = manifest.segments.last().map_or(1, |s| s.seq + 1);
+    let last_id = manifest.last_id().unwrap_or_default();
+    let entry = store_segment(seg_dir, db_path, seq, last_id, true, key)?;
+    manifest.segments = vec![entry.clone()];
+    write_manifest(seg_dir, &manifest)?;
+    remove_unlisted(seg_dir, &manifest)?;
+    Ok(entry)
+}
+
+/// Delete segments the manifest no longer lists (compacted away, or left by
+/// an interrupted write). Dotfiles are in-progress temp files.
+fn remove_unlisted(seg_dir: &Path, manifest: &Manifest) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(seg_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+        if name == MANIFEST || name.starts_with('.') || !path.is_file() {
+            continue;
+        }
+        if !manifest.segments.iter().any(|s| s.name == name) {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Verify, decrypt and decompress a segment into a private temp database,
+/// deleted when dropped.
+pub fn open_segment(
+    seg_dir: &Path,
+    entry: &SegmentEntry,
+    key: Option<&SyncKey>,
+) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+    // Manifests come from other machines: never follow a name out of the
+    // segment directory.
+    if Path::new(&entry.name).file_name().is_none_or(|n| n != entry.name.as_str()) {
+        return Err(format!("invalid segment name {:?}", entry.name).into());
+    }
+    let mut data = fs::read(seg_dir.join(&entry.name))?;
+    if checksum(&data) != entry.checksum {
+        return Err("checksum mismatch (incomplete or corrupted)".into());
+    }
+    if entry.name.ends_with(".enc") {
+        let key = key.ok_or("encrypted; set [sync] key_file in the config to read it")?;
+        data = encryption::decrypt(key, &data)?;
+    }
+    let mut plaintext = Vec::new();
+    GzDecoder::new(data.as_slice()).read_to_end(&mut plaintext)?;
+    let mut temp_file = tempfile::Builder::new().prefix("pxh-sync-").suffix(".db").tempfile()?;
+    temp_file.write_all(&plaintext)?;
+    temp_file.flush()?;
+    Ok(temp_file)
+}
+
+fn consumed_key(seg_dir: &Path) -> String {
+    let dir = fs::canonicalize(seg_dir).unwrap_or_else(|_| seg_dir.to_path_buf());
+    format!("sync_segments_{}", dir.display())
+}
+
+/// Names of the segments in `seg_dir` already merged into `conn`.
+pub fn consumed_segments(conn: &Connection, seg_dir: &Path) -> BTreeSet<String> {
+    crate::get_setting(conn, &consumed_key(seg_dir))
+        .ok()
+        .flatten()
+        .map(|names| names.to_string().lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Record the segments of `seg_dir` merged so far, keeping only those its
+/// manifest still lists.
+pub fn set_consumed_segments(
+    conn: &Connection,
+    seg_dir: &Path,
+    manifest: &Manifest,
+    consumed: &BTreeSet<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let names: Vec<&str> = manifest
+        .segments
+        .iter()
+        .map(|s| s.name.as_str())
+        .filter(|name| consumed.contains(*name))
+        .collect();
+    crate::set_setting(conn, &consumed_key(seg_dir), &names.join("\n").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::initialize_base_schema(&conn).unwrap();
+        crate::run_schema_migrations(&conn).unwrap();
+        conn
+    }
+
+    fn insert(conn: &Connection, cmd: &str, exit_status: Option<i64>) -> i64 {
+        let now = chrono::Utc::now().timestamp();
+        conn.execute(
+            "INSERT INTO command_history (session_id, full_command, shellname, hostname,
+                                          exit_status, start_unix_timestamp)
+             VALUES (1, CAST(? AS blob), 'zsh', CAST('alpha' AS blob), ?, ?)",
+            rusqlite::params![cmd, exit_status, now],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn segment_commands(seg_dir: &Path, entry: &SegmentEntry) -> Vec<(String, Option<i64>)> {
+        let file = open_segment(seg_dir, entry, None).unwrap();
+        let conn = Connection::open(file.path()).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT CAST(full_command AS text), exit_status FROM command_history ORDER BY id",
+            )
+            .unwrap();
+        stmt.query_map((), |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_segments_append_seal_and_compact() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let seg_dir = segment_dir(dir.path(), "alpha");
+        let conn = history_db();
+        let policy = ExportPolicy::default();
+        let write = || write_segment(&conn, &seg_dir, "alpha", None, &policy, 1000).unwrap();
+
+        insert(&conn, "ls", Some(0));
+        let running = insert(&conn, "sleep 100", None);
+        let first = write().unwrap();
+        assert!(first.snapshot);
+        assert_eq!(first.rows, 2);
+        assert!(write().is_none(), "nothing new, no segment");
+
+        // A newly sealed row travels again along with new rows.
+        conn.execute("UPDATE command_history SET exit_status = 0 WHERE id = ?", [running]).unwrap();
+        insert(&conn, "make", Some(2));
+        let second = write().unwrap();
+        assert!(!second.snapshot);
+        assert_eq!(
+            segment_commands(&seg_dir, &second),
+            vec![("sleep 100".to_string(), Some(0)), ("make".to_string(), Some(2))]
+        );
+        let manifest = read_manifest(&seg_dir).unwrap().unwrap();
+        assert_eq!(manifest.segments, vec![first.clone(), second.clone()]);
+        assert!(manifest.unsealed_ids.is_empty());
+
+        // A corrupted segment is refused rather than merged.
+        fs::write(seg_dir.join(&second.name), b"garbage").unwrap();
+        assert!(open_segment(&seg_dir, &second, None).is_err());
+
+        // Deleting history compacts into a snapshot without the deleted row.
+        crate::sync::delete_with_tombstones(&conn, "full_command = CAST(? AS blob)", ["ls"])
+            .unwrap();
+        let snapshot = write().unwrap();
+        assert!(snapshot.snapshot);
+        assert_eq!(snapshot.seq, 3);
+        assert_eq!(
+            segment_commands(&seg_dir, &snapshot),
+            vec![("sleep 100".to_string(), Some(0)), ("make".to_string(), Some(2))]
+        );
+        let mut files: Vec<String> = fs::read_dir(&seg_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["00000003.db.gz".to_string(), MANIFEST.to_string()]);
+    }
+
+    #[test]
+    fn test_enabling_encryption_replaces_plaintext_segments() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let seg_dir = segment_dir(dir.path(), "alpha");
+        let key_file = dir.path().join("sync.key");
+        fs::write(&key_file, "correct horse battery staple\n").unwrap();
+        let key = SyncKey::from_file(&key_file).unwrap();
+        let conn = history_db();
+        let policy = ExportPolicy::default();
+
+        insert(&conn, "ls", Some(0));
+        write_segment(&conn, &seg_dir, "alpha", None, &policy, 1000).unwrap();
+        insert(&conn, "make", Some(0));
+        let encrypted =
+            write_segment(&conn, &seg_dir, "alpha", Some(&key), &policy, 1000).unwrap().unwrap();
+        assert!(encrypted.snapshot);
+        assert_eq!(encrypted.rows, 2);
+        let files: Vec<String> = fs::read_dir(&seg_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != MANIFEST)
+            .collect();
+        assert_eq!(files, vec!["00000002.db.gz.enc".to_string()]);
+    }
+
+    #[test]
+    fn test_open_segment_rejects_names_outside_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let entry = SegmentEntry {
+            name: "../alpha.db".into(),
+            seq: 1,
+            last_id: 1,
+            rows: 1,
+            checksum: String::new(),
+            snapshot: true,
+        };
+        let err = open_segment(dir.path(), &entry, None).unwrap_err().to_string();
+        assert!(err.contains("invalid segment name"), "{err}");
+    }
+}
@@ -58,6 +58,15 @@ _pxh_check_run() {
     fi
 }
 
+# Offer a learned correction for unknown commands # PXH_FIX_HANDLER
+command_not_found_handle() { # PXH_FIX_HANDLER
+    local fix # PXH_FIX_HANDLER
+    printf 'bash: %s: command not found\n' "$1" >&2 # PXH_FIX_HANDLER
+    fix=$(pxh --db "$PXH_DB_PATH" fix -- "$@" 2>/dev/null) # PXH_FIX_HANDLER
+    [[ -n "$fix" ]] && printf 'pxh: did you mean: %s\n' "$fix" >&2 # PXH_FIX_HANDLER
+    return 127 # PXH_FIX_HANDLER
+} # PXH_FIX_HANDLER
+
 _pxh_init() {
     export PXH_SESSION_ID=$(_pxh_random)
     export PXH_HOSTNAME=$(hostname -s)
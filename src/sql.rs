@@ -0,0 +1,158 @@
+use std::{
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use rusqlite::{
+    Connection, OpenFlags,
+    types::{Value, ValueRef},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum SqlFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Parser, Debug)]
+#[clap(after_help = "Views: v_history (decoded text columns plus duration and start_time) and \
+v_sessions (per-session counts and time range).\n\
+Functions: REGEXP, text(blob), duration(start, end), fmt_time(ts [, strftime format]).")]
+pub struct SqlCommand {
+    #[clap(long, value_enum, default_value_t = SqlFormat::Table, help = "Output format")]
+    pub format: SqlFormat,
+    #[clap(long, help = "Suppress headers")]
+    pub suppress_headers: bool,
+    #[clap(
+        required = true,
+        help = "A single SQL statement, e.g. \"SELECT command, duration FROM v_history ORDER BY duration DESC LIMIT 10\"; multiple words are joined with spaces"
+    )]
+    pub query: Vec<String>,
+}
+
+impl SqlCommand {
+    pub fn go(&self, db_path: &Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+        let path = db_path.as_ref().ok_or("Database not defined; use --db or PXH_DB_PATH")?;
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(Duration::from_millis(5000))?;
+        pxh::register_regexp(&conn)?;
+        pxh::register_sql_helpers(&conn)?;
+
+        // Report SQL errors by message; the Debug form is noisy for a user's query.
+        let mut stmt = conn.prepare(&self.query.join(" ")).map_err(|e| e.to_string())?;
+        // The connection is read-only anyway; this just gives a clearer error.
+        if !stmt.readonly() {
+            return Err("pxh sql only runs read-only statements".into());
+        }
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let rows: Vec<Vec<Value>> = stmt
+            .query_map([], |row| (0..columns.len()).map(|i| row.get_ref(i).map(owned)).collect())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+
+        match self.format {
+            SqlFormat::Table => self.print_table(&columns, &rows),
+            SqlFormat::Json => print_json(&columns, &rows)?,
+            SqlFormat::Csv => self.print_csv(&columns, &rows)?,
+        }
+        Ok(())
+    }
+
+    fn print_table(&self, columns: &[String], rows: &[Vec<Value>]) {
+        let mut table = prettytable::Table::new();
+        table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+        if !self.suppress_headers {
+            table.set_titles(prettytable::Row::new(
+                columns.iter().map(|c| prettytable::Cell::new(c).style_spec("Fc")).collect(),
+            ));
+        }
+        for row in rows {
+            table.add_row(prettytable::Row::new(
+                row.iter()
+                    .map(|v| match v {
+                        Value::Null => prettytable::Cell::new("NULL").style_spec("Fd"),
+                        Value::Integer(_) | Value::Real(_) => {
+                            prettytable::Cell::new(&value_text(v)).style_spec("r")
+                        }
+                        _ => prettytable::Cell::new(&value_text(v)),
+                    })
+                    .collect(),
+            ));
+        }
+        table.printstd();
+    }
+
+    fn print_csv(&self, columns: &[String], rows: &[Vec<Value>]) -> io::Result<()> {
+        let mut out = BufWriter::new(io::stdout().lock());
+        if !self.suppress_headers {
+            let header: Vec<String> = columns.iter().map(|c| pxh::csv_escape(c)).collect();
+            writeln!(out, "{}", header.join(","))?;
+        }
+        for row in rows {
+            let fields: Vec<String> = row.iter().map(|v| pxh::csv_escape(&value_text(v))).collect();
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        out.flush()
+    }
+}
+
+// One object per row keyed by column name. BLOBs become strings when they
+// are valid UTF-8 and byte arrays otherwise, as in `pxh export`.
+fn print_json(columns: &[String], rows: &[Vec<Value>]) -> Result<(), Box<dyn std::error::Error>> {
+    let objects: Vec<serde_json::Map<String, serde_json::Value>> = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .zip(row)
+                .map(|(column, value)| {
+                    let value = match value {
+                        Value::Null => serde_json::Value::Null,
+                        Value::Integer(i) => (*i).into(),
+                        Value::Real(f) => (*f).into(),
+                        Value::Text(s) => s.clone().into(),
+                        Value::Blob(b) => match std::str::from_utf8(b) {
+                            Ok(s) => s.into(),
+                            Err(_) => b.clone().into(),
+                        },
+                    };
+                    (column.clone(), value)
+                })
+                .collect()
+        })
+        .collect();
+    let mut out = BufWriter::new(io::stdout().lock());
+    serde_json::to_writer(&mut out, &objects)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+// TEXT that is not valid UTF-8 (e.g. a CAST of a non-UTF-8 command) is
+// decoded lossily rather than failing the whole query.
+fn owned(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::Integer(i),
+        ValueRef::Real(f) => Value::Real(f),
+        ValueRef::Text(bytes) => Value::Text(String::from_utf8_lossy(bytes).into_owned()),
+        ValueRef::Blob(bytes) => Value::Blob(bytes.to_vec()),
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(s) => s.clone(),
+        Value::Blob(b) => String::from_utf8_lossy(b).into_owned(),
+    }
+}
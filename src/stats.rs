@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+
+use bstr::{BString, ByteSlice};
+use chrono::prelude::{Local, TimeZone};
+use clap::Parser;
+use rusqlite::{Connection, Row, ToSql};
+use serde::Serialize;
+
+const BAR_WIDTH: usize = 40;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[derive(Parser, Debug)]
+pub struct StatsCommand {
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Only count commands started at or after TIME (e.g. 2h, 3d, yesterday, monday, 2025-01-31)"
+    )]
+    pub since: Option<String>,
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Only count commands started before TIME (same forms as --since)"
+    )]
+    pub until: Option<String>,
+    #[clap(
+        long,
+        value_name = "HOST",
+        value_delimiter = ',',
+        help = "Only count commands from these hosts; this host's name includes its configured aliases"
+    )]
+    pub host: Vec<String>,
+    #[clap(short = 'H', long, help = "Only count commands run in the current directory")]
+    pub here: bool,
+    #[clap(short, long, default_value_t = 10, help = "Entries to show in each top-N list")]
+    pub limit: usize,
+    #[clap(long, help = "Output as JSON")]
+    pub json: bool,
+}
+
+/// A WHERE clause over `command_history` and its bound parameters, shared
+/// by every query so all sections describe the same set of commands.
+pub(crate) struct Scope {
+    clause: String,
+    params: Vec<Box<dyn ToSql>>,
+}
+
+impl Scope {
+    pub(crate) fn new(
+        since: Option<&str>,
+        until: Option<&str>,
+        hosts: &[String],
+        here: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut conditions = vec!["1".to_string()];
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        let now = Local::now();
+        if let Some(since) = since {
+            conditions.push("start_unix_timestamp >= ?".into());
+            params.push(Box::new(pxh::helpers::parse_time_spec(since, now)?));
+        }
+        if let Some(until) = until {
+            conditions.push("start_unix_timestamp < ?".into());
+            params.push(Box::new(pxh::helpers::parse_time_spec(until, now)?));
+        }
+        if !hosts.is_empty() {
+            let hosts = crate::expand_host_names(hosts);
+            crate::push_in_condition(
+                &mut conditions,
+                &mut params,
+                "hostname",
+                "CAST(? as blob)",
+                hosts,
+            );
+        }
+        if here {
+            use std::os::unix::ffi::OsStrExt;
+            conditions.push("working_directory = CAST(? as blob)".into());
+            params.push(Box::new(crate::here_directory().as_os_str().as_bytes().to_vec()));
+        }
+        Ok(Scope { clause: conditions.join(" AND "), params })
+    }
+
+    /// Run `sql`, with `{scope}` replaced by the WHERE clause, mapping each row.
+    pub(crate) fn query<T>(
+        &self,
+        conn: &Connection,
+        sql: &str,
+        f: impl FnMut(&Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare(&sql.replace("{scope}", &self.clause))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(self.params.iter()), f)?;
+        Ok(rows.collect::<Result<Vec<T>, _>>()?)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Stats {
+    commands: i64,
+    unique_commands: i64,
+    sessions: i64,
+    first_unix_timestamp: Option<i64>,
+    last_unix_timestamp: Option<i64>,
+    with_status: i64,
+    succeeded: i64,
+    shells: Vec<Count>,
+    top_commands: Vec<Count>,
+    top_programs: Vec<Count>,
+    top_directories: Vec<Count>,
+    hosts: Vec<Count>,
+    machines: Vec<Count>,
+    /// Commands started in each local hour, 00 to 23
+    hours: Vec<i64>,
+    /// Commands started on each local weekday, Monday first
+    weekdays: Vec<i64>,
+    failing_commands: Vec<Failing>,
+    longest_commands: Vec<Longest>,
+    busiest_sessions: Vec<Session>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Count {
+    pub(crate) name: String,
+    pub(crate) count: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Failing {
+    command: String,
+    failures: i64,
+    runs: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Longest {
+    command: String,
+    duration: i64,
+    start_unix_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Session {
+    session_id: i64,
+    hostname: String,
+    commands: i64,
+    start_unix_timestamp: Option<i64>,
+    end_unix_timestamp: Option<i64>,
+}
+
+pub(crate) fn text(bytes: Option<Vec<u8>>) -> String {
+    bytes.map(|b| BString::from(b).to_str_lossy().into_owned()).unwrap_or_default()
+}
+
+pub(crate) fn truncated(s: &str, max: usize) -> String {
+    if s.chars().count() > max {
+        format!("{}...", s.chars().take(max).collect::<String>())
+    } else {
+        s.to_string()
+    }
+}
+
+fn bar(count: i64, max: i64) -> String {
+    if count <= 0 || max <= 0 {
+        return String::new();
+    }
+    let width = (count as f64 / max as f64 * BAR_WIDTH as f64).round() as usize;
+    "█".repeat(width.max(1))
+}
+
+impl StatsCommand {
+    pub fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let scope =
+            Scope::new(self.since.as_deref(), self.until.as_deref(), &self.host, self.here)?;
+        let stats = self.collect(&conn, &scope)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        } else {
+            print_stats(&stats);
+        }
+        Ok(())
+    }
+
+    fn collect(
+        &self,
+        conn: &Connection,
+        scope: &Scope,
+    ) -> Result<Stats, Box<dyn std::error::Error>> {
+        let limit = self.limit as i64;
+        let mut stats = scope
+            .query(
+                conn,
+                "SELECT COUNT(*), COUNT(DISTINCT full_command), COUNT(DISTINCT session_id),
+                        MIN(start_unix_timestamp), MAX(start_unix_timestamp),
+                        COUNT(exit_status), COUNT(CASE WHEN exit_status = 0 THEN 1 END)
+                   FROM command_history WHERE {scope}",
+                |r| {
+                    Ok(Stats {
+                        commands: r.get(0)?,
+                        unique_commands: r.get(1)?,
+                        sessions: r.get(2)?,
+                        first_unix_timestamp: r.get(3)?,
+                        last_unix_timestamp: r.get(4)?,
+                        with_status: r.get(5)?,
+                        succeeded: r.get(6)?,
+                        ..Default::default()
+                    })
+                },
+            )?
+            .pop()
+            .unwrap_or_default();
+
+        let count = |r: &Row| Ok(Count { name: text(r.get(0)?), count: r.get(1)? });
+        stats.shells = scope.query(
+            conn,
+            "SELECT CAST(shellname AS BLOB), COUNT(*) AS cnt FROM command_history WHERE {scope}
+              GROUP BY shellname ORDER BY cnt DESC",
+            count,
+        )?;
+        let by_command: Vec<(Vec<u8>, i64)> = scope.query(
+            conn,
+            "SELECT full_command, COUNT(*) AS cnt FROM command_history WHERE {scope}
+              GROUP BY full_command ORDER BY cnt DESC, full_command",
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        stats.top_commands = by_command
+            .iter()
+            .take(self.limit)
+            .map(|(cmd, count)| Count { name: text(Some(cmd.clone())), count: *count })
+            .collect();
+        stats.top_programs = top_programs(&by_command, self.limit);
+        stats.top_directories = scope.query(
+            conn,
+            &format!(
+                "SELECT working_directory, COUNT(*) AS cnt FROM command_history
+                  WHERE {{scope}} AND working_directory IS NOT NULL
+                  GROUP BY working_directory ORDER BY cnt DESC, working_directory LIMIT {limit}"
+            ),
+            count,
+        )?;
+        stats.hosts = scope.query(
+            conn,
+            "SELECT hostname, COUNT(*) AS cnt FROM command_history WHERE {scope}
+              GROUP BY hostname ORDER BY cnt DESC",
+            count,
+        )?;
+        stats.machines = scope.query(
+            conn,
+            "SELECT machine_id, COUNT(*) AS cnt FROM command_history WHERE {scope}
+              GROUP BY machine_id ORDER BY cnt DESC",
+            |r| {
+                let name = match r.get::<_, Option<i64>>(0)? {
+                    Some(id) => (id as u64).to_string(),
+                    None => "(unknown)".into(),
+                };
+                Ok(Count { name, count: r.get(1)? })
+            },
+        )?;
+
+        stats.hours = vec![0; 24];
+        stats.weekdays = vec![0; 7];
+        let buckets: Vec<(i64, i64, i64)> = scope.query(
+            conn,
+            "SELECT CAST(strftime('%H', start_unix_timestamp, 'unixepoch', 'localtime') AS INTEGER),
+                    CAST(strftime('%w', start_unix_timestamp, 'unixepoch', 'localtime') AS INTEGER),
+                    COUNT(*)
+               FROM command_history WHERE {scope} AND start_unix_timestamp IS NOT NULL
+              GROUP BY 1, 2",
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )?;
+        for (hour, weekday, count) in buckets {
+            stats.hours[hour as usize % 24] += count;
+            // strftime's %w counts from Sunday
+            stats.weekdays[(weekday as usize + 6) % 7] += count;
+        }
+
+        stats.failing_commands = scope.query(
+            conn,
+            &format!(
+                "SELECT full_command, SUM(exit_status != 0) AS failures, COUNT(*)
+                   FROM command_history WHERE {{scope}} AND exit_status IS NOT NULL
+                  GROUP BY full_command HAVING failures > 0
+                  ORDER BY failures DESC, COUNT(*) LIMIT {limit}"
+            ),
+            |r| Ok(Failing { command: text(r.get(0)?), failures: r.get(1)?, runs: r.get(2)? }),
+        )?;
+        stats.longest_commands = scope.query(
+            conn,
+            &format!(
+                "SELECT full_command, end_unix_timestamp - start_unix_timestamp AS duration,
+                        start_unix_timestamp
+                   FROM command_history WHERE {{scope}} AND duration IS NOT NULL
+                  ORDER BY duration DESC LIMIT {limit}"
+            ),
+            |r| {
+                Ok(Longest {
+                    command: text(r.get(0)?),
+                    duration: r.get(1)?,
+                    start_unix_timestamp: r.get(2)?,
+                })
+            },
+        )?;
+        stats.busiest_sessions = scope.query(
+            conn,
+            &format!(
+                "SELECT session_id, MIN(hostname), COUNT(*) AS cnt,
+                        MIN(start_unix_timestamp), MAX(COALESCE(end_unix_timestamp, start_unix_timestamp))
+                   FROM command_history WHERE {{scope}}
+                  GROUP BY session_id ORDER BY cnt DESC LIMIT {limit}"
+            ),
+            |r| {
+                Ok(Session {
+                    session_id: r.get(0)?,
+                    hostname: text(r.get(1)?),
+                    commands: r.get(2)?,
+                    start_unix_timestamp: r.get(3)?,
+                    end_unix_timestamp: r.get(4)?,
+                })
+            },
+        )?;
+        Ok(stats)
+    }
+}
+
+// Fold per-command counts into per-program counts (see `command_program`).
+pub(crate) fn top_programs(by_command: &[(Vec<u8>, i64)], limit: usize) -> Vec<Count> {
+    let mut programs: HashMap<String, i64> = HashMap::new();
+    for (command, count) in by_command {
+        if let Some(program) = pxh::helpers::command_program(command) {
+            *programs.entry(program).or_default() += count;
+        }
+    }
+    let mut programs: Vec<Count> =
+        programs.into_iter().map(|(name, count)| Count { name, count }).collect();
+    programs.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    programs.truncate(limit);
+    programs
+}
+
+fn date(ts: Option<i64>) -> String {
+    ts.and_then(|ts| Local.timestamp_opt(ts, 0).single())
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "n/a".into())
+}
+
+fn print_counts(title: &str, counts: &[Count]) {
+    if counts.is_empty() {
+        return;
+    }
+    println!("\n{title}:");
+    for Count { name, count } in counts {
+        println!("  {count:>6}  {}", truncated(name, 60));
+    }
+}
+
+fn print_histogram<'a>(title: &str, buckets: impl Iterator<Item = (String, &'a i64)> + Clone) {
+    let max = buckets.clone().map(|(_, c)| *c).max().unwrap_or(0);
+    if max == 0 {
+        return;
+    }
+    println!("\n{title}:");
+    for (label, count) in buckets {
+        let line = format!("  {label:>3} {count:>6}  {}", bar(*count, max));
+        println!("{}", line.trim_end());
+    }
+}
+
+fn print_stats(stats: &Stats) {
+    println!("Commands:   {} total, {} unique", stats.commands, stats.unique_commands);
+    println!("Sessions:   {}", stats.sessions);
+
+    if let (Some(min), Some(max)) = (stats.first_unix_timestamp, stats.last_unix_timestamp) {
+        if let (Some(min_date), Some(max_date)) =
+            (Local.timestamp_opt(min, 0).single(), Local.timestamp_opt(max, 0).single())
+        {
+            let days = (max - min) / 86400;
+            println!(
+                "Period:     {} to {} ({days} days)",
+                min_date.format("%Y-%m-%d"),
+                max_date.format("%Y-%m-%d"),
+            );
+        } else {
+            println!("Period:     n/a (invalid timestamps in database)");
+        }
+    }
+
+    if stats.with_status > 0 {
+        let pct = 100.0 * stats.succeeded as f64 / stats.with_status as f64;
+        println!("Success:    {pct:.1}% (of {} commands with exit status)", stats.with_status);
+    }
+
+    if !stats.shells.is_empty() {
+        let shell_str: Vec<String> =
+            stats.shells.iter().map(|c| format!("{}: {}", c.name, c.count)).collect();
+        println!("Shells:     {}", shell_str.join("  "));
+    }
+
+    print_counts("Top commands", &stats.top_commands);
+    print_counts("Top programs", &stats.top_programs);
+    print_counts("Top directories", &stats.top_directories);
+    print_counts("Hosts", &stats.hosts);
+    if stats.machines.len() > 1 {
+        print_counts("Machines", &stats.machines);
+    }
+
+    print_histogram(
+        "Hour of day",
+        stats.hours.iter().enumerate().map(|(hour, count)| (format!("{hour:02}"), count)),
+    );
+    print_histogram(
+        "Weekday",
+        WEEKDAYS.iter().zip(&stats.weekdays).map(|(day, count)| (day.to_string(), count)),
+    );
+
+    if !stats.failing_commands.is_empty() {
+        println!("\nMost failing commands:");
+        for Failing { command, failures, runs } in &stats.failing_commands {
+            let rate = 100.0 * *failures as f64 / *runs as f64;
+            println!("  {failures:>6}/{runs:<6} {rate:>5.1}%  {}", truncated(command, 60));
+        }
+    }
+
+    if !stats.longest_commands.is_empty() {
+        println!("\nLongest running:");
+        for Longest { command, duration, start_unix_timestamp } in &stats.longest_commands {
+            println!(
+                "  {:>10}  {}  {}",
+                pxh::helpers::format_duration(*duration),
+                date(*start_unix_timestamp),
+                truncated(command, 60)
+            );
+        }
+    }
+
+    if !stats.busiest_sessions.is_empty() {
+        println!("\nBusiest sessions:");
+        for session in &stats.busiest_sessions {
+            let span = match (session.start_unix_timestamp, session.end_unix_timestamp) {
+                (Some(start), Some(end)) => pxh::helpers::format_duration(end - start),
+                _ => "n/a".into(),
+            };
+            println!(
+                "  {:>6}  {}  {:>10}  {} (session {})",
+                session.commands,
+                date(session.start_unix_timestamp),
+                span,
+                session.hostname,
+                session.session_id
+            );
+        }
+    }
+}
@@ -5,12 +5,20 @@
 //! optional secret filtering, an optional incremental watermark, and
 //! unsealed-row upgrades. Callers own transport and presentation; this module
 //! owns the merge semantics and reports what happened via [`MergeStats`].
+//! [`write_delta_database`] builds the other half of incremental sync: the
+//! rows a peer is missing given the watermark it holds for us, and
+//! [`preview_merge_from_file`] reports what a merge would do without doing it.
+//!
+//! Deletions propagate through tombstones: [`delete_with_tombstones`] records
+//! the identity hash of each row it deletes, every merge adopts the source's
+//! tombstones, and tombstoned rows are neither imported nor kept locally.
 
 use std::path::Path;
 use std::time::Duration;
 
+use blake2::{Blake2b, Digest, digest::consts::U32};
 use regex::bytes::RegexSet;
-use rusqlite::Connection;
+use rusqlite::{Connection, functions::FunctionFlags, types::ValueRef};
 
 /// What a merge did, for callers to present or assert on.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -21,6 +29,8 @@ pub struct MergeStats {
     pub added: i64,
     /// Rows skipped due to secret-pattern filtering.
     pub filtered: i64,
+    /// Rows already in main removed by tombstones newly learned from the source.
+    pub deleted: i64,
     /// `MAX(id)` from the source -- the next watermark for it.
     pub new_max_id: Option<i64>,
 }
@@ -29,6 +39,9 @@ pub struct MergeStats {
 /// rather than failing the merge.
 const WRITE_RETRY_BUDGET: Duration = Duration::from_secs(30);
 const CHUNK_SIZE: i64 = 5000;
+/// Unsealed rows older than this are from shells that died; they will
+/// never be sealed, so [`oldest_unsealed_start`] ignores them.
+const UNSEALED_LOOKBACK_SECS: i64 = 7 * 86400;
 
 /// Read the incremental-sync watermark recorded for a source machine, i.e.
 /// the highest source `id` a previous merge fully processed.
@@ -54,6 +67,433 @@ fn watermark_key(machine_id: u64) -> String {
     format!("sync_watermark_{machine_id}")
 }
 
+/// Note that a merge from `machine_id` just completed, and what it came
+/// through (a sync file, or `ssh:<host>`), for `pxh sync status`.
+pub fn record_sync_source(
+    conn: &Connection,
+    machine_id: u64,
+    via: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+    crate::set_setting(conn, &format!("sync_last_merged_{machine_id}"), &now.to_string().into())?;
+    crate::set_setting(conn, &format!("sync_via_{machine_id}"), &via.into())
+}
+
+/// Remember which sources last claimed the same `machine_id`, or with
+/// `None` forget that they did.
+pub fn record_duplicate_machine_id(
+    conn: &Connection,
+    machine_id: u64,
+    sources: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = format!("sync_duplicate_{machine_id}");
+    match sources {
+        Some(sources) => crate::set_setting(conn, &key, &sources.into()),
+        None => {
+            conn.execute("DELETE FROM settings WHERE key = ?", [key])?;
+            Ok(())
+        }
+    }
+}
+
+/// What this database knows about one machine whose history it holds or
+/// has synced from.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MachineStatus {
+    /// `None` for rows recorded without a machine id.
+    pub machine_id: Option<u64>,
+    pub hostnames: Vec<String>,
+    pub rows: i64,
+    /// Start time of the newest command.
+    pub last_command: Option<i64>,
+    /// Watermark we hold for the machine (see [`sync_watermark`]).
+    pub watermark: Option<i64>,
+    pub last_merged: Option<i64>,
+    /// Sync file or remote the last merge came through.
+    pub via: Option<String>,
+    /// Sources that last claimed this machine id at once.
+    pub duplicate: Option<String>,
+}
+
+/// Every machine in the history or the sync bookkeeping, ordered by
+/// machine id with rows lacking one last.
+pub fn machine_statuses(
+    conn: &Connection,
+) -> Result<Vec<MachineStatus>, Box<dyn std::error::Error>> {
+    let mut machines: std::collections::BTreeMap<Option<u64>, MachineStatus> =
+        std::collections::BTreeMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT machine_id, COUNT(*), MAX(start_unix_timestamp),
+                group_concat(DISTINCT CAST(hostname AS text))
+           FROM command_history GROUP BY machine_id",
+    )?;
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let machine_id = row.get::<_, Option<i64>>(0)?.map(|mid| mid as u64);
+        let hostnames: Option<String> = row.get(3)?;
+        machines.insert(
+            machine_id,
+            MachineStatus {
+                machine_id,
+                hostnames: hostnames
+                    .map(|h| h.split(',').map(str::to_string).collect())
+                    .unwrap_or_default(),
+                rows: row.get(1)?,
+                last_command: row.get(2)?,
+                ..Default::default()
+            },
+        );
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT key, CAST(value AS text) FROM settings
+          WHERE key LIKE 'sync_watermark_%' OR key LIKE 'sync_last_merged_%'
+             OR key LIKE 'sync_via_%' OR key LIKE 'sync_duplicate_%'",
+    )?;
+    let mut rows = stmt.query(())?;
+    while let Some(row) = rows.next()? {
+        let key: String = row.get(0)?;
+        let value: String = row.get(1)?;
+        let Some((field, mid)) = key.rsplit_once('_') else { continue };
+        let Ok(mid) = mid.parse::<u64>() else { continue };
+        let machine = machines
+            .entry(Some(mid))
+            .or_insert_with(|| MachineStatus { machine_id: Some(mid), ..Default::default() });
+        match field {
+            "sync_watermark" => machine.watermark = value.parse().ok(),
+            "sync_last_merged" => machine.last_merged = value.parse().ok(),
+            "sync_via" => machine.via = Some(value),
+            _ => machine.duplicate = Some(value),
+        }
+    }
+
+    let (with_id, without): (Vec<_>, Vec<_>) =
+        machines.into_values().partition(|m| m.machine_id.is_some());
+    Ok(with_id.into_iter().chain(without).collect())
+}
+
+/// The columns of the unique index, in the order [`row_identity_hash`] and
+/// the `pxh_row_hash` SQL function take them.
+const ROW_HASH_COLUMNS: &str =
+    "full_command, start_unix_timestamp, shellname, username, hostname, working_directory";
+
+/// Identity of a history row for tombstones: a BLAKE2b-256 hash of the
+/// columns of the unique index (NULL username, hostname and directory hash
+/// like empty ones, as the index treats them), so a deleted command can be
+/// recognized in other databases without keeping its text.
+pub fn row_identity_hash(
+    full_command: &[u8],
+    start_unix_timestamp: Option<i64>,
+    shellname: &[u8],
+    username: Option<&[u8]>,
+    hostname: Option<&[u8]>,
+    working_directory: Option<&[u8]>,
+) -> Vec<u8> {
+    let mut hasher = Blake2b::<U32>::new();
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    field(full_command);
+    match start_unix_timestamp {
+        Some(ts) => field(&ts.to_le_bytes()),
+        // Distinct from any 8-byte timestamp.
+        None => field(&[]),
+    }
+    field(shellname);
+    for optional in [username, hostname, working_directory] {
+        field(optional.unwrap_or_default());
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Register `pxh_row_hash(...)` over [`ROW_HASH_COLUMNS`] on `conn`.
+pub fn register_row_hash(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function("pxh_row_hash", 6, FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+        let bytes = |i: usize| -> rusqlite::Result<Option<Vec<u8>>> {
+            Ok(match ctx.get_raw(i) {
+                ValueRef::Null => None,
+                ValueRef::Text(b) | ValueRef::Blob(b) => Some(b.to_vec()),
+                other => {
+                    Some(other.as_i64().map(|n| n.to_string().into_bytes()).unwrap_or_default())
+                }
+            })
+        };
+        let start = ctx.get::<Option<i64>>(1)?;
+        Ok(row_identity_hash(
+            &bytes(0)?.unwrap_or_default(),
+            start,
+            &bytes(2)?.unwrap_or_default(),
+            bytes(3)?.as_deref(),
+            bytes(4)?.as_deref(),
+            bytes(5)?.as_deref(),
+        ))
+    })
+}
+
+/// Delete the history rows matching `condition` (an SQL expression over
+/// `command_history`, using `params`), leaving a tombstone for each so sync
+/// doesn't bring them back. Returns the number of rows deleted.
+pub fn delete_with_tombstones<P: rusqlite::Params + Clone>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    register_row_hash(conn)?;
+    conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO tombstones (row_hash, deleted_unix_timestamp)
+             SELECT pxh_row_hash({ROW_HASH_COLUMNS}), unixepoch()
+               FROM command_history WHERE {condition}"
+        ),
+        params.clone(),
+    )?;
+    Ok(conn.execute(&format!("DELETE FROM command_history WHERE {condition}"), params)?)
+}
+
+/// Start time of the oldest recent row still lacking an exit status. A peer
+/// sending a delta re-sends sealed rows from then on, so copies received
+/// while the commands were running get sealed by the merge.
+pub fn oldest_unsealed_start(conn: &Connection) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+    let cutoff = chrono::Utc::now().timestamp() - UNSEALED_LOOKBACK_SECS;
+    Ok(conn.query_row(
+        "SELECT MIN(start_unix_timestamp) FROM command_history
+          WHERE exit_status IS NULL AND start_unix_timestamp > ?",
+        [cutoff],
+        |r| r.get(0),
+    )?)
+}
+
+/// Write the rows a peer holding `watermark` for this database is missing to
+/// a new database file at `path`: every row above the watermark, plus sealed
+/// rows started at or after `unsealed_since` (the peer's
+/// [`oldest_unsealed_start`]) to seal its running copies. Row ids are
+/// preserved. Returns the number of rows written.
+pub fn write_delta_database(
+    conn: &Connection,
+    path: &Path,
+    watermark: i64,
+    unsealed_since: Option<i64>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // Tombstones are few and small; the peer always gets all of them.
+    write_history_database(
+        conn,
+        path,
+        "id > ?1 OR (exit_status IS NOT NULL AND start_unix_timestamp >= ?2)",
+        (watermark, unsealed_since),
+        true,
+    )
+}
+
+/// Write the rows of `main.command_history` matching `condition` to a new
+/// database file at `path`, ids preserved, along with every tombstone when
+/// `tombstones` is set. Returns the number of rows written.
+pub fn write_history_database<P: rusqlite::Params>(
+    conn: &Connection,
+    path: &Path,
+    condition: &str,
+    params: P,
+    tombstones: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    {
+        let delta = Connection::open(path)?;
+        crate::initialize_base_schema(&delta)?;
+        crate::run_schema_migrations(&delta)?;
+    }
+
+    use std::os::unix::ffi::OsStrExt;
+    conn.execute("ATTACH DATABASE ? AS delta", (path.as_os_str().as_bytes(),))?;
+    let result = conn.execute(
+        &format!(
+            r#"
+INSERT INTO delta.command_history (
+    id, session_id, full_command, shellname, hostname, username,
+    working_directory, exit_status, start_unix_timestamp, end_unix_timestamp, machine_id
+)
+SELECT id, session_id, full_command, shellname, hostname, username,
+    working_directory, exit_status, start_unix_timestamp, end_unix_timestamp, machine_id
+FROM main.command_history
+WHERE {condition}
+"#
+        ),
+        params,
+    );
+    let copied = if tombstones {
+        conn.execute("INSERT INTO delta.tombstones SELECT * FROM main.tombstones", ()).map(|_| ())
+    } else {
+        Ok(())
+    };
+    conn.execute("DETACH DATABASE delta", ())?;
+    copied?;
+    Ok(result?)
+}
+
+/// Ids of recent rows still lacking an exit status, within the same window
+/// as [`oldest_unsealed_start`].
+pub fn unsealed_ids(conn: &Connection) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let cutoff = chrono::Utc::now().timestamp() - UNSEALED_LOOKBACK_SECS;
+    let mut stmt = conn.prepare(
+        "SELECT id FROM command_history WHERE exit_status IS NULL AND start_unix_timestamp > ?",
+    )?;
+    let ids = stmt.query_map([cutoff], |r| r.get(0))?.collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Extra `WHERE` conditions (starting with `AND`, or empty) on
+/// `other.command_history` rows that a merge must never import: rows whose
+/// hash is in `tombstones` (a query yielding row hashes) and redacted echoes
+/// of our own rows. Needs [`register_row_hash`] on `conn`.
+fn source_row_filter(
+    conn: &Connection,
+    tombstones: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Skip the per-row hashing entirely when there is nothing to match.
+    let has_tombstones: bool =
+        conn.query_row(&format!("SELECT EXISTS ({tombstones})"), (), |r| r.get(0))?;
+    let mut live = if has_tombstones {
+        format!("AND pxh_row_hash({ROW_HASH_COLUMNS}) NOT IN ({tombstones})")
+    } else {
+        String::new()
+    };
+    // With `[sync.export]` redaction on, peers send our own rows back
+    // redacted; we still hold the originals.
+    if let Some(mid) = crate::read_local_machine_id(conn) {
+        let redacted = crate::export_policy::REDACTED;
+        live.push_str(&format!(
+            " AND NOT (machine_id IS {} AND (CAST(username AS text) = '{redacted}'
+                                          OR CAST(working_directory AS text) = '{redacted}'))",
+            mid as i64
+        ));
+    }
+    Ok(live)
+}
+
+/// What merging a source would do, computed without writing anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergePreview {
+    /// Rows above the watermark, as in [`MergeStats::considered`].
+    pub considered: i64,
+    /// Rows that would be inserted.
+    pub new_rows: i64,
+    /// Rows the secret filter would skip.
+    pub filtered: i64,
+    /// Local unsealed rows the source would seal.
+    pub upgrades: i64,
+    /// Local rows the source's tombstones would delete.
+    pub deleted: i64,
+    /// `MAX(id)` from the source -- the watermark a merge would record.
+    pub new_max_id: Option<i64>,
+    /// Up to the requested number of new commands, oldest first.
+    pub sample: Vec<String>,
+}
+
+/// Dry-run counterpart of [`merge_database_from_file`]: the same filtering,
+/// reported as a [`MergePreview`]. The source is read through a temporary
+/// copy, so neither it nor `conn` is modified.
+pub fn preview_merge_from_file(
+    conn: &Connection,
+    path: &Path,
+    secret_filter: Option<&RegexSet>,
+    watermark: Option<i64>,
+    sample_size: usize,
+) -> Result<MergePreview, Box<dyn std::error::Error>> {
+    // Older sources need migrating before they can be read like ours.
+    let copy = tempfile::Builder::new().prefix("pxh-preview-").suffix(".db").tempfile()?;
+    std::fs::copy(path, copy.path())?;
+    {
+        let other = Connection::open(copy.path())?;
+        crate::initialize_base_schema(&other)?;
+        crate::run_schema_migrations(&other)?;
+    }
+
+    register_row_hash(conn)?;
+    use std::os::unix::ffi::OsStrExt;
+    conn.execute("ATTACH DATABASE ? AS other", (copy.path().as_os_str().as_bytes(),))?;
+    let result = preview_attached(conn, secret_filter, watermark, sample_size);
+    conn.execute("DETACH DATABASE other", ())?;
+    result
+}
+
+fn preview_attached(
+    conn: &Connection,
+    secret_filter: Option<&RegexSet>,
+    watermark: Option<i64>,
+    sample_size: usize,
+) -> Result<MergePreview, Box<dyn std::error::Error>> {
+    let lo = watermark.unwrap_or(-1);
+    let mut preview = MergePreview {
+        considered: conn.query_row(
+            "SELECT COUNT(*) FROM other.command_history WHERE id > ?",
+            [lo],
+            |r| r.get(0),
+        )?,
+        new_max_id: conn
+            .query_row("SELECT MAX(id) FROM other.command_history", (), |r| r.get(0))?,
+        ..Default::default()
+    };
+
+    // The merge adopts the source's tombstones before anything else.
+    preview.deleted = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM main.command_history
+              WHERE pxh_row_hash({ROW_HASH_COLUMNS}) IN (SELECT row_hash FROM other.tombstones)"
+        ),
+        (),
+        |r| r.get(0),
+    )?;
+    let live = source_row_filter(
+        conn,
+        "SELECT row_hash FROM main.tombstones UNION SELECT row_hash FROM other.tombstones",
+    )?;
+
+    // Same identity as the unique index, so this is what INSERT OR IGNORE
+    // would skip.
+    let mut stmt = conn.prepare(&format!(
+        r#"
+SELECT full_command,
+       NOT EXISTS (SELECT 1 FROM main.command_history m
+                    WHERE m.full_command = o.full_command
+                      AND m.start_unix_timestamp IS o.start_unix_timestamp
+                      AND m.shellname = o.shellname
+                      AND COALESCE(m.username, '') = COALESCE(o.username, '')
+                      AND COALESCE(m.hostname, '') = COALESCE(o.hostname, '')
+                      AND COALESCE(m.working_directory, '') = COALESCE(o.working_directory, ''))
+FROM other.command_history o
+WHERE id > ? {live}
+ORDER BY id
+"#
+    ))?;
+    let mut rows = stmt.query([lo])?;
+    while let Some(row) = rows.next()? {
+        let command = row.get_ref(0)?.as_bytes()?;
+        if secret_filter.is_some_and(|filter| filter.is_match(command)) {
+            preview.filtered += 1;
+        } else if row.get::<_, bool>(1)? {
+            preview.new_rows += 1;
+            if preview.sample.len() < sample_size {
+                preview.sample.push(String::from_utf8_lossy(command).into_owned());
+            }
+        }
+    }
+
+    preview.upgrades = conn.query_row(
+        r#"
+SELECT COUNT(*)
+  FROM main.command_history m
+  JOIN other.command_history o
+    ON m.full_command = o.full_command
+   AND m.start_unix_timestamp IS o.start_unix_timestamp
+   AND m.shellname = o.shellname
+   AND COALESCE(m.hostname, '') = COALESCE(o.hostname, '')
+ WHERE m.exit_status IS NULL
+   AND o.exit_status IS NOT NULL
+"#,
+        (),
+        |r| r.get(0),
+    )?;
+    Ok(preview)
+}
+
 /// Merge history from a database file into `conn`, with an optional secret
 /// filter (rows whose command matches are skipped and counted) and an
 /// optional incremental-sync watermark (skip rows in the source whose
@@ -74,6 +514,7 @@ pub fn merge_database_from_file(
         crate::run_schema_migrations(&other)?;
     }
 
+    register_row_hash(conn)?;
     use std::os::unix::ffi::OsStrExt;
     conn.execute("ATTACH DATABASE ? AS other", (path.as_os_str().as_bytes(),))?;
     let result = merge_attached(conn, secret_filter, watermark);
@@ -99,6 +540,27 @@ fn merge_attached(
     // -1 sentinel matches all rows (id is AUTOINCREMENT, so always >= 1).
     let lo = watermark.unwrap_or(-1);
 
+    // Adopt the source's tombstones. Rows they cover that we already hold
+    // were deleted elsewhere after we got them, so delete them here too.
+    let adopted = crate::with_write_retry(conn, WRITE_RETRY_BUDGET, |tx| {
+        tx.execute("INSERT OR IGNORE INTO main.tombstones SELECT * FROM other.tombstones", ())
+    })?;
+    let deleted = if adopted > 0 {
+        crate::with_write_retry(conn, WRITE_RETRY_BUDGET, |tx| {
+            tx.execute(
+                &format!(
+                    "DELETE FROM main.command_history
+                      WHERE pxh_row_hash({ROW_HASH_COLUMNS}) IN (SELECT row_hash FROM main.tombstones)"
+                ),
+                (),
+            )
+        })?
+    } else {
+        0
+    };
+    // Source rows that were deleted somewhere are never imported.
+    let live = source_row_filter(conn, "SELECT row_hash FROM main.tombstones")?;
+
     // Read-only pre-pass: source stats, taken before the merge so
     // `considered` reflects what we scan below.
     let considered: i64 = conn
@@ -138,14 +600,14 @@ fn merge_attached(
                 Option<i64>,
             );
             let rows: Vec<SourceRow> = conn
-                .prepare(
+                .prepare(&format!(
                     r#"
 SELECT session_id, full_command, shellname, hostname, username,
        working_directory, exit_status, start_unix_timestamp, end_unix_timestamp, machine_id
 FROM other.command_history
-WHERE id > ? AND id <= ?
-"#,
-                )?
+WHERE id > ? AND id <= ? {live}
+"#
+                ))?
                 .query_map((cursor, hi), |row| {
                     Ok((
                         row.get(0)?,
@@ -189,7 +651,8 @@ VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             // No filtering, bulk-copy the chunk in SQL.
             added += crate::with_write_retry(conn, WRITE_RETRY_BUDGET, |tx| {
                 tx.execute(
-                    r#"
+                    &format!(
+                        r#"
 INSERT OR IGNORE INTO main.command_history (
     session_id, full_command, shellname, hostname, username,
     working_directory, exit_status, start_unix_timestamp, end_unix_timestamp, machine_id
@@ -197,8 +660,9 @@ INSERT OR IGNORE INTO main.command_history (
 SELECT session_id, full_command, shellname, hostname, username,
     working_directory, exit_status, start_unix_timestamp, end_unix_timestamp, machine_id
 FROM other.command_history
-WHERE id > ? AND id <= ?
-"#,
+WHERE id > ? AND id <= ? {live}
+"#
+                    ),
                     (cursor, hi),
                 )
             })?;
@@ -243,7 +707,13 @@ SELECT m.id, o.exit_status, o.end_unix_timestamp
         })?;
     }
 
-    Ok(MergeStats { considered, added: added as i64, filtered: filtered_count, new_max_id })
+    Ok(MergeStats {
+        considered,
+        added: added as i64,
+        filtered: filtered_count,
+        deleted: deleted as i64,
+        new_max_id,
+    })
 }
 
 #[cfg(test)]
@@ -292,12 +762,18 @@ mod tests {
         drop(source);
 
         let stats = merge_database_from_file(&mut target, &source_path, None, None).unwrap();
-        assert_eq!(stats, MergeStats { considered: 3, added: 3, filtered: 0, new_max_id: Some(3) });
+        assert_eq!(
+            stats,
+            MergeStats { considered: 3, added: 3, filtered: 0, deleted: 0, new_max_id: Some(3) }
+        );
         assert_eq!(count(&target), 3);
 
         // INSERT OR IGNORE makes a re-merge a no-op.
         let stats = merge_database_from_file(&mut target, &source_path, None, None).unwrap();
-        assert_eq!(stats, MergeStats { considered: 3, added: 0, filtered: 0, new_max_id: Some(3) });
+        assert_eq!(
+            stats,
+            MergeStats { considered: 3, added: 0, filtered: 0, deleted: 0, new_max_id: Some(3) }
+        );
         assert_eq!(count(&target), 3);
     }
 
@@ -338,7 +814,10 @@ mod tests {
         drop(source);
 
         let stats = merge_database_from_file(&mut target, &source_path, None, Some(2)).unwrap();
-        assert_eq!(stats, MergeStats { considered: 3, added: 3, filtered: 0, new_max_id: Some(5) });
+        assert_eq!(
+            stats,
+            MergeStats { considered: 3, added: 3, filtered: 0, deleted: 0, new_max_id: Some(5) }
+        );
         // Only rows with id > 2 (cmd-2 .. cmd-4) came over.
         assert_eq!(count(&target), 3);
         let has_cmd0: i64 = target
@@ -361,7 +840,10 @@ mod tests {
         let filter = RegexSet::new(["API_KEY="]).unwrap();
         let stats =
             merge_database_from_file(&mut target, &source_path, Some(&filter), None).unwrap();
-        assert_eq!(stats, MergeStats { considered: 2, added: 1, filtered: 1, new_max_id: Some(2) });
+        assert_eq!(
+            stats,
+            MergeStats { considered: 2, added: 1, filtered: 1, deleted: 0, new_max_id: Some(2) }
+        );
         assert_eq!(count(&target), 1);
     }
 
@@ -388,6 +870,167 @@ mod tests {
         assert_eq!(exit_status, Some(7), "seal info must be copied onto the unsealed row");
     }
 
+    #[test]
+    fn test_delta_has_new_rows_and_ones_the_peer_holds_unsealed() {
+        let (dir, mut target, _source_path, source) = merge_fixture();
+        insert_row(&source, "old", 1000, Some(0));
+        insert_row(&source, "long-running", 5000, None);
+        insert_row(&source, "synced", 6000, Some(0));
+        insert_row(&source, "new", 7000, Some(0));
+        // The peer got rows 1-3 while long-running was still running.
+        insert_row(&target, "long-running", 5000, None);
+        source
+            .execute(
+                "UPDATE command_history SET exit_status = 0, end_unix_timestamp = 6500
+                  WHERE id = 2",
+                (),
+            )
+            .unwrap();
+
+        let delta_path = dir.path().join("delta.db");
+        assert_eq!(write_delta_database(&source, &delta_path, 3, Some(5000)).unwrap(), 3);
+        let delta = Connection::open(&delta_path).unwrap();
+        let ids: Vec<i64> = delta
+            .prepare("SELECT id FROM command_history ORDER BY id")
+            .unwrap()
+            .query_map((), |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(ids, vec![2, 3, 4], "new rows plus sealed ones from 5000 on, ids preserved");
+        drop(delta);
+
+        let stats = merge_database_from_file(&mut target, &delta_path, None, None).unwrap();
+        assert_eq!(stats.new_max_id, Some(4));
+        let unsealed: i64 = target
+            .query_row("SELECT COUNT(*) FROM command_history WHERE exit_status IS NULL", (), |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(unsealed, 0, "the delta must carry the seal for the running row");
+
+        let only_new = dir.path().join("only_new.db");
+        assert_eq!(write_delta_database(&source, &only_new, 3, None).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_merge_adopts_tombstones_and_removes_deleted_rows() {
+        let (_dir, mut target, source_path, source) = merge_fixture();
+        insert_row(&target, "keep", 1000, Some(0));
+        insert_row(&target, "secret", 1001, Some(0));
+        insert_row(&source, "keep", 1000, Some(0));
+        insert_row(&source, "secret", 1001, Some(0));
+        // Deleted on the source after the target already copied it.
+        assert_eq!(
+            delete_with_tombstones(&source, "full_command = CAST(? AS blob)", ["secret"]).unwrap(),
+            1
+        );
+        drop(source);
+
+        let stats = merge_database_from_file(&mut target, &source_path, None, None).unwrap();
+        assert_eq!(stats.deleted, 1);
+        assert_eq!(count(&target), 1);
+        let tombstones: i64 =
+            target.query_row("SELECT COUNT(*) FROM tombstones", (), |r| r.get(0)).unwrap();
+        assert_eq!(tombstones, 1);
+
+        // Once adopted, a tombstone doesn't delete again on the next merge.
+        let stats = merge_database_from_file(&mut target, &source_path, None, None).unwrap();
+        assert_eq!(stats.deleted, 0);
+    }
+
+    #[test]
+    fn test_merge_skips_source_rows_deleted_locally() {
+        let (_dir, mut target, source_path, source) = merge_fixture();
+        insert_row(&target, "secret", 1001, Some(0));
+        delete_with_tombstones(&target, "1", ()).unwrap();
+        insert_row(&source, "secret", 1001, Some(0));
+        // Same text at another time is a different row and still comes over.
+        insert_row(&source, "secret", 2002, Some(0));
+        drop(source);
+
+        let stats = merge_database_from_file(&mut target, &source_path, None, None).unwrap();
+        assert_eq!((stats.considered, stats.added), (2, 1));
+        let start: i64 = target
+            .query_row("SELECT start_unix_timestamp FROM command_history", (), |r| r.get(0))
+            .unwrap();
+        assert_eq!(start, 2002);
+    }
+
+    #[test]
+    fn test_preview_matches_merge_and_writes_nothing() {
+        let (_dir, mut target, source_path, source) = merge_fixture();
+        insert_row(&target, "shared", 1000, None);
+        insert_row(&source, "shared", 1000, Some(0));
+        insert_row(&source, "new-1", 1001, Some(0));
+        insert_row(
+            &source,
+            "export TOKEN=sk-ant-REDACTED",
+            1002,
+            Some(0),
+        );
+        insert_row(&source, "new-2", 1003, Some(0));
+        drop(source);
+        let source_bytes = std::fs::read(&source_path).unwrap();
+        let filter = RegexSet::new(["sk-ant-"]).unwrap();
+
+        let preview =
+            preview_merge_from_file(&target, &source_path, Some(&filter), None, 1).unwrap();
+        assert_eq!(
+            preview,
+            MergePreview {
+                considered: 4,
+                new_rows: 2,
+                filtered: 1,
+                upgrades: 1,
+                deleted: 0,
+                new_max_id: Some(4),
+                sample: vec!["new-1".to_string()],
+            }
+        );
+        assert_eq!(count(&target), 1);
+        assert_eq!(std::fs::read(&source_path).unwrap(), source_bytes);
+
+        let stats =
+            merge_database_from_file(&mut target, &source_path, Some(&filter), None).unwrap();
+        assert_eq!((stats.added, stats.filtered), (preview.new_rows, preview.filtered));
+    }
+
+    #[test]
+    fn test_merge_skips_redacted_echoes_of_our_own_rows() {
+        let (_dir, mut target, source_path, source) = merge_fixture();
+        target
+            .execute("INSERT INTO settings (key, value) VALUES ('local_machine_id', '7')", ())
+            .unwrap();
+        insert_row(&source, "ours", 1000, Some(0));
+        insert_row(&source, "theirs", 1001, Some(0));
+        source
+            .execute(
+                "UPDATE command_history SET username = CAST('[redacted]' AS blob),
+                        machine_id = CASE WHEN full_command = CAST('ours' AS blob) THEN 7 ELSE 8 END",
+                (),
+            )
+            .unwrap();
+        drop(source);
+
+        let stats = merge_database_from_file(&mut target, &source_path, None, None).unwrap();
+        assert_eq!((stats.considered, stats.added), (2, 1));
+    }
+
+    #[test]
+    fn test_row_identity_hash_treats_null_like_empty() {
+        let with_null = row_identity_hash(b"ls", Some(1), b"zsh", None, Some(b"h"), None);
+        let with_empty =
+            row_identity_hash(b"ls", Some(1), b"zsh", Some(b""), Some(b"h"), Some(b""));
+        assert_eq!(with_null, with_empty);
+        assert_ne!(with_null, row_identity_hash(b"ls", None, b"zsh", None, Some(b"h"), None));
+        // Length prefixes keep field boundaries from shifting.
+        assert_ne!(
+            row_identity_hash(b"ab", Some(1), b"c", None, None, None),
+            row_identity_hash(b"a", Some(1), b"bc", None, None, None)
+        );
+    }
+
     #[test]
     fn test_merge_reports_max_id_regression_via_stats() {
         // A source restored from backup can have max(id) below our watermark;
@@ -397,7 +1040,10 @@ mod tests {
         drop(source);
 
         let stats = merge_database_from_file(&mut target, &source_path, None, Some(100)).unwrap();
-        assert_eq!(stats, MergeStats { considered: 0, added: 0, filtered: 0, new_max_id: Some(1) });
+        assert_eq!(
+            stats,
+            MergeStats { considered: 0, added: 0, filtered: 0, deleted: 0, new_max_id: Some(1) }
+        );
     }
 
     #[test]
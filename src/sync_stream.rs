@@ -0,0 +1,294 @@
+//! Framed, integrity-checked database transfer for remote sync (protocol v3).
+//!
+//! Older protocols send an 8-byte length followed by the raw database. A v3
+//! stream instead carries the database -- compressed when both peers agreed
+//! on it -- as a sequence of frames, each a little-endian `u32` length, that
+//! many payload bytes and the payload's BLAKE2b-256 hash. A zero length ends
+//! the stream and is followed by a trailer: the decoded size as a `u64` and
+//! the hash of the whole decoded database. Receivers check every chunk as it
+//! arrives and the trailer before anyone merges the result, and stream
+//! straight into the destination instead of buffering the payload.
+
+use std::io::{self, Read, Write};
+
+use blake2::{Blake2b, Digest, digest::consts::U32};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+
+/// Payload bytes per frame a sender aims for.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Frames larger than this are refused rather than buffered.
+const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const HASH_LEN: usize = 32;
+
+type Hasher = Blake2b<U32>;
+
+/// Compression applied to a stream's payload, negotiated per session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+/// Compression this build can use, in order of preference.
+pub const SUPPORTED_COMPRESSION: &[Compression] = &[Compression::Gzip];
+
+/// The first of the peer's `offered` compressions we support, or none.
+pub fn negotiate(offered: &[Compression]) -> Compression {
+    offered.iter().copied().find(|c| SUPPORTED_COMPRESSION.contains(c)).unwrap_or_default()
+}
+
+/// Buffers written bytes into frames of [`CHUNK_SIZE`].
+struct FrameWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    fn emit(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_all(&(self.buf.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&self.buf)?;
+        self.inner.write_all(&Hasher::digest(&self.buf))?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn finish(mut self, size: u64, hash: &[u8]) -> io::Result<()> {
+        self.emit()?;
+        self.inner.write_all(&0u32.to_le_bytes())?;
+        self.inner.write_all(&size.to_le_bytes())?;
+        self.inner.write_all(hash)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for FrameWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == CHUNK_SIZE {
+            self.emit()?;
+        }
+        Ok(n)
+    }
+
+    // Frames go out when full or at the end; a flush mid-compression must
+    // not produce a short frame per call.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Yields the verified payload of each frame; at the end frame, reads the
+/// trailer and reports end of data.
+struct FrameReader<R: Read> {
+    inner: R,
+    chunk: Vec<u8>,
+    pos: usize,
+    frames: u64,
+    trailer: Option<(u64, [u8; HASH_LEN])>,
+}
+
+impl<R: Read> FrameReader<R> {
+    fn next_frame(&mut self) -> io::Result<()> {
+        let mut len = [0u8; 4];
+        self.inner.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len == 0 {
+            let mut size = [0u8; 8];
+            let mut hash = [0u8; HASH_LEN];
+            self.inner.read_exact(&mut size)?;
+            self.inner.read_exact(&mut hash)?;
+            self.trailer = Some((u64::from_le_bytes(size), hash));
+            return Ok(());
+        }
+        if len > MAX_CHUNK_SIZE {
+            return Err(corrupt(format!(
+                "frame of {len} bytes exceeds the {MAX_CHUNK_SIZE} limit"
+            )));
+        }
+        self.chunk.resize(len, 0);
+        self.inner.read_exact(&mut self.chunk)?;
+        let mut hash = [0u8; HASH_LEN];
+        self.inner.read_exact(&mut hash)?;
+        self.frames += 1;
+        if Hasher::digest(&self.chunk).as_slice() != hash {
+            return Err(corrupt(format!("chunk {} failed its checksum", self.frames)));
+        }
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for FrameReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.trailer.is_some() {
+                return Ok(0);
+            }
+            self.chunk.clear();
+            self.pos = 0;
+            self.next_frame()?;
+        }
+        let n = out.len().min(self.chunk.len() - self.pos);
+        out[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn corrupt(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("sync stream corrupted: {msg}"))
+}
+
+/// Copy `source` to `dest`, hashing what passes through and refusing more
+/// than `max_size` bytes. Returns the byte count and hash.
+fn hashed_copy<R: Read, W: Write>(
+    source: &mut R,
+    dest: &mut W,
+    max_size: u64,
+) -> io::Result<(u64, [u8; HASH_LEN])> {
+    let mut hasher = Hasher::new();
+    let mut total = 0u64;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+        if total > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("received database exceeds maximum allowed ({max_size} bytes)"),
+            ));
+        }
+        hasher.update(&buf[..n]);
+        dest.write_all(&buf[..n])?;
+    }
+    Ok((total, hasher.finalize().into()))
+}
+
+/// Send everything `source` yields as one framed stream. Returns the
+/// (uncompressed) size sent.
+pub fn write_stream<R: Read, W: Write>(
+    writer: &mut W,
+    source: &mut R,
+    compression: Compression,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let frames = FrameWriter { inner: writer, buf: Vec::with_capacity(CHUNK_SIZE) };
+    let (frames, size, hash) = match compression {
+        Compression::None => {
+            let mut frames = frames;
+            let (size, hash) = hashed_copy(source, &mut frames, u64::MAX)?;
+            (frames, size, hash)
+        }
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(frames, flate2::Compression::fast());
+            let (size, hash) = hashed_copy(source, &mut encoder, u64::MAX)?;
+            (encoder.finish()?, size, hash)
+        }
+    };
+    frames.finish(size, &hash)?;
+    Ok(size)
+}
+
+/// Receive one framed stream into `dest`, verifying every chunk and the
+/// whole payload. Fails without reading further once more than `max_size`
+/// decoded bytes arrive. Returns the decoded size.
+pub fn read_stream<R: Read, W: Write>(
+    reader: &mut R,
+    dest: &mut W,
+    compression: Compression,
+    max_size: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let frames = FrameReader { inner: reader, chunk: Vec::new(), pos: 0, frames: 0, trailer: None };
+    let (mut frames, size, hash) = match compression {
+        Compression::None => {
+            let mut frames = frames;
+            let (size, hash) = hashed_copy(&mut frames, dest, max_size)?;
+            (frames, size, hash)
+        }
+        Compression::Gzip => {
+            let mut decoder = GzDecoder::new(frames);
+            let (size, hash) = hashed_copy(&mut decoder, dest, max_size)?;
+            (decoder.into_inner(), size, hash)
+        }
+    };
+    // The decoder stops at the end of its data; the end frame follows.
+    if frames.read(&mut [0u8; 1])? != 0 {
+        return Err(corrupt("data after the end of the compressed payload".into()).into());
+    }
+    let (expected_size, expected_hash) = frames.trailer.ok_or("sync stream ended early")?;
+    if size != expected_size || hash != expected_hash {
+        return Err(corrupt(format!(
+            "received {size} bytes that don't match the {expected_size} bytes sent"
+        ))
+        .into());
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..CHUNK_SIZE * 3 + 17).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_stream_round_trips() {
+        for compression in [Compression::None, Compression::Gzip] {
+            let data = payload();
+            let mut wire = Vec::new();
+            write_stream(&mut wire, &mut data.as_slice(), compression).unwrap();
+            // A second stream follows on the same connection.
+            write_stream(&mut wire, &mut &b"next"[..], compression).unwrap();
+
+            let mut reader = wire.as_slice();
+            let mut received = Vec::new();
+            let size = read_stream(&mut reader, &mut received, compression, u64::MAX).unwrap();
+            assert_eq!(size, data.len() as u64);
+            assert_eq!(received, data, "{compression:?}");
+            let mut next = Vec::new();
+            read_stream(&mut reader, &mut next, compression, u64::MAX).unwrap();
+            assert_eq!(next, b"next");
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_stream_rejects_corruption_and_oversize() {
+        let data = payload();
+        let mut wire = Vec::new();
+        write_stream(&mut wire, &mut data.as_slice(), Compression::None).unwrap();
+
+        let mut flipped = wire.clone();
+        flipped[4 + CHUNK_SIZE + HASH_LEN + 4 + 10] ^= 1;
+        let err =
+            read_stream(&mut flipped.as_slice(), &mut Vec::new(), Compression::None, u64::MAX)
+                .unwrap_err()
+                .to_string();
+        assert!(err.contains("chunk 2 failed its checksum"), "{err}");
+
+        let mut truncated = &wire[..wire.len() - 1];
+        assert!(read_stream(&mut truncated, &mut Vec::new(), Compression::None, u64::MAX).is_err());
+
+        let err = read_stream(&mut wire.as_slice(), &mut Vec::new(), Compression::None, 1000)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("exceeds maximum"), "{err}");
+    }
+
+    #[test]
+    fn test_negotiate_picks_a_shared_compression() {
+        assert_eq!(negotiate(&[Compression::Gzip]), Compression::Gzip);
+        assert_eq!(negotiate(&[]), Compression::None);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use clap::{Parser, ValueEnum};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::{
+    report::{display_path, git_root},
+    stats::Scope,
+};
+
+/// Time in directories outside any configured project or git checkout.
+const NO_PROJECT: &str = "(other)";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum TimesheetFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+#[clap(after_help = "Each session's commands form blocks of activity: a command counts until \
+the next one in its session if that starts within --idle of it finishing, and otherwise for its \
+own run time. Time overlapping in parallel sessions is counted once. Each block is credited to \
+the project of the directory it ran in: the longest matching path in the [projects] config \
+section, else the enclosing git checkout.")]
+pub struct TimesheetCommand {
+    #[clap(
+        long,
+        value_name = "TIME",
+        default_value = "monday",
+        help = "Count activity from TIME (e.g. monday, 7d, 2025-03-01)"
+    )]
+    pub since: String,
+    #[clap(long, value_name = "TIME", help = "Count activity before TIME (same forms as --since)")]
+    pub until: Option<String>,
+    #[clap(
+        long,
+        value_name = "HOST",
+        value_delimiter = ',',
+        help = "Only count commands from these hosts; this host's name includes its configured aliases"
+    )]
+    pub host: Vec<String>,
+    #[clap(
+        long,
+        value_name = "DURATION",
+        default_value = "15m",
+        help = "Longest gap between commands in a session that still counts as working"
+    )]
+    pub idle: String,
+    #[clap(long, value_enum, default_value_t = TimesheetFormat::Table, help = "Output format")]
+    pub format: TimesheetFormat,
+}
+
+struct Entry {
+    session_id: i64,
+    working_directory: Option<Vec<u8>>,
+    start: i64,
+    end: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct DayTotal {
+    date: String,
+    project: String,
+    seconds: i64,
+    hours: f64,
+}
+
+#[derive(Serialize)]
+struct ProjectTotal {
+    project: String,
+    seconds: i64,
+    hours: f64,
+}
+
+#[derive(Serialize)]
+struct Timesheet {
+    days: Vec<DayTotal>,
+    projects: Vec<ProjectTotal>,
+    total_seconds: i64,
+    total_hours: f64,
+}
+
+impl TimesheetCommand {
+    pub fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let idle = pxh::helpers::parse_duration_spec(&self.idle)?;
+        let scope = Scope::new(Some(&self.since), self.until.as_deref(), &self.host, false)?;
+        let entries: Vec<Entry> = scope.query(
+            &conn,
+            "SELECT session_id, working_directory, start_unix_timestamp, end_unix_timestamp
+               FROM command_history
+              WHERE {scope} AND start_unix_timestamp IS NOT NULL
+              ORDER BY session_id, start_unix_timestamp, id",
+            |r| {
+                Ok(Entry {
+                    session_id: r.get(0)?,
+                    working_directory: r.get(1)?,
+                    start: r.get(2)?,
+                    end: r.get(3)?,
+                })
+            },
+        )?;
+
+        let mut projects = Projects::from_config();
+        let mut by_day: BTreeMap<(NaiveDate, String), i64> = BTreeMap::new();
+        let spans: Vec<_> = entries.iter().map(|e| (e.session_id, e.start, e.end)).collect();
+        for (start, end, i) in active_intervals(&spans, idle) {
+            let project = projects.resolve(entries[i].working_directory.as_deref());
+            for (day, secs) in split_by_day(start, end) {
+                *by_day.entry((day, project.clone())).or_default() += secs;
+            }
+        }
+        let timesheet = build_timesheet(by_day);
+
+        match self.format {
+            TimesheetFormat::Table => print_table(&timesheet),
+            TimesheetFormat::Csv => {
+                println!("date,project,seconds,hours");
+                for day in &timesheet.days {
+                    println!(
+                        "{},{},{},{:.2}",
+                        day.date,
+                        pxh::csv_escape(&day.project),
+                        day.seconds,
+                        day.hours
+                    );
+                }
+            }
+            TimesheetFormat::Json => println!("{}", serde_json::to_string_pretty(&timesheet)?),
+        }
+        Ok(())
+    }
+}
+
+/// Non-overlapping `(start, end, span index)` intervals of activity, given
+/// each command's `(session_id, start, end)`. A command counts until the next
+/// one in its session unless that comes more than `idle` seconds after it
+/// finished. `spans` must be ordered by session, then start time.
+pub(crate) fn active_intervals(
+    spans: &[(i64, i64, Option<i64>)],
+    idle: i64,
+) -> Vec<(i64, i64, usize)> {
+    let mut intervals: Vec<(i64, i64, usize)> = Vec::new();
+    for (i, &(session_id, start, end)) in spans.iter().enumerate() {
+        let finished = end.unwrap_or(start).max(start);
+        let next = spans.get(i + 1).filter(|next| next.0 == session_id);
+        let end = match next {
+            Some(&(_, next_start, _)) if next_start - finished <= idle => next_start.max(finished),
+            _ => finished,
+        };
+        if end > start {
+            intervals.push((start, end, i));
+        }
+    }
+
+    // Across sessions, whichever interval started first keeps the overlap.
+    intervals.sort_unstable();
+    let mut covered = i64::MIN;
+    let mut active = Vec::with_capacity(intervals.len());
+    for (start, end, i) in intervals {
+        let start = start.max(covered);
+        if end > start {
+            active.push((start, end, i));
+        }
+        covered = covered.max(end);
+    }
+    active
+}
+
+// Seconds of [start, end) falling on each local calendar day.
+fn split_by_day(mut start: i64, end: i64) -> Vec<(NaiveDate, i64)> {
+    let mut days = Vec::new();
+    while start < end {
+        let Some(day) = Local.timestamp_opt(start, 0).single().map(|t| t.date_naive()) else {
+            break;
+        };
+        let next_midnight = day
+            .succ_opt()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|d| Local.from_local_datetime(&d).earliest())
+            .map_or(end, |d| d.timestamp());
+        let until = end.min(next_midnight.max(start + 1));
+        days.push((day, until - start));
+        start = until;
+    }
+    days
+}
+
+/// Maps working directories to project names.
+struct Projects {
+    // Configured prefixes, longest first so the most specific project wins.
+    configured: Vec<(PathBuf, String)>,
+    cache: HashMap<Vec<u8>, String>,
+}
+
+impl Projects {
+    fn from_config() -> Self {
+        let config = pxh::recall::Config::load();
+        let mut configured: Vec<(PathBuf, String)> = config
+            .projects
+            .iter()
+            .flat_map(|(name, paths)| paths.paths().into_iter().map(|p| (p, name.clone())))
+            .collect();
+        configured.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+        Projects { configured, cache: HashMap::new() }
+    }
+
+    fn resolve(&mut self, dir: Option<&[u8]>) -> String {
+        let Some(dir) = dir else {
+            return NO_PROJECT.to_string();
+        };
+        if let Some(project) = self.cache.get(dir) {
+            return project.clone();
+        }
+        let path = Path::new(OsStr::from_bytes(dir));
+        let project = self
+            .configured
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix))
+            .map(|(_, name)| name.clone())
+            .or_else(|| git_root(path).map(display_path))
+            .unwrap_or_else(|| NO_PROJECT.to_string());
+        self.cache.insert(dir.to_vec(), project.clone());
+        project
+    }
+}
+
+fn hours(seconds: i64) -> f64 {
+    (seconds as f64 / 36.0).round() / 100.0
+}
+
+fn build_timesheet(by_day: BTreeMap<(NaiveDate, String), i64>) -> Timesheet {
+    let mut by_project: BTreeMap<String, i64> = BTreeMap::new();
+    for ((_, project), secs) in &by_day {
+        *by_project.entry(project.clone()).or_default() += secs;
+    }
+    let mut projects: Vec<ProjectTotal> = by_project
+        .into_iter()
+        .map(|(project, seconds)| ProjectTotal { project, seconds, hours: hours(seconds) })
+        .collect();
+    projects.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.project.cmp(&b.project)));
+    let total_seconds = projects.iter().map(|p| p.seconds).sum();
+    let days = by_day
+        .into_iter()
+        .map(|((date, project), seconds)| DayTotal {
+            date: date.format("%Y-%m-%d").to_string(),
+            project,
+            seconds,
+            hours: hours(seconds),
+        })
+        .collect();
+    Timesheet { days, projects, total_seconds, total_hours: hours(total_seconds) }
+}
+
+fn print_table(timesheet: &Timesheet) {
+    if timesheet.days.is_empty() {
+        println!("No activity in this period.");
+        return;
+    }
+    let width = timesheet.projects.iter().map(|p| p.project.chars().count()).max().unwrap_or(0);
+    let width = width.max("Project".len());
+    let duration = pxh::helpers::format_duration;
+
+    println!("{:<10}  {:<width$}  {:>12}  {:>7}", "Date", "Project", "Time", "Hours");
+    let mut last_date = "";
+    for day in &timesheet.days {
+        let date = if day.date == last_date { "" } else { day.date.as_str() };
+        last_date = &day.date;
+        println!(
+            "{date:<10}  {:<width$}  {:>12}  {:>7.2}",
+            day.project,
+            duration(day.seconds),
+            day.hours
+        );
+    }
+
+    println!("\n{:<width$}  {:>12}  {:>7}", "Project", "Time", "Hours");
+    for project in &timesheet.projects {
+        println!(
+            "{:<width$}  {:>12}  {:>7.2}",
+            project.project,
+            duration(project.seconds),
+            project.hours
+        );
+    }
+    println!(
+        "{:<width$}  {:>12}  {:>7.2}",
+        "Total",
+        duration(timesheet.total_seconds),
+        timesheet.total_hours
+    );
+}
@@ -0,0 +1,32 @@
+-- Stable, documented views for ad-hoc queries (`pxh sql`, sqlite3). They use
+-- only core SQLite so they also work outside pxh. Recreated by migrations
+-- when their definitions change; `maintenance` leaves them in place.
+
+DROP VIEW IF EXISTS v_history;
+CREATE VIEW v_history AS
+SELECT id,
+       session_id,
+       CAST(full_command AS TEXT) AS command,
+       shellname,
+       CAST(hostname AS TEXT) AS hostname,
+       CAST(username AS TEXT) AS username,
+       CAST(working_directory AS TEXT) AS working_directory,
+       exit_status,
+       start_unix_timestamp,
+       end_unix_timestamp,
+       end_unix_timestamp - start_unix_timestamp AS duration,
+       datetime(start_unix_timestamp, 'unixepoch', 'localtime') AS start_time,
+       machine_id
+  FROM command_history;
+
+DROP VIEW IF EXISTS v_sessions;
+CREATE VIEW v_sessions AS
+SELECT session_id,
+       CAST(MIN(hostname) AS TEXT) AS hostname,
+       COUNT(*) AS commands,
+       SUM(exit_status IS NOT NULL AND exit_status != 0) AS failures,
+       MIN(start_unix_timestamp) AS start_unix_timestamp,
+       MAX(COALESCE(end_unix_timestamp, start_unix_timestamp)) AS end_unix_timestamp,
+       datetime(MIN(start_unix_timestamp), 'unixepoch', 'localtime') AS start_time
+  FROM command_history
+ GROUP BY session_id;
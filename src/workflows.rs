@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use clap::{Parser, ValueEnum};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::stats::{Scope, truncated};
+
+/// Programs whose second word is a subcommand worth keeping (`git commit`,
+/// `terraform plan`); any other command is reduced to its program.
+const SUBCOMMAND_PROGRAMS: &[&str] = &[
+    "apt",
+    "aws",
+    "az",
+    "brew",
+    "bun",
+    "bundle",
+    "cargo",
+    "conda",
+    "deno",
+    "dnf",
+    "docker",
+    "dotnet",
+    "flatpak",
+    "gcloud",
+    "gh",
+    "git",
+    "go",
+    "gradle",
+    "helm",
+    "hg",
+    "jj",
+    "just",
+    "kubectl",
+    "make",
+    "minikube",
+    "mvn",
+    "nix",
+    "npm",
+    "pip",
+    "pip3",
+    "pnpm",
+    "podman",
+    "poetry",
+    "pxh",
+    "rake",
+    "rustup",
+    "snap",
+    "svn",
+    "systemctl",
+    "terraform",
+    "tmux",
+    "tofu",
+    "uv",
+    "vagrant",
+    "yarn",
+    "zypper",
+];
+/// A pause longer than this ends a workflow even within a session.
+const MAX_STEP_GAP_SECS: i64 = 30 * 60;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum WorkflowsFormat {
+    /// The most common workflows
+    #[default]
+    Table,
+    /// Workflows and the transition graph
+    Json,
+    /// The transition graph, for Graphviz
+    Dot,
+}
+
+#[derive(Parser, Debug)]
+#[clap(after_help = "Commands are reduced to their program, plus the subcommand for tools like \
+git, cargo, docker, kubectl and terraform. Within each session, repeats of the same step are \
+merged and a pause of over 30 minutes starts a new sequence. A workflow is only listed when no \
+longer workflow containing it occurs just as often.\n\n\
+Graph: pxh workflows --format dot | dot -Tsvg > workflows.svg")]
+pub struct WorkflowsCommand {
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Only consider commands started at or after TIME (e.g. 30d, 2025-01-31)"
+    )]
+    pub since: Option<String>,
+    #[clap(
+        long,
+        value_name = "HOST",
+        value_delimiter = ',',
+        help = "Only consider commands from these hosts; this host's name includes its configured aliases"
+    )]
+    pub host: Vec<String>,
+    #[clap(short = 'H', long, help = "Only consider commands run in the current directory")]
+    pub here: bool,
+    #[clap(long, default_value_t = 2, help = "Fewest steps in a workflow")]
+    pub min_length: usize,
+    #[clap(long, default_value_t = 4, help = "Most steps in a workflow")]
+    pub max_length: usize,
+    #[clap(
+        long,
+        default_value_t = 3,
+        help = "Fewest occurrences for a workflow or graph transition to be shown"
+    )]
+    pub min_count: i64,
+    #[clap(
+        long,
+        value_name = "PROGRAM",
+        value_delimiter = ',',
+        default_value = "cd,ls,ll,pwd,clear",
+        help = "Programs that are not workflow steps (empty to keep everything)"
+    )]
+    pub ignore: Vec<String>,
+    #[clap(short, long, default_value_t = 20, help = "Maximum number of workflows to list")]
+    pub limit: usize,
+    #[clap(long, value_enum, default_value_t = WorkflowsFormat::Table, help = "Output format")]
+    pub format: WorkflowsFormat,
+}
+
+#[derive(Default)]
+struct Tally {
+    count: i64,
+    sessions: HashSet<i64>,
+}
+
+#[derive(Serialize)]
+struct Workflow {
+    steps: Vec<String>,
+    count: i64,
+    sessions: usize,
+}
+
+#[derive(Serialize)]
+struct Node {
+    id: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct Edge {
+    from: String,
+    to: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl WorkflowsCommand {
+    pub fn go(&self, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+        if self.min_length < 2 || self.max_length < self.min_length {
+            return Err("workflows need 2 <= --min-length <= --max-length".into());
+        }
+        let scope = Scope::new(self.since.as_deref(), None, &self.host, self.here)?;
+        let rows: Vec<(i64, Vec<u8>, Option<i64>)> = scope.query(
+            &conn,
+            "SELECT session_id, full_command, start_unix_timestamp FROM command_history
+              WHERE {scope} ORDER BY session_id, start_unix_timestamp, id",
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )?;
+
+        let sequences = self.sequences(&rows);
+        let mut grams: HashMap<Vec<&str>, Tally> = HashMap::new();
+        let mut steps: HashMap<&str, i64> = HashMap::new();
+        for (session, sequence) in &sequences {
+            for step in sequence {
+                *steps.entry(step).or_default() += 1;
+            }
+            for n in 2..=self.max_length {
+                for window in sequence.windows(n) {
+                    let tally =
+                        grams.entry(window.iter().map(String::as_str).collect()).or_default();
+                    tally.count += 1;
+                    tally.sessions.insert(*session);
+                }
+            }
+        }
+
+        let graph = self.graph(&grams, &steps);
+        let workflows = self.workflows(&grams);
+        match self.format {
+            WorkflowsFormat::Table => print_table(&workflows),
+            WorkflowsFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(
+                    &serde_json::json!({"workflows": workflows, "graph": graph})
+                )?
+            ),
+            WorkflowsFormat::Dot => print!("{}", render_dot(&graph)),
+        }
+        Ok(())
+    }
+
+    // Each session's normalized steps, with repeats merged, split at long pauses.
+    fn sequences(&self, rows: &[(i64, Vec<u8>, Option<i64>)]) -> Vec<(i64, Vec<String>)> {
+        let mut sequences: Vec<(i64, Vec<String>)> = Vec::new();
+        let mut last: Option<(i64, Option<i64>)> = None;
+        for (session, command, start) in rows {
+            let Some(step) = normalize(command).filter(|step| {
+                let program = step.split(' ').next().unwrap_or_default();
+                !self.ignore.iter().any(|ignored| ignored == program)
+            }) else {
+                continue;
+            };
+            let continues = last.is_some_and(|(last_session, last_start)| {
+                last_session == *session
+                    && match (last_start, start) {
+                        (Some(a), Some(b)) => b - a <= MAX_STEP_GAP_SECS,
+                        _ => true,
+                    }
+            });
+            last = Some((*session, *start));
+            match sequences.last_mut() {
+                Some((_, sequence)) if continues => {
+                    if sequence.last() != Some(&step) {
+                        sequence.push(step);
+                    }
+                }
+                _ => sequences.push((*session, vec![step])),
+            }
+        }
+        sequences
+    }
+
+    // Frequent workflows, leaving out those always seen inside a longer one.
+    fn workflows(&self, grams: &HashMap<Vec<&str>, Tally>) -> Vec<Workflow> {
+        // The highest count of any one-step-longer workflow containing each.
+        let mut extended: HashMap<&[&str], i64> = HashMap::new();
+        for (gram, tally) in grams {
+            for part in [&gram[1..], &gram[..gram.len() - 1]] {
+                let best = extended.entry(part).or_default();
+                *best = (*best).max(tally.count);
+            }
+        }
+        let mut workflows: Vec<Workflow> = grams
+            .iter()
+            .filter(|(gram, tally)| {
+                gram.len() >= self.min_length
+                    && tally.count >= self.min_count
+                    && extended.get(gram.as_slice()) != Some(&tally.count)
+            })
+            .map(|(gram, tally)| Workflow {
+                steps: gram.iter().map(|s| s.to_string()).collect(),
+                count: tally.count,
+                sessions: tally.sessions.len(),
+            })
+            .collect();
+        workflows.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.steps.len().cmp(&a.steps.len()))
+                .then_with(|| a.steps.cmp(&b.steps))
+        });
+        workflows.truncate(self.limit);
+        workflows
+    }
+
+    // Transitions seen at least --min-count times, and the steps they join.
+    fn graph(&self, grams: &HashMap<Vec<&str>, Tally>, steps: &HashMap<&str, i64>) -> Graph {
+        let mut edges: Vec<Edge> = grams
+            .iter()
+            .filter(|(gram, tally)| gram.len() == 2 && tally.count >= self.min_count)
+            .map(|(gram, tally)| Edge {
+                from: gram[0].to_string(),
+                to: gram[1].to_string(),
+                count: tally.count,
+            })
+            .collect();
+        edges.sort_by(|a, b| {
+            b.count.cmp(&a.count).then_with(|| (&a.from, &a.to).cmp(&(&b.from, &b.to)))
+        });
+        let used: BTreeMap<&str, i64> = edges
+            .iter()
+            .flat_map(|e| [e.from.as_str(), e.to.as_str()])
+            .map(|id| (id, steps.get(id).copied().unwrap_or_default()))
+            .collect();
+        let nodes =
+            used.into_iter().map(|(id, count)| Node { id: id.to_string(), count }).collect();
+        Graph { nodes, edges }
+    }
+}
+
+/// A command as a workflow step: its program, plus its subcommand for
+/// programs that have them. `None` for blank commands.
+fn normalize(command: &[u8]) -> Option<String> {
+    let program = pxh::helpers::command_program(command)?;
+    let words = pxh::helpers::command_words(command);
+    let subcommand =
+        words.get(1).filter(|_| SUBCOMMAND_PROGRAMS.contains(&program.as_str())).filter(|w| {
+            w.first().is_some_and(u8::is_ascii_lowercase)
+                && w.iter().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+        });
+    Some(match subcommand {
+        Some(sub) => format!("{program} {}", String::from_utf8_lossy(sub)),
+        None => program,
+    })
+}
+
+fn print_table(workflows: &[Workflow]) {
+    if workflows.is_empty() {
+        println!("No repeated workflows found (try lowering --min-count).");
+        return;
+    }
+    println!("{:>7}  {:>8}  Workflow", "Count", "Sessions");
+    for workflow in workflows {
+        println!(
+            "{:>7}  {:>8}  {}",
+            workflow.count,
+            workflow.sessions,
+            truncated(&workflow.steps.join(" → "), 100)
+        );
+    }
+}
+
+fn render_dot(graph: &Graph) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let max = graph.edges.iter().map(|e| e.count).max().unwrap_or(1) as f64;
+    let mut out = String::from("digraph workflows {\n    rankdir=LR;\n    node [shape=box];\n");
+    for node in &graph.nodes {
+        let id = escape(&node.id);
+        out.push_str(&format!("    \"{id}\" [label=\"{id}\\n{}\"];\n", node.count));
+    }
+    for edge in &graph.edges {
+        out.push_str(&format!(
+            "    \"{}\" -> \"{}\" [label=\"{}\", penwidth={:.1}];\n",
+            escape(&edge.from),
+            escape(&edge.to),
+            edge.count,
+            1.0 + 4.0 * edge.count as f64 / max
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
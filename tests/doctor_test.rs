@@ -193,3 +193,110 @@ fn doctor_fix_merges_legacy_db() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn doctor_flags_plaintext_in_encrypted_sync_dir() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let home_dir = temp_dir.path().join("home");
+    let sync_dir = temp_dir.path().join("sync");
+    fs::create_dir_all(home_dir.join(".config").join("pxh"))?;
+    fs::create_dir_all(&sync_dir)?;
+    let key_file = temp_dir.path().join("sync.key");
+    fs::write(&key_file, "passphrase")?;
+    fs::write(
+        home_dir.join(".config").join("pxh").join("config.toml"),
+        format!("[sync]\ndirectory = {sync_dir:?}\nkey_file = {key_file:?}\n"),
+    )?;
+    let db_path = temp_dir.path().join("pxh.db");
+    create_test_db(&db_path)?;
+    fs::copy(&db_path, sync_dir.join("other-host.db"))?;
+
+    let output = pxh_command()
+        .env("HOME", &home_dir)
+        .env_remove("XDG_CONFIG_HOME")
+        .args(["--db", db_path.to_str().unwrap(), "doctor"])
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Unencrypted database in encrypted sync dir"),
+        "should flag plaintext sync file: {stdout}"
+    );
+    assert!(stdout.contains("other-host.db"), "should name the file: {stdout}");
+    Ok(())
+}
+
+#[test]
+fn doctor_checks_sync_target_directories() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let home_dir = temp_dir.path().join("home");
+    let sync_dir = temp_dir.path().join("sync");
+    let target_dir = temp_dir.path().join("dropbox");
+    fs::create_dir_all(home_dir.join(".config").join("pxh"))?;
+    fs::create_dir_all(&sync_dir)?;
+    fs::create_dir_all(&target_dir)?;
+    let key_file = target_dir.join("sync.key");
+    fs::write(&key_file, "passphrase")?;
+    fs::write(
+        home_dir.join(".config").join("pxh").join("config.toml"),
+        format!(
+            "[sync]\ndirectory = {sync_dir:?}\nkey_file = {key_file:?}\n\n\
+             [[sync.targets]]\nname = \"dropbox\"\ntype = \"dir\"\npath = {target_dir:?}\n"
+        ),
+    )?;
+    let db_path = temp_dir.path().join("pxh.db");
+    create_test_db(&db_path)?;
+    fs::copy(&db_path, target_dir.join("other-host.db"))?;
+
+    let output = pxh_command()
+        .env("HOME", &home_dir)
+        .env_remove("XDG_CONFIG_HOME")
+        .args(["--db", db_path.to_str().unwrap(), "doctor"])
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Sync key is inside the sync directory"),
+        "should flag the key inside the target directory: {stdout}"
+    );
+    assert!(
+        stdout.contains("Unencrypted database in encrypted sync dir"),
+        "should flag plaintext files in the target directory: {stdout}"
+    );
+    assert!(stdout.contains("other-host.db"), "should name the file: {stdout}");
+    Ok(())
+}
+
+#[test]
+fn doctor_flags_plaintext_segments_in_encrypted_sync_dir() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let home_dir = temp_dir.path().join("home");
+    let sync_dir = temp_dir.path().join("sync");
+    let seg_dir = sync_dir.join("other-host.segments");
+    fs::create_dir_all(home_dir.join(".config").join("pxh"))?;
+    fs::create_dir_all(&seg_dir)?;
+    let key_file = temp_dir.path().join("sync.key");
+    fs::write(&key_file, "passphrase")?;
+    fs::write(
+        home_dir.join(".config").join("pxh").join("config.toml"),
+        format!("[sync]\ndirectory = {sync_dir:?}\nkey_file = {key_file:?}\n"),
+    )?;
+    fs::write(
+        seg_dir.join("manifest.json"),
+        r#"{"version": 1, "machine_id": null, "hostname": "other-host", "segments": [
+            {"name": "00000001.db.gz", "seq": 1, "last_id": 1, "rows": 1, "checksum": "00"}]}"#,
+    )?;
+    let db_path = temp_dir.path().join("pxh.db");
+    create_test_db(&db_path)?;
+
+    let output = pxh_command()
+        .env("HOME", &home_dir)
+        .env_remove("XDG_CONFIG_HOME")
+        .args(["--db", db_path.to_str().unwrap(), "doctor"])
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Unencrypted segments in encrypted sync dir"),
+        "should flag plaintext segments: {stdout}"
+    );
+    assert!(stdout.contains("other-host.segments"), "should name the directory: {stdout}");
+    Ok(())
+}
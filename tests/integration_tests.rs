@@ -245,17 +245,348 @@ fn show_loosen_honors_limit() {
 }
 
 #[test]
-fn show_session_conflicts_with_here() {
+fn show_session_combines_with_here() {
+    let pc = PxhCaller::new();
+    let here = fs::canonicalize(pc.tmpdir()).unwrap();
+    let here = here.to_string_lossy();
+    for (session, dir, cmd) in [
+        (1, here.as_ref(), "cmd_1a"),
+        (1, "/b", "cmd_1b"),
+        (2, here.as_ref(), "cmd_2a"),
+        (2, "/b", "cmd_2b"),
+    ] {
+        pc.call(format!(
+            "insert --shellname s --hostname h --username u --session-id {session} --working-directory {dir} {cmd}"
+        ))
+        .assert()
+        .success();
+    }
+
+    let output = pc
+        .call("show --suppress-headers --session 1 --here")
+        .current_dir(here.as_ref())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(count_lines(&output.stdout), 1, "{stdout}");
+    assert!(stdout.contains("cmd_1a"));
+}
+
+#[test]
+fn show_with_time_host_user_shell_and_status_filters() {
     let helper = PxhTestHelper::new();
+    let call = |args: String| {
+        helper.command_with_args(&args.split(' ').collect::<Vec<_>>()).output().unwrap()
+    };
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+        as i64;
+    // (age in hours, shell, host, user, exit status, command)
+    let rows = [
+        (72, "zsh", "laptop", "alice", 0, "old_cmd"),
+        (1, "zsh", "laptop", "alice", 127, "typo_cmd"),
+        (1, "bash", "server", "root", 130, "interrupted_cmd"),
+        (1, "bash", "oldname", "alice", 0, "alias_host_cmd"),
+    ];
+    for (hours, shell, host, user, status, cmd) in rows {
+        let start = now - hours * 3600;
+        assert!(call(format!(
+            "insert --shellname {shell} --hostname {host} --username {user} --session-id 1 --start-unix-timestamp {start} {cmd}"
+        ))
+        .status
+        .success());
+        assert!(
+            call(format!(
+                "seal --session-id 1 --exit-status {status} --end-unix-timestamp {}",
+                start + 1
+            ))
+            .status
+            .success()
+        );
+    }
+    fs::write(
+        helper.home_dir().join(".pxh/config.toml"),
+        "[history]\nignore_patterns = []\n[host]\naliases = [\"oldname\"]\n",
+    )
+    .unwrap();
+
+    let shown = |args: &str| -> Vec<String> {
+        let output = call(format!("show --suppress-headers {args}"));
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let mut cmds: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|l| l.split_whitespace().last().map(String::from))
+            .collect();
+        cmds.sort();
+        cmds
+    };
+
+    assert_eq!(shown("--since 2d"), ["alias_host_cmd", "interrupted_cmd", "typo_cmd"]);
+    assert_eq!(shown("--until 2d"), ["old_cmd"]);
+    assert_eq!(shown("--shell bash"), ["alias_host_cmd", "interrupted_cmd"]);
+    assert_eq!(shown("--user root"), ["interrupted_cmd"]);
+    assert_eq!(shown("--exit-status 127,130"), ["interrupted_cmd", "typo_cmd"]);
+    assert_eq!(shown("--exit-status 0 --since 2d"), ["alias_host_cmd"]);
+    assert_eq!(shown("--host server,laptop"), ["interrupted_cmd", "old_cmd", "typo_cmd"]);
+    // Naming this host brings in history recorded under its aliases.
+    assert_eq!(shown(&format!("--host {}", helper.hostname)), ["alias_host_cmd"]);
+
+    let output = call("show --since whenever".to_string());
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unrecognized time"));
+}
+
+#[test]
+fn show_with_context() {
+    let pc = PxhCaller::new();
+    // Session 1 around two terraform runs, with an unrelated session-2
+    // command interleaved by id.
+    let commands = [
+        (1, "cd_proj"),
+        (1, "export_a"),
+        (1, "terraform_plan"),
+        (1, "echo_done"),
+        (2, "other_session"),
+        (1, "vim_x"),
+        (1, "ls_a"),
+        (1, "terraform_apply"),
+        (1, "exit_0"),
+    ];
+    for (i, (session, cmd)) in commands.iter().enumerate() {
+        pc.call(format!(
+            "insert --shellname s --hostname h --username u --session-id {session} --start-unix-timestamp {} {cmd}",
+            1000 + i
+        ))
+        .assert()
+        .success();
+    }
+
+    let output = pc.call("show --suppress-headers -C 1 terraform").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().map(str::trim).collect();
+    assert_eq!(lines.len(), 7, "two groups of three plus a separator: {stdout}");
+    assert!(lines[1].starts_with('>') && lines[1].ends_with("terraform_plan"));
+    assert!(lines[2].ends_with("echo_done"), "context stays within the session: {stdout}");
+    assert_eq!(lines[3], "--");
+    assert!(lines[4].ends_with("ls_a"));
+    assert!(lines[5].starts_with('>') && lines[5].ends_with("terraform_apply"));
+    assert!(!stdout.contains("other_session"));
+
+    // Overlapping or adjacent windows merge into one group without duplicates.
+    let output = pc.call("show --suppress-headers -B 3 -A 0 terraform").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(count_lines(&output.stdout), 7, "{stdout}");
+    assert!(!stdout.contains("--"));
+    assert_eq!(stdout.matches("echo_done").count(), 1);
+
+    // Context applies after --loosen filtering selects the hits.
+    let output = pc.call("show --suppress-headers --loosen -A 1 apply terraform").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(count_lines(&output.stdout), 2, "{stdout}");
+    assert!(stdout.contains("exit_0"));
+
+    // Sorting would drop hits whose windows come out of id order.
+    let output = pc.call("show --sort duration -C 1 terraform").output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--sort other than time"));
+
+    // Only the table shows which rows matched.
+    for format in ["--format json", "--format csv", "--template {command}", "--null"] {
+        let output = pc.call(format!("show {format} -C 1 terraform")).output().unwrap();
+        assert!(!output.status.success(), "{format}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be combined"), "{format}");
+    }
+}
+
+#[test]
+fn show_context_with_interleaved_sessions() {
+    let pc = PxhCaller::new();
+    let commands = [(1, "cd_proj"), (1, "mark_one"), (2, "mark_two"), (1, "mark_three")];
+    for (i, (session, cmd)) in commands.iter().enumerate() {
+        pc.call(format!(
+            "insert --shellname s --hostname h --username u --session-id {session} --start-unix-timestamp {} {cmd}",
+            1000 + i
+        ))
+        .assert()
+        .success();
+    }
+
+    // Session 1's rows from the first group aren't printed again after
+    // session 2's, and the earlier hit isn't shown as plain context.
+    let output = pc.call("show --suppress-headers -B 2 mark").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().map(str::trim).collect();
+    assert_eq!(lines.len(), 6, "{stdout}");
+    assert!(lines[0].ends_with("cd_proj"));
+    assert!(lines[1].starts_with('>') && lines[1].ends_with("mark_one"));
+    assert_eq!(lines[2], "--");
+    assert!(lines[3].starts_with('>') && lines[3].ends_with("mark_two"));
+    assert_eq!(lines[4], "--");
+    assert!(lines[5].starts_with('>') && lines[5].ends_with("mark_three"));
+}
+
+#[test]
+fn show_structured_output() {
+    let pc = PxhCaller::new();
+    for (i, cmd) in ["make_build", "echo_a,b", "make_test"].iter().enumerate() {
+        pc.call(format!(
+            "insert --shellname zsh --hostname h --username u --session-id 9 --working-directory /w --start-unix-timestamp {} --end-unix-timestamp {} --exit-status {i} {cmd}",
+            1000 + 10 * i,
+            1002 + 10 * i
+        ))
+        .assert()
+        .success();
+    }
+
+    // JSON is the export encoding, oldest first like the table.
+    let output = pc.call("show --format json make").output().unwrap();
+    let json: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.len(), 2);
+    assert_eq!(json[0]["command"], "make_build");
+    assert_eq!(json[1]["exit_status"], 2);
+    assert_eq!(json[1]["working_directory"], "/w");
+
+    let output = pc.call("show --format ndjson").output().unwrap();
+    assert_eq!(count_lines(&output.stdout), 3);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+
+    let output = pc.call("show --format csv echo").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("start_unix_timestamp,"));
+    assert_eq!(lines[1], "1010,1012,2,9,1,h,u,zsh,/w,\"echo_a,b\"");
+    let output = pc.call("show --format tsv --suppress-headers echo").output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1010\t1012\t2\t9\t1\th\tu\tzsh\t/w\techo_a,b\n"
+    );
 
     let output =
-        helper.command_with_args(&["show", "--session", "current", "--here"]).output().unwrap();
-    assert!(!output.status.success(), "--session and --here should conflict");
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(
-        stderr.contains("cannot be used with") || stderr.contains("conflict"),
-        "error should mention conflict: {stderr}"
+        pc.call("show --template {session}:{status}:{duration}:{command} test").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9:2:2:make_test\n");
+    pc.call("show --template {bogus}").assert().failure();
+
+    let output = pc.call("show --null make").output().unwrap();
+    assert_eq!(output.stdout, b"make_build\0make_test\0");
+    pc.call("show --null --format json").assert().failure();
+}
+
+#[test]
+fn show_sort_unique_and_duration_filters() {
+    let pc = PxhCaller::new();
+    let runs = [
+        ("make_build", 100, 110, 0),
+        ("make_test", 200, 260, 0),
+        ("make_build", 300, 305, 1),
+        ("make_build", 400, 402, 0),
+        ("docker_run", 500, 900, 0),
+    ];
+    for (cmd, start, end, status) in runs {
+        pc.call(format!(
+            "insert --shellname s --hostname h --username u --session-id 1 --start-unix-timestamp {start} --end-unix-timestamp {end} --exit-status {status} {cmd}"
+        ))
+        .assert()
+        .success();
+    }
+    let lines = |args: &str| -> Vec<String> {
+        let output = pc.call(format!("show --suppress-headers {args}")).output().unwrap();
+        assert!(output.status.success(), "{args}: {}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).lines().map(|l| l.trim().to_string()).collect()
+    };
+
+    // The longest runs are kept by --limit and shown last.
+    let slowest = lines("--sort duration -l 2");
+    assert_eq!(slowest.len(), 2);
+    assert!(slowest[0].contains("60s") && slowest[0].ends_with("make_test"));
+    assert!(slowest[1].contains("400s") && slowest[1].ends_with("docker_run"));
+
+    let unique = lines("--unique make");
+    assert_eq!(unique.len(), 2, "{unique:?}");
+    assert!(unique[1].starts_with("3 ") && unique[1].contains("67%"));
+    assert!(unique[1].ends_with("make_build"));
+
+    // --sort count implies --unique
+    let frequent = lines("--sort count -l 1");
+    assert_eq!(frequent.len(), 1);
+    assert!(frequent[0].starts_with("3 ") && frequent[0].ends_with("make_build"));
+
+    let sorted = lines("--unique --sort command");
+    let commands: Vec<&str> = sorted.iter().map(|l| l.rsplit(' ').next().unwrap()).collect();
+    assert_eq!(commands, ["docker_run", "make_build", "make_test"]);
+
+    let long = lines("--min-duration 1m");
+    assert_eq!(long.len(), 2);
+    assert!(lines("--max-duration 5s").iter().all(|l| l.ends_with("make_build")));
+    assert_eq!(lines("--min-duration 10 --max-duration 10").len(), 1);
+
+    // --loosen with --limit keeps the most recent matches.
+    let recent = lines("--loosen -l 1 build make");
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent.last(), lines("--loosen build make").last());
+    assert_ne!(recent.last(), lines("--loosen build make").first());
+
+    pc.call("show --unique -C 1 make").assert().failure();
+    pc.call("show --min-duration soon").assert().failure();
+}
+
+#[test]
+fn sql_queries_views_read_only() {
+    let pc = PxhCaller::new();
+    for (i, cmd) in ["make_build", "cargo_test"].iter().enumerate() {
+        pc.call(format!(
+            "insert --shellname zsh --hostname h --username u --session-id 3 --start-unix-timestamp {} --end-unix-timestamp {} --exit-status {i} {cmd}",
+            100 * i,
+            100 * i + 7
+        ))
+        .assert()
+        .success();
+    }
+
+    let output = pc
+        .call("sql --format json SELECT command,duration,exit_status FROM v_history WHERE command REGEXP 'test'")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{"command": "cargo_test", "duration": 7, "exit_status": 1}])
     );
+
+    let output = pc
+        .call("sql --format csv SELECT session_id,commands,failures FROM v_sessions")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "session_id,commands,failures\n3,2,1\n");
+
+    let output = pc
+        .call("sql --suppress-headers SELECT text(full_command) FROM command_history ORDER BY id")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().map(str::trim).collect::<Vec<_>>(), ["make_build", "cargo_test"]);
+
+    // Writes are refused, and nothing is changed.
+    pc.call("sql DELETE FROM command_history").assert().failure();
+    pc.call("sql SELECT 1; DELETE FROM command_history").assert().failure();
+    let output =
+        pc.call("sql --suppress-headers SELECT count(*) FROM command_history").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2");
+
+    // Maintenance keeps the standard views and KEEP_ views but drops others.
+    let conn = Connection::open(pc.tmpdir().join("test")).unwrap();
+    conn.execute_batch(
+        "CREATE VIEW scratch AS SELECT 1; CREATE VIEW KEEP_mine AS SELECT 2; DROP VIEW v_sessions;",
+    )
+    .unwrap();
+    pc.call("maintenance").assert().success();
+    let mut stmt =
+        conn.prepare("SELECT name FROM sqlite_master WHERE type='view' ORDER BY name").unwrap();
+    let views: Vec<String> =
+        stmt.query_map([], |r| r.get(0)).unwrap().map(Result::unwrap).collect();
+    assert_eq!(views, ["KEEP_mine", "v_history", "v_sessions"]);
 }
 
 #[test]
@@ -1619,6 +1950,171 @@ fn stats_command() {
     assert!(!stdout.is_empty(), "stats should produce output");
 }
 
+#[test]
+fn stats_breakdowns_and_filters() {
+    let caller = PxhTestHelper::new();
+    let runs: [(&str, &str, &str, i64, i64, i64); 5] = [
+        ("h1", "/src/a", "sudo apt update", 100, 140, 0),
+        ("h1", "/src/a", "FOO=1 make test", 200, 210, 2),
+        ("h1", "/src/b", "make test", 300, 1300, 0),
+        ("h2", "/src/a", "make build", 5_000_000, 5_000_001, 2),
+        ("h2", "/src/b", "ls", 5_000_100, 5_000_100, 0),
+    ];
+    for (i, (host, dir, cmd, start, end, status)) in runs.iter().enumerate() {
+        let session = if i < 3 { "1" } else { "2" };
+        let mut args = vec![
+            "insert",
+            "--shellname",
+            "zsh",
+            "--hostname",
+            host,
+            "--username",
+            "u",
+            "--session-id",
+            session,
+            "--working-directory",
+            dir,
+        ];
+        let (start, end, status) = (start.to_string(), end.to_string(), status.to_string());
+        args.extend(["--start-unix-timestamp", &start, "--end-unix-timestamp", &end]);
+        args.extend(["--exit-status", &status]);
+        args.extend(cmd.split(' '));
+        assert!(caller.command_with_args(&args).output().unwrap().status.success());
+    }
+
+    let stats = |args: &[&str]| -> serde_json::Value {
+        let mut all = vec!["stats", "--json"];
+        all.extend(args);
+        let output = caller.command_with_args(&all).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let all = stats(&[]);
+    assert_eq!(all["commands"], 5);
+    // Programs skip sudo and env assignments
+    assert_eq!(all["top_programs"][0], serde_json::json!({"name": "make", "count": 3}));
+    assert_eq!(all["top_programs"][1], serde_json::json!({"name": "apt", "count": 1}));
+    assert_eq!(all["top_directories"][0], serde_json::json!({"name": "/src/a", "count": 3}));
+    assert_eq!(all["hosts"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        all["hours"].as_array().unwrap().iter().map(|v| v.as_i64().unwrap()).sum::<i64>(),
+        5
+    );
+    assert_eq!(all["weekdays"].as_array().unwrap().len(), 7);
+    assert_eq!(all["longest_commands"][0]["command"], "make test");
+    assert_eq!(all["longest_commands"][0]["duration"], 1000);
+    assert_eq!(all["busiest_sessions"][0]["session_id"], 1);
+    assert_eq!(all["busiest_sessions"][0]["commands"], 3);
+    let failing = all["failing_commands"].as_array().unwrap();
+    assert_eq!(failing.len(), 2);
+    assert!(failing.iter().all(|f| f["failures"] == 1 && f["runs"] == 1));
+
+    assert_eq!(stats(&["--host", "h2"])["commands"], 2);
+    assert_eq!(stats(&["--since", "@1000000"])["commands"], 2);
+    assert_eq!(
+        stats(&["--until", "@1000000", "--limit", "1"])["top_commands"].as_array().unwrap().len(),
+        1
+    );
+
+    let output = caller.command_with_args(&["stats"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for section in [
+        "Top programs:",
+        "Top directories:",
+        "Hour of day:",
+        "Weekday:",
+        "Most failing commands:",
+        "Longest running:",
+        "Busiest sessions:",
+    ] {
+        assert!(stdout.contains(section), "missing {section}: {stdout}");
+    }
+}
+
+#[test]
+fn report_summarizes_period() {
+    let caller = PxhTestHelper::new();
+    let project = caller.home_dir().join("work/proj");
+    std::fs::create_dir_all(project.join(".git")).unwrap();
+    std::fs::create_dir_all(project.join("sub")).unwrap();
+    let project = project.to_string_lossy().to_string();
+    let sub = format!("{project}/sub");
+    use chrono::TimeZone;
+    let day = |y, m, d| chrono::Local.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap().timestamp();
+    let runs = [
+        (day(2024, 6, 1), "svn up", "/old", 0),
+        (day(2024, 6, 2), "svn up", "/old", 0),
+        (day(2024, 6, 3), "svn up", "/old", 0),
+        (day(2024, 6, 3) + 60, "git pull", project.as_str(), 0),
+        (day(2025, 3, 3), "git status", project.as_str(), 0),
+        (day(2025, 3, 3) + 120, "cargo build --secret-flag", sub.as_str(), 1),
+        (day(2025, 3, 4), "vim notes", project.as_str(), 0),
+        (day(2025, 3, 5), "vim notes", project.as_str(), 0),
+        (day(2025, 3, 9), "vim notes", project.as_str(), 0),
+    ];
+    for (start, cmd, dir, status) in runs {
+        let (start_s, end_s, status_s) =
+            (start.to_string(), (start + 5).to_string(), status.to_string());
+        let mut args = vec![
+            "insert",
+            "--shellname",
+            "zsh",
+            "--hostname",
+            "secret-host",
+            "--username",
+            "u",
+            "--session-id",
+            "7",
+            "--working-directory",
+            dir,
+            "--start-unix-timestamp",
+            &start_s,
+            "--end-unix-timestamp",
+            &end_s,
+            "--exit-status",
+            &status_s,
+        ];
+        args.extend(cmd.split(' '));
+        assert!(caller.command_with_args(&args).output().unwrap().status.success());
+    }
+
+    let output = caller.command_with_args(&["report", "--period", "2025"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.starts_with("# Shell history report: 2025"));
+    assert!(report.contains("- **Commands:** 5"));
+    assert!(report.contains("- **Longest streak:** 3 days (2025-03-03 to 2025-03-05)"), "{report}");
+    assert!(report.contains("| vim | 3 |"));
+    // cargo and vim are new; git was used before; svn was abandoned.
+    let section = |title: &str| {
+        let start = report.find(&format!("## {title}")).unwrap();
+        let rest = &report[start + 3..];
+        rest[..rest.find("\n## ").unwrap_or(rest.len())].to_string()
+    };
+    assert!(section("Newly adopted tools").contains("| cargo | 1 |"));
+    assert!(!section("Newly adopted tools").contains("git"));
+    assert!(section("Abandoned tools").contains("| svn | 3 |"));
+    // Time in the subdirectory is credited to the enclosing checkout, and the
+    // overnight gaps count only each command's own 5 seconds: 120 + 4 * 5.
+    assert!(section("Time per project").contains("| ~/work/proj | 2m 20s |"), "{report}");
+    assert!(section("Notable failures").contains("| cargo build --secret-flag | 1 | 1 |"));
+
+    let output = caller
+        .command_with_args(&["report", "--period", "2025", "--anonymize", "--format", "html"])
+        .output()
+        .unwrap();
+    let html = String::from_utf8_lossy(&output.stdout);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<code>project 1</code>"));
+    for leak in ["secret-host", "--secret-flag", "work/proj", "notes"] {
+        assert!(!html.contains(leak), "anonymized report leaks {leak}: {html}");
+    }
+
+    let output = caller.command_with_args(&["report", "--period", "2025-13"]).output().unwrap();
+    assert!(!output.status.success());
+}
+
 #[test]
 fn completions_command_bash() {
     let caller = PxhTestHelper::new();
@@ -1741,3 +2237,274 @@ fn insert_accepts_hyphen_prefixed_commands() {
     assert!(stdout.contains("-la"), "command '-la' should be in history");
     assert!(stdout.contains("-rf"), "command '-rf' should be in history");
 }
+
+#[test]
+fn fix_learns_corrections() {
+    let helper = PxhTestHelper::new();
+    let runs: [(&str, &str, i64, i64); 8] = [
+        ("1", "gti status", 100, 127),
+        ("1", "git status", 105, 0),
+        ("1", "cargo buidl", 200, 101),
+        ("1", "cargo build", 210, 0),
+        // Too slow to count as a correction
+        ("1", "make tset", 300, 2),
+        ("1", "make test", 1000, 0),
+        ("2", "sl", 400, 127),
+        ("3", "gti status", 500, 127),
+    ];
+    for (session, cmd, start, status) in runs {
+        let (start, status) = (start.to_string(), status.to_string());
+        let mut args = vec!["insert", "--shellname", "zsh", "--hostname", "h", "--username", "u"];
+        args.extend(["--session-id", session, "--start-unix-timestamp", &start]);
+        args.extend(["--end-unix-timestamp", &start, "--exit-status", &status]);
+        args.extend(cmd.split(' '));
+        assert!(helper.command_with_args(&args).output().unwrap().status.success());
+    }
+
+    let fix = |args: &[&str], session: &str| {
+        let mut all = vec!["fix"];
+        all.extend(args);
+        let output =
+            helper.command_with_args(&all).env("PXH_SESSION_ID", session).output().unwrap();
+        (output.status.success(), String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let (ok, list) = fix(&["--list"], "1");
+    assert!(ok);
+    assert!(list.contains("gti status  ->  git status"), "{list}");
+    assert!(list.contains("cargo buidl  ->  cargo build"), "{list}");
+    assert!(!list.contains("make"), "{list}");
+
+    assert_eq!(fix(&["gti", "status"], "1"), (true, "git status\n".into()));
+    // A learned word correction applies to new command lines
+    assert_eq!(fix(&["--", "gti", "log", "-p"], "1"), (true, "git log -p\n".into()));
+    // The last failed command in the session
+    assert_eq!(fix(&[], "3"), (true, "git status\n".into()));
+    assert!(!fix(&[], "1").0);
+    assert!(!fix(&[], "2").0);
+    assert!(!fix(&["frobnicate"], "1").0);
+
+    // The handler is only emitted on request
+    let config = |args: &[&str]| {
+        let output = helper.command_with_args(args).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert!(!config(&["shell-config", "zsh"]).contains("command_not_found_handler"));
+    let zsh = config(&["shell-config", "zsh", "--command-not-found-fix"]);
+    assert!(zsh.contains("command_not_found_handler()"));
+    assert!(zsh.contains("PXH_CTRL_R_BINDING"));
+    let bash = config(&["shell-config", "bash", "--command-not-found-fix", "--no-ctrl-r"]);
+    assert!(bash.contains("command_not_found_handle()"));
+    assert!(!bash.contains("PXH_CTRL_R_BINDING"));
+}
+
+#[test]
+fn suggest_aliases_from_repeated_commands() {
+    let helper = PxhTestHelper::new();
+    let mut start = 1000;
+    let mut insert = |session: &str, cmd: &str| {
+        start += 10;
+        let start = start.to_string();
+        let mut args = vec!["insert", "--shellname", "zsh", "--hostname", "h", "--username", "u"];
+        args.extend(["--session-id", session, "--start-unix-timestamp", &start, "--"]);
+        args.extend(cmd.split(' '));
+        assert!(helper.command_with_args(&args).output().unwrap().status.success());
+    };
+    for session in ["1", "2", "3"] {
+        insert(session, "kubectl get pods --namespace prod");
+        insert(session, "kubectl get pods --namespace staging");
+        insert(session, "git status");
+        insert(session, "docker exec -it web bash");
+        insert(session, "docker exec -it db bash");
+        insert(session, "terraform plan");
+    }
+    // Frequent, but only ever in one session
+    for _ in 0..6 {
+        insert("4", "make integration-tests");
+    }
+    // Typed as one argument each: spacing inside quotes matters, and an
+    // alias can't hold two lines.
+    for session in ["1", "2", "3"] {
+        for cmd in ["grep -r \"TODO  FIXME\" src", "cargo build --release\n./target/release/app"] {
+            start += 10;
+            let start = start.to_string();
+            let mut args = vec!["insert", "--shellname", "zsh", "--hostname", "h"];
+            args.extend(["--username", "u", "--session-id", session]);
+            args.extend(["--start-unix-timestamp", &start, "--", cmd]);
+            assert!(helper.command_with_args(&args).output().unwrap().status.success());
+        }
+    }
+
+    let suggest = |args: &[&str], aliases: &str| {
+        use std::io::Write;
+        let mut all = vec!["suggest-aliases", "--min-count", "3"];
+        all.extend(args);
+        let mut child = helper
+            .command_with_args(&all)
+            .env("PATH", "")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(aliases.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let out = suggest(&[], "");
+    // The common prefix, not the two full commands
+    assert!(out.contains("alias kgpn='kubectl get pods --namespace'"), "{out}");
+    assert!(!out.contains("--namespace prod'"), "{out}");
+    assert!(out.contains("alias gs='git status'"), "{out}");
+    assert!(out.contains("alias tp='terraform plan'"), "{out}");
+    // A varying middle word becomes a function argument
+    assert!(out.contains(r#"deib() { docker exec -it "$1" bash "${@:2}"; }"#), "{out}");
+    assert!(out.contains("# docker exec -it <arg> bash: typed 6 times in 3 sessions"), "{out}");
+    assert!(!out.contains("docker exec -it web"), "{out}");
+    assert!(!out.contains("make"), "{out}");
+    assert!(out.contains(r#"='grep -r "TODO  FIXME" src'"#), "{out}");
+    assert!(!out.contains("cargo build"), "{out}");
+    // Biggest savings first
+    assert!(out.find("kgpn").unwrap() < out.find("alias gs").unwrap(), "{out}");
+
+    // Existing aliases cover commands and claim names
+    let out = suggest(&["--aliases", "-"], "alias gs='git status'\ntp='tmuxploy'\n");
+    assert!(!out.contains("git status"), "{out}");
+    assert!(out.contains("alias tpl='terraform plan'"), "{out}");
+
+    let out = suggest(&["--min-sessions", "4"], "");
+    assert!(out.starts_with("# No repeated commands"), "{out}");
+}
+
+#[test]
+fn timesheet_attributes_time_to_projects() {
+    use chrono::TimeZone;
+
+    let helper = PxhTestHelper::new();
+    let home = helper.home_dir().to_path_buf();
+    std::fs::create_dir_all(home.join("work/acme/sub")).unwrap();
+    std::fs::create_dir_all(home.join("src/tool/.git")).unwrap();
+    std::fs::create_dir_all(home.join("misc")).unwrap();
+    std::fs::write(
+        home.join(".pxh/config.toml"),
+        "[history]\nignore_patterns = []\n\n[projects]\nacme = \"~/work/acme\"\n",
+    )
+    .unwrap();
+
+    let at = |h, m, s| chrono::Local.with_ymd_and_hms(2025, 3, 10, h, m, s).unwrap().timestamp();
+    let runs = [
+        ("1", "work/acme/sub", at(9, 0, 0), at(9, 0, 10)),
+        ("1", "work/acme", at(9, 5, 0), at(9, 5, 5)),
+        // After an idle gap
+        ("1", "work/acme", at(10, 0, 0), at(10, 0, 30)),
+        ("2", "src/tool", at(11, 0, 0), at(11, 0, 0)),
+        ("2", "src/tool", at(11, 10, 0), at(11, 10, 0)),
+        ("2", "src/tool", at(11, 20, 0), at(11, 21, 0)),
+        // In parallel with session 1; only time after 9:05 counts
+        ("3", "misc", at(9, 3, 0), at(9, 3, 0)),
+        ("3", "misc", at(9, 4, 0), at(9, 6, 0)),
+    ];
+    for (session, dir, start, end) in runs {
+        let dir = home.join(dir).to_string_lossy().into_owned();
+        let (start, end) = (start.to_string(), end.to_string());
+        let mut args = vec!["insert", "--shellname", "zsh", "--hostname", "h", "--username", "u"];
+        args.extend(["--session-id", session, "--working-directory", &dir]);
+        args.extend(["--start-unix-timestamp", &start, "--end-unix-timestamp", &end]);
+        args.extend(["--exit-status", "0", "true"]);
+        assert!(helper.command_with_args(&args).output().unwrap().status.success());
+    }
+
+    let timesheet = |args: &[&str]| {
+        let mut all = vec!["timesheet", "--since", "2025-03-10", "--until", "2025-03-11"];
+        all.extend(args);
+        let output = helper.command_with_args(&all).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&timesheet(&["--format", "json"])).unwrap();
+    let seconds = |project: &str| {
+        json["projects"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["project"] == project)
+            .map(|p| p["seconds"].as_i64().unwrap())
+    };
+    assert_eq!(seconds("~/src/tool"), Some(1260));
+    assert_eq!(seconds("acme"), Some(330));
+    assert_eq!(seconds("(other)"), Some(60));
+    assert_eq!(json["total_seconds"], 1650);
+    assert_eq!(json["days"][0]["date"], "2025-03-10");
+
+    let csv = timesheet(&["--format", "csv", "--idle", "5m"]);
+    assert!(csv.starts_with("date,project,seconds,hours\n"), "{csv}");
+    assert!(csv.contains("2025-03-10,~/src/tool,60,0.02\n"), "{csv}");
+
+    let table = timesheet(&[]);
+    assert!(table.contains("2025-03-10  "), "{table}");
+    assert!(table.contains("Total"), "{table}");
+    assert_eq!(timesheet(&["--host", "elsewhere"]).trim(), "No activity in this period.");
+}
+
+#[test]
+fn workflows_mines_command_sequences() {
+    let helper = PxhTestHelper::new();
+    let mut start = 1_000_000;
+    let mut insert = |session: &str, cmd: &str| {
+        start += 60;
+        let start = start.to_string();
+        let mut args = vec!["insert", "--shellname", "zsh", "--hostname", "h", "--username", "u"];
+        args.extend(["--session-id", session, "--start-unix-timestamp", &start, "--"]);
+        args.extend(cmd.split(' '));
+        assert!(helper.command_with_args(&args).output().unwrap().status.success());
+    };
+    for session in ["1", "2", "3"] {
+        insert(session, "git add -A");
+        insert(session, "git add src/main.rs");
+        insert(session, "ls");
+        insert(session, "git commit -m wip");
+        insert(session, "git push origin HEAD");
+        insert(session, "vim notes.txt");
+        insert(session, "terraform plan -out plan");
+        insert(session, "terraform apply plan");
+    }
+
+    let workflows = |args: &[&str]| {
+        let mut all = vec!["workflows"];
+        all.extend(args);
+        let output = helper.command_with_args(&all).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let table = workflows(&[]);
+    // Repeats merge, ls is ignored, and sub-sequences that never occur on
+    // their own are left out
+    assert!(table.contains("git add → git commit → git push → vim"), "{table}");
+    assert!(!table.contains("git add → git commit\n"), "{table}");
+    assert!(table.contains("      3         3  "), "{table}");
+    let table = workflows(&["--max-length", "2"]);
+    assert!(table.contains("terraform plan → terraform apply"), "{table}");
+    assert!(table.contains("git add → git commit\n"), "{table}");
+    assert!(workflows(&["--min-count", "4"]).starts_with("No repeated workflows"));
+
+    let json: serde_json::Value = serde_json::from_str(&workflows(&["--format", "json"])).unwrap();
+    let edges = json["graph"]["edges"].as_array().unwrap();
+    assert!(
+        edges.contains(&serde_json::json!({"from": "git add", "to": "git commit", "count": 3}))
+    );
+    // Sessions are not joined together
+    assert!(!edges.iter().any(|e| e["from"] == "terraform apply"));
+    assert!(
+        json["graph"]["nodes"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({"id": "git add", "count": 3}))
+    );
+
+    let dot = workflows(&["--format", "dot"]);
+    assert!(dot.starts_with("digraph workflows {"), "{dot}");
+    assert!(dot.contains("\"terraform plan\" -> \"terraform apply\" [label=\"3\""), "{dot}");
+}
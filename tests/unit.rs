@@ -104,6 +104,31 @@ fn test_build_remote_pxh_command_default() {
     assert!(cmd.contains("--db ~/.pxh/pxh.db sync --server"));
 }
 
+#[test]
+fn test_transport_command() {
+    // Exec-style transports get the remote command through a shell
+    let (cmd, args) =
+        helpers::transport_command("kubectl exec -i {target} --", "dev-pod", "pxh sync --server")
+            .unwrap();
+    assert_eq!(cmd, "kubectl");
+    assert_eq!(args, vec!["exec", "-i", "dev-pod", "--", "sh", "-c", "pxh sync --server"]);
+
+    // {command} places it as one argument, and {target} works inside arguments
+    let (cmd, args) =
+        helpers::transport_command("sudo -u svc sh -c {command}", "x", "pxh sync --server")
+            .unwrap();
+    assert_eq!(cmd, "sudo");
+    assert_eq!(args, vec!["-u", "svc", "sh", "-c", "pxh sync --server"]);
+    let (_, args) =
+        helpers::transport_command("docker exec -i --user='{target}' box", "me", "c").unwrap();
+    assert_eq!(args, vec!["exec", "-i", "--user=me", "box", "sh", "-c", "c"]);
+
+    assert!(helpers::transport_command("  ", "x", "c").is_err());
+    // {command} inside a larger word can't be substituted safely
+    assert!(helpers::transport_command("bash -lc 'cd /srv && {command}'", "x", "c").is_err());
+    assert!(helpers::transport_command("run --cmd={command}", "x", "c").is_err());
+}
+
 #[test]
 fn test_default_remote_db_expr() {
     let expr = helpers::default_remote_db_expr();
@@ -188,6 +213,74 @@ fn test_parse_ssh_command() {
     assert_eq!(args, vec!["-o", "Option=\"value\""]);
 }
 
+#[test]
+fn test_parse_time_spec() {
+    use chrono::{Local, TimeZone};
+
+    // Wednesday 2025-01-15 12:00:00 local
+    let now = Local.with_ymd_and_hms(2025, 1, 15, 12, 0, 0).unwrap();
+    let at = |y, mo, d, h, mi| Local.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap().timestamp();
+    let parse = |spec| helpers::parse_time_spec(spec, now);
+
+    assert_eq!(parse("2h"), Ok(now.timestamp() - 7200));
+    assert_eq!(parse("3d"), Ok(now.timestamp() - 3 * 86400));
+    assert_eq!(parse("1w"), Ok(now.timestamp() - 7 * 86400));
+    assert_eq!(parse("90s"), Ok(now.timestamp() - 90));
+    assert_eq!(parse("now"), Ok(now.timestamp()));
+    assert_eq!(parse("today"), Ok(at(2025, 1, 15, 0, 0)));
+    assert_eq!(parse("Yesterday"), Ok(at(2025, 1, 14, 0, 0)));
+    assert_eq!(parse("monday"), Ok(at(2025, 1, 13, 0, 0)));
+    assert_eq!(parse("wed"), Ok(at(2025, 1, 15, 0, 0)), "today's weekday means today");
+    assert_eq!(parse("thursday"), Ok(at(2025, 1, 9, 0, 0)));
+    assert_eq!(parse("2024-12-31"), Ok(at(2024, 12, 31, 0, 0)));
+    assert_eq!(parse("2024-12-31 18:30"), Ok(at(2024, 12, 31, 18, 30)));
+    assert_eq!(parse("2024-12-31T18:30:00"), Ok(at(2024, 12, 31, 18, 30)));
+    assert_eq!(parse("@1700000000"), Ok(1700000000));
+
+    for bad in ["", "soon", "3y", "2024-13-01", "@x"] {
+        assert!(parse(bad).is_err(), "{bad:?} should not parse");
+    }
+}
+
+#[test]
+fn test_parse_period() {
+    use chrono::{Local, TimeZone};
+
+    let at = |y, mo, d| Local.with_ymd_and_hms(y, mo, d, 0, 0, 0).unwrap().timestamp();
+    assert_eq!(helpers::parse_period("2025"), Ok((at(2025, 1, 1), at(2026, 1, 1))));
+    assert_eq!(helpers::parse_period("2025-02"), Ok((at(2025, 2, 1), at(2025, 3, 1))));
+    assert_eq!(helpers::parse_period("2024-12"), Ok((at(2024, 12, 1), at(2025, 1, 1))));
+    for bad in ["", "last year", "2025-13", "2025-00", "25-x"] {
+        assert!(helpers::parse_period(bad).is_err(), "{bad:?} should not parse");
+    }
+}
+
+#[test]
+fn test_command_program() {
+    let program = |cmd: &str| helpers::command_program(cmd.as_bytes());
+    assert_eq!(program("git status"), Some("git".into()));
+    assert_eq!(program("  /usr/bin/make -j8"), Some("make".into()));
+    assert_eq!(program("sudo apt install foo"), Some("apt".into()));
+    assert_eq!(program("sudo -u postgres -E psql"), Some("psql".into()));
+    assert_eq!(program("RUST_LOG=debug FOO='a b' cargo run"), Some("cargo".into()));
+    assert_eq!(program("env -i PATH=/bin ls"), Some("ls".into()));
+    assert_eq!(program("'my tool' --flag"), Some("my tool".into()));
+    assert_eq!(program("=oops x"), Some("=oops".into()));
+    assert_eq!(program("   "), None);
+    assert_eq!(program("sudo"), None);
+}
+
+#[test]
+fn test_parse_duration_spec() {
+    assert_eq!(helpers::parse_duration_spec("45"), Ok(45));
+    assert_eq!(helpers::parse_duration_spec("90s"), Ok(90));
+    assert_eq!(helpers::parse_duration_spec("5M"), Ok(300));
+    assert_eq!(helpers::parse_duration_spec(" 2h "), Ok(7200));
+    for bad in ["", "-5", "fast", "1y", "m"] {
+        assert!(helpers::parse_duration_spec(bad).is_err(), "{bad:?} should not parse");
+    }
+}
+
 #[test]
 fn test_path_resolution_across_home_dirs() {
     // Test that /Users/chip/bin/pxh and /home/chip/bin/pxh resolve properly
@@ -338,3 +431,53 @@ fn test_dotted_hostname_db_filename() {
 
     assert_ne!(path, path2, "different hostnames must produce different filenames");
 }
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(helpers::edit_distance(b"git status", b"git status"), 0);
+    assert_eq!(helpers::edit_distance(b"gti status", b"git status"), 1);
+    assert_eq!(helpers::edit_distance(b"cargo buidl", b"cargo build"), 1);
+    assert_eq!(helpers::edit_distance(b"make tset", b"make test"), 1);
+    assert_eq!(helpers::edit_distance(b"ls", b"ls -l"), 3);
+    assert_eq!(helpers::edit_distance(b"", b"abc"), 3);
+    assert_eq!(helpers::edit_distance(b"kitten", b"sitting"), 3);
+}
+
+#[test]
+fn test_command_words() {
+    let words = |cmd: &str| -> Vec<String> {
+        helpers::command_words(cmd.as_bytes())
+            .into_iter()
+            .map(|w| String::from_utf8(w).unwrap())
+            .collect()
+    };
+    assert_eq!(words("git commit -m 'a b'"), ["git", "commit", "-m", "a b"]);
+    assert_eq!(words("RUST_LOG=debug sudo -u root cargo test"), ["cargo", "test"]);
+    assert!(words("  ").is_empty());
+}
+
+#[test]
+fn test_sync_encryption_roundtrip() {
+    use pxh::encryption::{SyncKey, decrypt, encrypt};
+
+    let mut key_file = NamedTempFile::new().unwrap();
+    writeln!(key_file, "  a passphrase  ").unwrap();
+    let key = SyncKey::from_file(key_file.path()).unwrap();
+
+    let sealed = encrypt(&key, b"SQLite format 3\0 history").unwrap();
+    assert!(sealed.starts_with(b"PXHENC1\0"));
+    assert_eq!(decrypt(&key, &sealed).unwrap(), b"SQLite format 3\0 history");
+    // Fresh salt and nonce every time.
+    assert_ne!(encrypt(&key, b"same").unwrap(), encrypt(&key, b"same").unwrap());
+
+    let mut tampered = sealed.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(decrypt(&key, &tampered).is_err());
+
+    let mut other_file = NamedTempFile::new().unwrap();
+    write!(other_file, "another passphrase").unwrap();
+    assert!(decrypt(&SyncKey::from_file(other_file.path()).unwrap(), &sealed).is_err());
+
+    let empty = NamedTempFile::new().unwrap();
+    assert!(SyncKey::from_file(empty.path()).is_err());
+}